@@ -90,6 +90,47 @@ impl Aabb {
         }
     true
     }
+    /// Slab test with the reciprocal ray direction computed once by the caller.
+    pub fn hit_inv(&self,origin:Vec3,inv_dir:Vec3,ray_t:Interval)->bool{
+        let slab=|ax:&Interval,o:f64,inv:f64|{
+            let t0=(ax.min-o)*inv;
+            let t1=(ax.max-o)*inv;
+            if t0<t1{(t0,t1)}else{(t1,t0)}
+        };
+        // plain comparisons instead of f64::max/min, whose NaN handling costs more than the
+        // rest of the test; a NaN slab (origin on a face, direction along it) still leaves
+        // the interval as it is
+        let max=|a:f64,b:f64| if b>a{b}else{a};
+        let min=|a:f64,b:f64| if b<a{b}else{a};
+        let (x0,x1)=slab(&self.x,origin.x,inv_dir.x);
+        let (y0,y1)=slab(&self.y,origin.y,inv_dir.y);
+        let (z0,z1)=slab(&self.z,origin.z,inv_dir.z);
+        let t_min=max(max(max(ray_t.min,x0),y0),z0);
+        let t_max=min(min(min(ray_t.max,x1),y1),z1);
+        t_min<t_max
+    }
+    /// The part of `ray_t` where `r` is inside the box, if any.
+    pub fn clip(&self,r:&Ray,ray_t:Interval)->Option<Interval>{
+        let inv_dir=Vec3::new(1.0/r.direction.x,1.0/r.direction.y,1.0/r.direction.z);
+        let slab=|ax:&Interval,o:f64,inv:f64|{
+            let t0=(ax.min-o)*inv;
+            let t1=(ax.max-o)*inv;
+            if t0<t1{(t0,t1)}else{(t1,t0)}
+        };
+        let (x0,x1)=slab(&self.x,r.origin.x,inv_dir.x);
+        let (y0,y1)=slab(&self.y,r.origin.y,inv_dir.y);
+        let (z0,z1)=slab(&self.z,r.origin.z,inv_dir.z);
+        let t_min=ray_t.min.max(x0).max(y0).max(z0);
+        let t_max=ray_t.max.min(x1).min(y1).min(z1);
+        if t_min<t_max{Some(Interval::set(t_min,t_max))}else{None}
+    }
+    pub fn surface_area(&self)->f64{
+        let (dx,dy,dz)=(self.x.size(),self.y.size(),self.z.size());
+        2.0*(dx*dy+dy*dz+dz*dx)
+    }
+    pub fn centroid(&self)->Vec3{
+        Vec3::new((self.x.min+self.x.max)*0.5,(self.y.min+self.y.max)*0.5,(self.z.min+self.z.max)*0.5)
+    }
     pub fn longest_axis(&self)->i32{
         if self.x.size()>self.y.size(){
             if self.x.size()>self.z.size(){
@@ -121,3 +162,55 @@ impl Add<Vec3> for Aabb {
 
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inv(direction: Vec3) -> Vec3 {
+        Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z)
+    }
+
+    #[test]
+    fn hit_inv_agrees_with_hit() {
+        let bbox = Aabb::set(Vec3::new(-1.0, -2.0, -0.5), Vec3::new(1.0, 2.0, 0.5));
+        let ray_t = Interval::set(0.001, f64::INFINITY);
+        for _ in 0..1000 {
+            let origin = (Vec3::random() - Vec3::new(0.5, 0.5, 0.5)) * 8.0;
+            let direction = Vec3::random() - Vec3::new(0.5, 0.5, 0.5);
+            let ray = Ray::new(origin, direction);
+            assert_eq!(bbox.hit_inv(origin, inv(direction), ray_t), bbox.hit(&ray, ray_t), "{:?} {:?}", origin, direction);
+        }
+        // the interval still limits it
+        let ray = Vec3::new(0.0, 0.0, 5.0);
+        assert!(bbox.hit_inv(ray, inv(Vec3::new(0.0, 0.0, -1.0)), Interval::set(0.0, 5.0)));
+        assert!(!bbox.hit_inv(ray, inv(Vec3::new(0.0, 0.0, -1.0)), Interval::set(0.0, 4.0)));
+    }
+
+    #[test]
+    fn nan_slabs_are_skipped_like_f64_max_does() {
+        // rays in a face plane with a zero direction component make 0 * inf slabs
+        let bbox = Aabb::set(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let with_f64_max = |origin: Vec3, inv_dir: Vec3, ray_t: Interval| {
+            let slab = |ax: &Interval, o: f64, inv: f64| {
+                let (t0, t1) = ((ax.min - o) * inv, (ax.max - o) * inv);
+                if t0 < t1 { (t0, t1) } else { (t1, t0) }
+            };
+            let (x0, x1) = slab(&bbox.x, origin.x, inv_dir.x);
+            let (y0, y1) = slab(&bbox.y, origin.y, inv_dir.y);
+            let (z0, z1) = slab(&bbox.z, origin.z, inv_dir.z);
+            ray_t.min.max(x0).max(y0).max(z0) < ray_t.max.min(x1).min(y1).min(z1)
+        };
+        let ray_t = Interval::set(0.001, f64::INFINITY);
+        let corners = [-1.0, 0.0, 1.0, 3.0];
+        let directions = [-1.0, 0.0, 1.0];
+        for x in corners {
+            for y in corners {
+                for (dx, dy) in directions.iter().flat_map(|&dx| directions.map(|dy| (dx, dy))) {
+                    let (origin, direction) = (Vec3::new(x, y, 5.0), Vec3::new(dx, dy, -1.0));
+                    assert_eq!(bbox.hit_inv(origin, inv(direction), ray_t), with_f64_max(origin, inv(direction), ray_t), "{:?} {:?}", origin, direction);
+                }
+            }
+        }
+    }
+}
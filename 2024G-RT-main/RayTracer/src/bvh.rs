@@ -3,6 +3,37 @@ use crate::AABB::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 use crate::hittable_list::HittableList;
 use crate::interval::Interval;
+use crate::vec3::Vec3;
+
+/// Subtrees with at least this many objects are built on separate rayon tasks.
+pub(crate) const PARALLEL_BUILD_THRESHOLD: usize = 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SplitMethod {
+    /// Binned surface area heuristic: slower to build, faster to trace.
+    Sah,
+    /// Sort on the longest axis and cut in the middle: fast to build.
+    Median,
+}
+
+#[derive(Clone, Copy)]
+pub struct BvhOptions {
+    pub split: SplitMethod,
+    /// Number of SAH buckets along the split axis.
+    pub bins: usize,
+    /// SAH may stop splitting once a node holds at most this many objects.
+    pub max_leaf_size: usize,
+}
+
+impl Default for BvhOptions {
+    fn default() -> Self {
+        BvhOptions {
+            split: SplitMethod::Sah,
+            bins: 16,
+            max_leaf_size: 4,
+        }
+    }
+}
 
 pub struct BvhNode {
     pub left: Arc<dyn Hittable+ Sync + Send>,
@@ -11,77 +42,203 @@ pub struct BvhNode {
 }
 
 impl BvhNode {
-    pub fn new(objects: &[Arc<dyn Hittable+ Send + Sync>], start: usize, end: usize) -> BvhNode {
-        let mut bbox = Aabb::new(Interval::empty(), Interval::empty(), Interval::empty());
-        for i in start..end {
-            let temp_bbox = objects[i].bounding_box().unwrap();
-            bbox = Aabb::surrounding_box(bbox, temp_bbox);
-        }
-        let axis = bbox.longest_axis();
-        let comparator = match axis {
-            0 => BvhNode::box_x_compare,
-            1 => BvhNode::box_y_compare,
-            2 => BvhNode::box_z_compare,
-            _ => BvhNode::box_x_compare,
-        };
-        let object_span = end - start;
+    pub fn set(lise: HittableList) -> BvhNode {
+        BvhNode::with_options(lise, BvhOptions::default())
+    }
+    pub fn with_options(lise: HittableList, options: BvhOptions) -> BvhNode {
+        let mut objects=lise.objects;
+        BvhNode::build(&mut objects, &options)
+    }
+
+    /// Builds in place: `objects` only gets reordered, never copied.
+    fn build(objects: &mut [Arc<dyn Hittable+ Send + Sync>], options: &BvhOptions) -> BvhNode {
+        let bbox = objects.iter()
+            .map(|object| object.bounding_box().unwrap())
+            .reduce(Aabb::surrounding_box)
+            .expect("BvhNode needs at least one object");
+        let object_span = objects.len();
         if object_span == 1 {
             return BvhNode {
-                left: Arc::clone(&objects[start]),
-                right: Arc::clone(&objects[start]),
-                bbox: objects[start].bounding_box().unwrap(),
+                left: Arc::clone(&objects[0]),
+                right: Arc::clone(&objects[0]),
+                bbox,
             };
         } else if object_span == 2 {
             return BvhNode {
-                left: Arc::clone(&objects[start]),
-                right: Arc::clone(&objects[start + 1]),
-                bbox: Aabb::surrounding_box(objects[start].bounding_box().unwrap(), objects[start + 1].bounding_box().unwrap()),
-            };
-        } else {
-            let mut sorted_objects = objects.to_vec();
-            sorted_objects[start..end].sort_by(|a, b| if comparator(a, b) { std::cmp::Ordering::Less } else { std::cmp::Ordering::Greater });
-            let mid = start + object_span / 2;
-            let left = BvhNode::new(&sorted_objects, start, mid);
-            let right = BvhNode::new(&sorted_objects, mid, end);
-            let bbox= Aabb::surrounding_box(left.bbox, right.bbox);
-            return BvhNode {
-                left: Arc::new(left),
-                right: Arc::new(right),
+                left: Arc::clone(&objects[0]),
+                right: Arc::clone(&objects[1]),
                 bbox,
             };
         }
-    }    pub fn set(lise: HittableList) -> BvhNode {
-        let objects=lise.objects;
-        let size=objects.len();
-        BvhNode::new(&objects, 0, size)
+        let mid = match BvhNode::split(objects, &bbox, options) {
+            Some((mid, _)) => mid,
+            None => {
+                // SAH prefers a leaf: keep the objects in two plain lists
+                let half = object_span / 2;
+                let mut left = HittableList::new();
+                let mut right = HittableList::new();
+                for object in &objects[..half] {
+                    left.add(Arc::clone(object));
+                }
+                for object in &objects[half..] {
+                    right.add(Arc::clone(object));
+                }
+                return BvhNode {
+                    left: Arc::new(left),
+                    right: Arc::new(right),
+                    bbox,
+                };
+            }
+        };
+        let (left_objects, right_objects) = objects.split_at_mut(mid);
+        let (left, right) = if object_span >= PARALLEL_BUILD_THRESHOLD {
+            rayon::join(
+                || BvhNode::build(left_objects, options),
+                || BvhNode::build(right_objects, options),
+            )
+        } else {
+            (BvhNode::build(left_objects, options), BvhNode::build(right_objects, options))
+        };
+        BvhNode {
+            left: Arc::new(left),
+            right: Arc::new(right),
+            bbox,
+        }
     }
-    pub fn box_compare(a:&Arc<dyn Hittable+ Sync + Send>,b:&Arc<dyn Hittable+ Sync + Send>,axis:usize)->bool{
-        let box_a=a.bounding_box().unwrap();
-        let box_b=b.bounding_box().unwrap();
-        return match axis{
-            0=>box_a.x.min<box_b.x.min,
-            1=>box_a.y.min<box_b.y.min,
-            2=>box_a.z.min<box_b.z.min,
-            _=>false,
+
+    /// Reorders `objects` and returns the cut position and the axis it was made on,
+    /// or `None` when SAH finds a leaf cheaper. The median split always cuts.
+    pub(crate) fn split(objects: &mut [Arc<dyn Hittable+ Send + Sync>], bbox: &Aabb, options: &BvhOptions) -> Option<(usize, usize)> {
+        match options.split {
+            SplitMethod::Sah => BvhNode::sah_split(objects, bbox, options),
+            SplitMethod::Median => Some(BvhNode::median_split(objects, bbox)),
         }
     }
-    pub fn box_x_compare(a:&Arc<dyn Hittable+ Sync + Send>,b:&Arc<dyn Hittable+ Sync + Send>)->bool{
-        BvhNode::box_compare(a, b, 0)
+
+    fn median_split(objects: &mut [Arc<dyn Hittable+ Send + Sync>], bbox: &Aabb) -> (usize, usize) {
+        let axis = bbox.longest_axis() as usize;
+        objects.sort_by(|a, b| BvhNode::box_compare(a, b, axis));
+        (objects.len() / 2, axis)
+    }
+
+    /// Partitions `objects` at the cheapest bucket boundary.
+    fn sah_split(objects: &mut [Arc<dyn Hittable+ Send + Sync>], bbox: &Aabb, options: &BvhOptions) -> Option<(usize, usize)> {
+        let object_span = objects.len();
+        let mut centroid_min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut centroid_max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for object in objects.iter() {
+            let c = object.bounding_box().unwrap().centroid();
+            for axis in 0..3 {
+                centroid_min[axis] = centroid_min[axis].min(c[axis]);
+                centroid_max[axis] = centroid_max[axis].max(c[axis]);
+            }
+        }
+        let extent = centroid_max - centroid_min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        if extent[axis] <= 0.0 {
+            // every centroid coincides, buckets cannot tell the objects apart
+            return if object_span <= options.max_leaf_size {
+                None
+            } else {
+                Some(BvhNode::median_split(objects, bbox))
+            };
+        }
+
+        let bins = options.bins.max(2);
+        let bin_of = |object: &Arc<dyn Hittable+ Send + Sync>| {
+            let c = object.bounding_box().unwrap().centroid()[axis];
+            (((c - centroid_min[axis]) / extent[axis] * bins as f64) as usize).min(bins - 1)
+        };
+        let mut counts = vec![0usize; bins];
+        let mut bounds: Vec<Option<Aabb>> = vec![None; bins];
+        for object in objects.iter() {
+            let b = bin_of(object);
+            counts[b] += 1;
+            let object_box = object.bounding_box().unwrap();
+            bounds[b] = Some(match bounds[b] {
+                Some(current) => Aabb::surrounding_box(current, object_box),
+                None => object_box,
+            });
+        }
+
+        // cost of cutting after bucket i, from a sweep in each direction
+        let mut right_area = vec![0.0; bins];
+        let mut right_count = vec![0usize; bins];
+        let mut acc: Option<Aabb> = None;
+        let mut count = 0;
+        for i in (1..bins).rev() {
+            count += counts[i];
+            acc = BvhNode::merge(acc, bounds[i]);
+            right_count[i - 1] = count;
+            right_area[i - 1] = acc.map_or(0.0, |b| b.surface_area());
+        }
+        let mut best_cost = f64::INFINITY;
+        let mut best_split = 0;
+        let mut acc: Option<Aabb> = None;
+        let mut count = 0;
+        let inv_area = 1.0 / bbox.surface_area();
+        for i in 0..bins - 1 {
+            count += counts[i];
+            acc = BvhNode::merge(acc, bounds[i]);
+            if count == 0 || right_count[i] == 0 {
+                continue;
+            }
+            let left_area = acc.map_or(0.0, |b| b.surface_area());
+            let cost = 0.125 + (count as f64 * left_area + right_count[i] as f64 * right_area[i]) * inv_area;
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = i;
+            }
+        }
+        if object_span <= options.max_leaf_size && object_span as f64 <= best_cost {
+            return None;
+        }
+
+        let mut mid = 0;
+        for j in 0..object_span {
+            if bin_of(&objects[j]) <= best_split {
+                objects.swap(mid, j);
+                mid += 1;
+            }
+        }
+        if mid == 0 || mid == object_span {
+            return Some(BvhNode::median_split(objects, bbox));
+        }
+        Some((mid, axis))
     }
-    pub fn box_y_compare(a:&Arc<dyn Hittable+ Sync + Send>,b:&Arc<dyn Hittable+ Sync + Send>)->bool{
-        BvhNode::box_compare(a, b, 1)
+
+    fn merge(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(Aabb::surrounding_box(a, b)),
+            (a, None) => a,
+            (None, b) => b,
+        }
     }
-    pub fn box_z_compare(a:&Arc<dyn Hittable+ Sync + Send>,b:&Arc<dyn Hittable+ Sync + Send>)->bool{
-        BvhNode::box_compare(a, b, 2)
+
+    /// Orders by the lower bound on `axis`; a total order so `sort_by` never sees contradictions.
+    pub fn box_compare(a:&Arc<dyn Hittable+ Sync + Send>,b:&Arc<dyn Hittable+ Sync + Send>,axis:usize)->std::cmp::Ordering{
+        let box_a=a.bounding_box().unwrap();
+        let box_b=b.bounding_box().unwrap();
+        box_a.axis_interval(axis as i32).min.total_cmp(&box_b.axis_interval(axis as i32).min)
     }
 
 }
 impl Hittable for BvhNode {
-    fn hit(&self, ray: &crate::ray::Ray, ray_t:Interval,rec:&mut HitRecord) -> bool{
+    fn hit<'a>(&'a self, ray: &crate::ray::Ray, ray_t:Interval,rec:&mut HitRecord<'a>) -> bool{
         if !self.bbox.hit(ray,ray_t) {
             return false;
         }
         let left_hit=self.left.hit(ray,ray_t,rec);
+        if Arc::ptr_eq(&self.left,&self.right) {
+            // single object leaf, both children are the same object
+            return left_hit;
+        }
         let right_hit=self.right.hit(ray,Interval::set(ray_t.min(),if !left_hit{ray_t.max()}else{rec.t}),rec);
         return left_hit||right_hit;
 
@@ -89,5 +246,11 @@ impl Hittable for BvhNode {
     fn bounding_box(&self) -> Option<Aabb> {
         return Option::from(self.bbox);
     }
+    fn occluded(&self, ray: &crate::ray::Ray, ray_t: Interval) -> bool {
+        if !self.bbox.hit(ray, ray_t) {
+            return false;
+        }
+        self.left.occluded(ray, ray_t) || (!Arc::ptr_eq(&self.left, &self.right) && self.right.occluded(ray, ray_t))
+    }
 
-}
\ No newline at end of file
+}
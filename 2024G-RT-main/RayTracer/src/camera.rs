@@ -13,6 +13,9 @@ use crate::vec3::Vec3;
 use crate::ray::Ray;
 use rayon::iter::ParallelIterator;
 use rayon::ThreadPoolBuilder;
+use std::sync::Arc;
+use crate::light::Light;
+use crate::AABB::Aabb;
 
 
 pub struct Camera{
@@ -47,6 +50,10 @@ pub struct Camera{
     pub(crate) defocus_disk_y:Vec3,
 
     pub(crate) background:Vec3,
+    pub(crate) lights:Vec<Arc<dyn Light + Sync + Send>>,
+
+    pub(crate) shutter_open:f64,
+    pub(crate) shutter_close:f64,
 }
 pub(crate) fn random() ->f64{
     let mut rng = StdRng::from_entropy();
@@ -107,6 +114,9 @@ impl Camera{
             defocus_disk_x,
             defocus_disk_y,
             background,
+            lights: Vec::new(),
+            shutter_open: 0.0,
+            shutter_close: 1.0,
         }
     }
     pub fn ray_color(&self,r: &Ray, depth:i32, world:&HittableList) -> vec3::Vec3 {
@@ -115,16 +125,68 @@ impl Camera{
         }
         let mut hit_record=HitRecord::new();
         if  world.hit(r, Interval::set(0.001, f64::INFINITY), &mut hit_record) {
+            // only camera rays come in at full depth
+            if depth == self.max_depth {
+                self.ray_differentials(r, &mut hit_record);
+            }
            let color_from_emission=hit_record.material.emitted(hit_record.u,hit_record.v,&hit_record.p);
             if let Some((scattered, attenuation)) = hit_record.material.scatter(r, &hit_record) {
-                return color_from_emission+Vec3::elemul( attenuation , self.ray_color(&scattered, depth - 1, world));
+                let color_from_lights=self.direct_light(r,&hit_record,world);
+                return color_from_emission+color_from_lights+Vec3::elemul( attenuation , self.ray_color(&scattered, depth - 1, world));
             }
             return color_from_emission;
         }
         return self.background
     }
+    /// Fills in `duv_dx` and `duv_dy` of the hit of a camera ray, pbrt's ray differentials:
+    /// the rays towards the neighbouring pixels meet the tangent plane at the hit, and the
+    /// offsets there are written in `dpdu` and `dpdv` by least squares. Nothing is traced and
+    /// the uv seams of closed shapes don't matter. Surfaces without `dpdu`/`dpdv` keep a zero
+    /// footprint. With many samples per pixel the footprint shrinks, as in pbrt-v4, the
+    /// samples already average over the pixel.
+    fn ray_differentials(&self, r: &Ray, hit_record: &mut HitRecord) {
+        let scale = (1.0 / f64::from(self.sample_per_pixel).sqrt()).max(0.125);
+        let (n, dpdu, dpdv) = (hit_record.geometric_normal, hit_record.dpdu, hit_record.dpdv);
+        let (uu, uv, vv) = (dpdu * dpdu, dpdu * dpdv, dpdv * dpdv);
+        let det = uu * vv - uv * uv;
+        let difference = |delta: Vec3| {
+            let direction = r.direction + delta * scale;
+            let facing = n * direction;
+            if det <= 1e-12 * uu * vv || facing.abs() < 1e-12 {
+                return (0.0, 0.0);
+            }
+            let t = n * (hit_record.p - r.origin) / facing;
+            let dp = r.origin + direction * t - hit_record.p;
+            let (bu, bv) = (dpdu * dp, dpdv * dp);
+            ((vv * bu - uv * bv) / det, (uu * bv - uv * bu) / det)
+        };
+        let (duv_dx, duv_dy) = (difference(self.pixel_delta_x), difference(self.pixel_delta_y));
+        hit_record.duv_dx = duv_dx;
+        hit_record.duv_dy = duv_dy;
+    }
+    /// Light reaching `hit_record` straight from the delta lights, found with one shadow ray per light.
+    fn direct_light(&self, r: &Ray, hit_record: &HitRecord, world: &HittableList) -> Vec3 {
+        let mut color = Vec3::new(0.0, 0.0, 0.0);
+        for light in self.lights.iter() {
+            let sample = match light.sample(hit_record.p) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            let f = hit_record.material.eval(r, hit_record, sample.wi);
+            if f.near_zero() {
+                continue;
+            }
+            let shadow_ray = Ray::new_time(hit_record.p, sample.wi, r.time);
+            if world.occluded(&shadow_ray, Interval::set(0.001, sample.distance - 0.001)) {
+                continue;
+            }
+            color += Vec3::elemul(f, sample.radiance);
+        }
+        color
+    }
     pub fn render(&self, world: HittableList, path:&str, quality:u8){
-        ThreadPoolBuilder::new().num_threads(24).build_global().unwrap();
+        // a parallel BVH build may already have started rayon's default global pool
+        let _ = ThreadPoolBuilder::new().num_threads(24).build_global();
         let bar: ProgressBar = if is_ci() {
             ProgressBar::hidden()
         } else {
@@ -191,9 +253,28 @@ impl Camera{
         output_image.write_to(&mut output_file, image::ImageOutputFormat::Jpeg(quality))
             .expect("Failed to write image");
     }
+    /// `lookfrom` and `lookat` for a view of the whole of `bbox`, seen from `direction`
+    /// (pointing from the box towards the camera) with the given field of view.
+    pub fn framing(bbox: &Aabb, direction: Vec3, vfov: f64, aspect_ratio: f64) -> (Vec3, Vec3) {
+        let center = bbox.centroid();
+        let radius = Vec3::new(bbox.x.size(), bbox.y.size(), bbox.z.size()).length() / 2.0;
+        // the bounding sphere has to fit the narrower of the two fields of view
+        let half_vertical = vfov.to_radians() / 2.0;
+        let half_horizontal = (half_vertical.tan() * aspect_ratio).atan();
+        let distance = radius / half_vertical.min(half_horizontal).sin();
+        (center + direction.unit() * distance, center)
+    }
     pub fn set_background(&mut self,background:Vec3){
         self.background=background;
     }
+    /// Ray times are spread uniformly over `[open, close]`.
+    pub fn set_shutter(&mut self,open:f64,close:f64){
+        self.shutter_open=open;
+        self.shutter_close=close;
+    }
+    pub fn add_light(&mut self,light:Arc<dyn Light + Sync + Send>){
+        self.lights.push(light);
+    }
     pub fn sample_squre()->Vec3{
         Vec3::new(random()-0.5,random()-0.5,0.0)
     }
@@ -210,7 +291,51 @@ impl Camera{
             self.defocus_disk_sample()
         };
         let ray_direction=pixel_sample-self.center;
-        let ray_time=random();
+        let ray_time=self.shutter_open+(self.shutter_close-self.shutter_open)*random();
         Ray::new_time(ray_origin,ray_direction,ray_time)
         }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::quad::quad;
+    use crate::sphere::Sphere;
+
+    fn camera(lookfrom: Vec3, lookat: Vec3) -> Camera {
+        Camera::new(100, 1.0, 1, 20.0, lookfrom, lookat, Vec3::new(0.0, 1.0, 0.0), 0.0, 1.0)
+    }
+
+    fn footprint<'a>(camera: &Camera, object: &'a dyn Hittable) -> HitRecord<'a> {
+        // camera rays reach the viewport a focus distance away
+        let r = Ray::new(camera.center, (camera.lookat - camera.center).unit() * camera.focus_dist);
+        let mut rec = HitRecord::new();
+        assert!(object.hit(&r, Interval::set(0.001, f64::INFINITY), &mut rec));
+        camera.ray_differentials(&r, &mut rec);
+        rec
+    }
+
+    #[test]
+    fn footprints_on_a_quad_are_the_pixel_spread_over_its_size() {
+        let wall = quad::new(Vec3::new(-2.0, -2.0, 0.0), Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 4.0, 0.0), Lambertian::new(Vec3::ones()));
+        let camera = camera(Vec3::new(0.0, 0.0, 5.0), Vec3::zero());
+        let rec = footprint(&camera, &wall);
+        // one-spp footprints span a whole pixel, 5 units away on a 4-unit quad
+        let spread = camera.pixel_delta_x.length() * 5.0 / 4.0;
+        assert!((rec.duv_dx.0 - spread).abs() < 1e-9 && rec.duv_dx.1.abs() < 1e-9, "{:?}", rec.duv_dx);
+        assert!((rec.duv_dy.1 + spread).abs() < 1e-9 && rec.duv_dy.0.abs() < 1e-9, "{:?}", rec.duv_dy);
+    }
+
+    #[test]
+    fn footprints_do_not_jump_across_the_sphere_seam() {
+        let ball = Sphere::new(Vec3::zero(), 1.0, Lambertian::new(Vec3::ones()));
+        // u wraps from 1 to 0 along -x, this ray lands right beside it
+        let camera = camera(Vec3::new(-5.0, 0.0, 1e-4), Vec3::new(0.0, 0.0, 1e-4));
+        let rec = footprint(&camera, &ball);
+        assert!(rec.u > 0.99 || rec.u < 0.01, "u = {}", rec.u);
+        // a pixel 4 units away, around the equator for u and half a meridian for v
+        let step = camera.pixel_delta_x.length() * 4.0 / std::f64::consts::PI;
+        assert!((rec.duv_dx.0.abs() - step / 2.0).abs() < 0.01 * step && rec.duv_dx.1.abs() < 1e-9, "{:?}", rec.duv_dx);
+        assert!((rec.duv_dy.1.abs() - step).abs() < 0.01 * step && rec.duv_dy.0.abs() < 1e-9, "{:?}", rec.duv_dy);
+    }
+}
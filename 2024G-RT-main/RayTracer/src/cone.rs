@@ -0,0 +1,206 @@
+use crate::AABB::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use num_traits::float::FloatConst;
+
+/// A cone closed by a disk at its base, intersected in the frame where its axis is `z`
+/// with the base at 0 and the apex at `height`.
+pub struct Cone<Mat: Material + Sync + Send> {
+    pub base: Vec3,
+    pub height: f64,
+    pub radius: f64,
+    pub onb: Onb,
+    pub mat: Mat,
+    pub bbox: Aabb,
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> Cone<Mat> {
+    /// A cone with a base of `radius` centered on `base`, narrowing to a point at `apex`.
+    pub fn new(base: Vec3, apex: Vec3, radius: f64, mat: Mat) -> Self {
+        assert!((apex - base).length() > 0.0, "Cone needs its apex apart from its base");
+        let onb = Onb::new(apex - base);
+        let radius = radius.max(0.0);
+        let extent = onb.circle_extent(radius);
+        let bbox = Aabb::surrounding_box(Aabb::set(base - extent, base + extent), Aabb::set(apex, apex));
+        Cone {
+            base,
+            height: (apex - base).length(),
+            radius,
+            onb,
+            mat,
+            bbox,
+        }
+    }
+
+    /// Nearest `t` inside `ray_t`, and whether it is on the base rather than the side.
+    fn intersect(&self, ray: &Ray, ray_t: Interval) -> Option<(f64, bool)> {
+        let local = self.onb.local_ray(self.base, ray);
+        let (o, d) = (local.origin, local.direction);
+        let mut nearest: Option<(f64, bool)> = None;
+        let mut consider = |t: f64, on_base: bool| {
+            if ray_t.surrounds(t) && nearest.is_none_or(|(best, _)| t < best) {
+                nearest = Some((t, on_base));
+            }
+        };
+
+        // x² + y² = (k (h - z))², the double cone through the apex
+        let k2 = (self.radius / self.height).powi(2);
+        let h = self.height - o.z;
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let half_b = o.x * d.x + o.y * d.y + k2 * h * d.z;
+        let c = o.x * o.x + o.y * o.y - k2 * h * h;
+        let roots = if a.abs() < 1e-12 {
+            // parallel to the surface, it is crossed once
+            if half_b == 0.0 { vec![] } else { vec![-c / (2.0 * half_b)] }
+        } else {
+            let discriminant = half_b * half_b - a * c;
+            if discriminant < 0.0 {
+                vec![]
+            } else {
+                let sqrtd = discriminant.sqrt();
+                vec![(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+            }
+        };
+        for t in roots {
+            // only the nappe between base and apex
+            let z = o.z + t * d.z;
+            if (0.0..=self.height).contains(&z) {
+                consider(t, false);
+            }
+        }
+        if d.z != 0.0 {
+            let t = -o.z / d.z;
+            let p = local.at(t);
+            if p.x * p.x + p.y * p.y <= self.radius * self.radius {
+                consider(t, true);
+            }
+        }
+        nearest
+    }
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> Hittable for Cone<Mat> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (t, on_base) = match self.intersect(ray, ray_t) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let p = self.onb.local_ray(self.base, ray).at(t);
+        let (normal, u, v, dpdu, dpdv) = if on_base {
+            (
+                Vec3::new(0.0, 0.0, -1.0),
+                p.x / (2.0 * self.radius) + 0.5,
+                p.y / (2.0 * self.radius) + 0.5,
+                Vec3::new(2.0 * self.radius, 0.0, 0.0),
+                Vec3::new(0.0, 2.0 * self.radius, 0.0),
+            )
+        } else {
+            // the gradient of the implicit surface, with k (h - z) swapped for the distance to the axis
+            let k = self.radius / self.height;
+            let rho = (p.x * p.x + p.y * p.y).sqrt();
+            // v runs up the side toward the apex, narrowing by k per unit of height
+            let outward = if rho > 0.0 { Vec3::new(p.x, p.y, 0.0) / rho } else { Vec3::zero() };
+            // the gradient vanishes at the apex, where the axis is the only sensible normal
+            let normal = if rho > 0.0 { Vec3::new(p.x, p.y, k * rho).unit() } else { Vec3::new(0.0, 0.0, 1.0) };
+            (
+                normal,
+                (f64::atan2(p.y, p.x) + f64::PI()) / (2.0 * f64::PI()),
+                p.z / self.height,
+                Vec3::new(-p.y, p.x, 0.0) * (2.0 * f64::PI()),
+                (Vec3::new(0.0, 0.0, 1.0) - outward * k) * self.height,
+            )
+        };
+
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.u = u;
+        rec.v = v;
+        rec.dpdu = self.onb.to_world(dpdu);
+        rec.dpdv = self.onb.to_world(dpdv);
+        rec.tangent = Vec3::zero();
+        rec.color = Vec3::ones();
+        rec.material = &self.mat;
+        rec.set_face_normal(*ray, self.onb.to_world(normal));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.intersect(ray, ray_t).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::assert_near;
+    use crate::material::Lambertian;
+
+    // Base of radius 1 at the origin, apex at z = 2, so the local frame is the world frame.
+    fn cone() -> Cone<Lambertian> {
+        Cone::new(Vec3::zero(), Vec3::new(0.0, 0.0, 2.0), 1.0, Lambertian::new(Vec3::ones()))
+    }
+
+    fn hit<'a>(object: &'a Cone<Lambertian>, origin: Vec3, direction: Vec3) -> Option<HitRecord<'a>> {
+        let mut rec = HitRecord::new();
+        object.hit(&Ray::new(origin, direction), Interval::set(0.001, f64::INFINITY), &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn side_normals_lean_toward_the_apex() {
+        let cone = cone();
+        // halfway up the radius is 0.5, and the side drops 1 in radius per 2 in height
+        let slope = Vec3::new(2.0, 0.0, 1.0).unit();
+        let rec = hit(&cone, Vec3::new(5.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+        assert!((rec.t - 4.5).abs() < 1e-9);
+        assert_near(rec.normal, slope, 1e-9);
+        assert!((rec.u - 0.5).abs() < 1e-9 && (rec.v - 0.5).abs() < 1e-9);
+
+        // from above the side is hit where its radius is 0.3
+        let rec = hit(&cone, Vec3::new(0.3, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+        assert_near(rec.p, Vec3::new(0.3, 0.0, 1.4), 1e-9);
+        assert_near(rec.normal, slope, 1e-9);
+
+        // the other nappe above the apex is not part of it
+        assert!(hit(&cone, Vec3::new(5.0, 0.0, 3.0), Vec3::new(-1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn rays_down_the_axis_hit_the_apex() {
+        let cone = cone();
+        let rec = hit(&cone, Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert_near(rec.normal, Vec3::new(0.0, 0.0, 1.0), 1e-9);
+
+        // the crowns of the forest scene stand upright with their apex at y = 0.75
+        let crown = Cone::new(Vec3::new(0.0, 0.2, 0.0), Vec3::new(0.0, 0.75, 0.0), 0.3, Lambertian::new(Vec3::ones()));
+        let mut rec = HitRecord::new();
+        let down = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(crown.hit(&down, Interval::set(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.t - 4.25).abs() < 1e-9);
+        assert_near(rec.normal, Vec3::new(0.0, 1.0, 0.0), 1e-9);
+    }
+
+    #[test]
+    fn base_hits() {
+        let cone = cone();
+        let rec = hit(&cone, Vec3::new(0.3, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0)).unwrap();
+        assert!((rec.t - 5.0).abs() < 1e-9 && rec.front_face);
+        assert_near(rec.normal, Vec3::new(0.0, 0.0, -1.0), 1e-9);
+        assert!((rec.u - 0.65).abs() < 1e-9 && (rec.v - 0.5).abs() < 1e-9);
+        assert!(hit(&cone, Vec3::new(0.8, 0.8, -5.0), Vec3::new(0.0, 0.0, 1.0)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cone needs its apex apart from its base")]
+    fn flat_cones_are_rejected() {
+        Cone::new(Vec3::ones(), Vec3::ones(), 1.0, Lambertian::new(Vec3::ones()));
+    }
+}
@@ -22,25 +22,54 @@ impl<Mat: Material+ Sync + Send> ConstantMedium<Mat>{
 }
 
 impl <Mat: Material + Clone+ Sync + Send + 'static>Hittable for ConstantMedium<Mat> {
-    fn hit(&self, r: &crate::ray::Ray, ray_t: crate::interval::Interval, rec: &mut crate::hittable::HitRecord) -> bool {
-        let enableDebug = false;
-        let debugging = enableDebug && crate::camera::random() < 0.00001;
+    fn hit<'a>(&'a self, r: &crate::ray::Ray, ray_t: crate::interval::Interval, rec: &mut crate::hittable::HitRecord<'a>) -> bool {
+        let t = match self.scatter_distance(r, ray_t) {
+            Some(t) => t,
+            None => return false,
+        };
 
+        rec.t = t;
+        rec.p = r.at(rec.t);
+
+
+        rec.normal = Vec3::new(1.0,0.0,0.0);
+        rec.geometric_normal = rec.normal;
+        rec.front_face = true;
+        rec.tangent = Vec3::zero();
+        rec.dpdu = Vec3::zero();
+        rec.dpdv = Vec3::zero();
+        rec.color = Vec3::ones();
+        rec.material =&self.phase_function;
+
+        true
+    }
+    fn bounding_box(&self) -> Option<crate::AABB::Aabb> {
+        self.boundary.bounding_box()
+    }
+    fn occluded(&self, r: &crate::ray::Ray, ray_t: Interval) -> bool {
+        self.scatter_distance(r, ray_t).is_some()
+    }
+}
+
+impl<Mat: Material+ Sync + Send> ConstantMedium<Mat>{
+    /// Samples where inside the boundary `r` scatters, `None` if it passes through.
+    /// The boundary still needs full records since both crossings' `t` matter.
+    fn scatter_distance(&self, r: &crate::ray::Ray, ray_t: Interval) -> Option<f64> {
         let mut rec1 = crate::hittable::HitRecord::new();
         let mut rec2 = crate::hittable::HitRecord::new();
 
         if !self.boundary.hit(r, Interval::universe(), &mut rec1)
-        { return false; }
+        { return None; }
 
         if !self.boundary.hit(r, Interval::set(rec1.t+0.0001, f64::INFINITY), &mut rec2)
-        { return false; }
+        { return None; }
 
 
         if rec1.t < ray_t.min { rec1.t = ray_t.min; }
         if rec2.t > ray_t.max{ rec2.t = ray_t.max; }
 
         if rec1.t >= rec2.t
-        { return false; }
+        { return None; }
 
         if rec1.t < 0.0
         { rec1.t = 0.0; }
@@ -50,19 +79,8 @@ impl <Mat: Material + Clone+ Sync + Send + 'static>Hittable for ConstantMedium<M
         let hit_distance = self.neg_inv_density *crate::camera::random().log(10.0);
 
         if hit_distance > distance_inside_boundary
-        { return false; }
-
-        rec.t = rec1.t + hit_distance / ray_length;
-        rec.p = r.at(rec.t);
-
+        { return None; }
 
-        rec.normal = Vec3::new(1.0,0.0,0.0);
-        rec.front_face = true;
-        rec.material =Box::new(self.phase_function.clone());
-
-        return true;
-    }
-    fn bounding_box(&self) -> Option<crate::AABB::Aabb> {
-        self.boundary.bounding_box()
+        Some(rec1.t + hit_distance / ray_length)
     }
 }
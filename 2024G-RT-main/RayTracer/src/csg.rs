@@ -0,0 +1,185 @@
+use crate::AABB::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+/// Gives up on a ray that crosses one operand more often than this, a leaking mesh
+/// could otherwise keep returning hits.
+const MAX_CROSSINGS: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    /// The left operand with the right one cut out of it.
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => in_left || in_right,
+            CsgOp::Intersection => in_left && in_right,
+            CsgOp::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// A boolean combination of two closed objects, anything from spheres and boxes to
+/// watertight meshes.
+///
+/// Both operands are walked along the ray, every hit toggling whether the ray is inside
+/// that operand, and the first hit where the combined inside state changes is the
+/// surface of the result. `front_face` is set from that change, so surfaces of the
+/// subtracted operand face into it, and a `Dielectric` on them refracts the right way.
+pub struct Csg<A: Hittable + Sync + Send, B: Hittable + Sync + Send> {
+    pub left: A,
+    pub right: B,
+    pub op: CsgOp,
+    pub bbox: Aabb,
+}
+
+impl<A: Hittable + Sync + Send, B: Hittable + Sync + Send> Csg<A, B> {
+    pub fn new(left: A, right: B, op: CsgOp) -> Self {
+        let left_box = left.bounding_box().unwrap();
+        let right_box = right.bounding_box().unwrap();
+        let bbox = match op {
+            CsgOp::Union => Aabb::surrounding_box(left_box, right_box),
+            CsgOp::Intersection => Aabb::new(
+                Interval::overlap(&left_box.x, &right_box.x),
+                Interval::overlap(&left_box.y, &right_box.y),
+                Interval::overlap(&left_box.z, &right_box.z),
+            ),
+            CsgOp::Difference => left_box,
+        };
+        Self { left, right, op, bbox }
+    }
+
+    pub fn union(left: A, right: B) -> Self {
+        Self::new(left, right, CsgOp::Union)
+    }
+
+    pub fn intersection(left: A, right: B) -> Self {
+        Self::new(left, right, CsgOp::Intersection)
+    }
+
+    pub fn difference(left: A, right: B) -> Self {
+        Self::new(left, right, CsgOp::Difference)
+    }
+
+    /// The first surface of the result inside `ray_t`.
+    fn first_surface<'a>(&'a self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+        // search past `ray_t.max`, a ray that ends inside an operand still has to see its exit
+        let (mut in_left, left) = crossings(&self.left, ray, ray_t.min);
+        let (mut in_right, right) = crossings(&self.right, ray, ray_t.min);
+        let mut inside = self.op.inside(in_left, in_right);
+        let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+        loop {
+            let from_left = match (left.peek(), right.peek()) {
+                (Some(l), Some(r)) => l.t <= r.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+            let mut rec = if from_left {
+                in_left = !in_left;
+                left.next()?
+            } else {
+                in_right = !in_right;
+                right.next()?
+            };
+            let now = self.op.inside(in_left, in_right);
+            if now != inside {
+                if !ray_t.surrounds(rec.t) {
+                    return None;
+                }
+                // `normal` already faces the ray, only which side is outside can change
+                rec.front_face = now;
+                return Some(rec);
+            }
+            inside = now;
+        }
+    }
+}
+
+/// Every hit on `obj` along `ray` after `t_min`, and whether the ray starts inside it.
+/// That is judged from the parity of the hits rather than their `front_face`, the sides
+/// of `quad::bx` do not all face outwards.
+fn crossings<'a, H: Hittable>(obj: &'a H, ray: &Ray, t_min: f64) -> (bool, Vec<HitRecord<'a>>) {
+    let mut hits: Vec<HitRecord<'a>> = Vec::new();
+    let mut t0 = t_min;
+    while hits.len() < MAX_CROSSINGS {
+        let mut rec = HitRecord::new();
+        if !obj.hit(ray, Interval::set(t0, f64::INFINITY), &mut rec) {
+            break;
+        }
+        // step past the hit so the same surface is not found again
+        t0 = rec.t + 1e-9 * rec.t.abs().max(1.0);
+        hits.push(rec);
+    }
+    (hits.len() % 2 == 1, hits)
+}
+
+impl<A: Hittable + Sync + Send, B: Hittable + Sync + Send> Hittable for Csg<A, B> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        match self.first_surface(ray, ray_t) {
+            Some(surface) => {
+                *rec = surface;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.first_surface(ray, ray_t).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    fn sphere(x: f64) -> Sphere<Lambertian> {
+        Sphere::new(Vec3::new(x, 0.0, 0.0), 1.0, Lambertian::new(Vec3::ones()))
+    }
+
+    fn first_hit<A: Hittable + Sync + Send, B: Hittable + Sync + Send>(csg: &Csg<A, B>, origin: f64, direction: f64) -> Option<(f64, bool)> {
+        let ray = Ray::new(Vec3::new(origin, 0.0, 0.0), Vec3::new(direction, 0.0, 0.0));
+        let mut rec = HitRecord::new();
+        csg.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec).then_some((rec.p.x, rec.front_face))
+    }
+
+    // The operands overlap for 0 <= x <= 1.
+    #[test]
+    fn boolean_operations() {
+        let union = Csg::union(sphere(0.0), sphere(1.0));
+        assert_eq!(first_hit(&union, 5.0, -1.0), Some((2.0, true)));
+        assert_eq!(first_hit(&union, 0.5, 1.0), Some((2.0, false)));
+
+        let intersection = Csg::intersection(sphere(0.0), sphere(1.0));
+        assert_eq!(first_hit(&intersection, -5.0, 1.0), Some((0.0, true)));
+        assert_eq!(first_hit(&intersection, 5.0, -1.0), Some((1.0, true)));
+
+        let difference = Csg::difference(sphere(0.0), sphere(1.0));
+        assert_eq!(first_hit(&difference, -5.0, 1.0), Some((-1.0, true)));
+        // the cut is entered through the inside of the subtracted sphere
+        assert_eq!(first_hit(&difference, 5.0, -1.0), Some((0.0, true)));
+        assert_eq!(first_hit(&difference, -0.5, 1.0), Some((0.0, false)));
+    }
+
+    #[test]
+    fn ray_ending_inside_an_operand() {
+        let union = Csg::union(sphere(0.0), sphere(1.0));
+        let ray = Ray::new(Vec3::new(-0.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        // the surface of the right sphere at x = 0 is inside the left one
+        assert!(!union.occluded(&ray, Interval::set(0.001, 1.0)));
+    }
+}
@@ -0,0 +1,311 @@
+use std::sync::Arc;
+use crate::AABB::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// How the width of a curve is turned into a surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CurveKind {
+    /// A ribbon that always faces the ray, the usual choice for hair and fur.
+    Flat,
+    /// Also faces the ray, but shaded as if it were a round tube.
+    Cylinder,
+    /// A ribbon with a fixed orientation, its normal blended from the first to the second
+    /// along the curve. Grass blades seen edge on get thin.
+    Ribbon(Vec3, Vec3),
+}
+
+/// A cubic Bézier segment swept with a width that changes linearly along it.
+///
+/// Intersected like pbrt does: the control points are moved into a frame where the ray
+/// runs along `z`, and the curve is split until the pieces are nearly straight and can
+/// be tested as segments. `u` runs along the curve and `v` across it, 0.5 on the center
+/// line and growing towards `cross(wo, tangent)` seen from the ray, with `wo` pointing
+/// back along it. `HairBsdf` reads the offset from the center line in that frame.
+pub struct Curve<Mat: Material + Sync + Send> {
+    pub cp: [Vec3; 4],
+    pub width: [f64; 2],
+    /// The part of a longer curve this segment covers, for `u`.
+    pub u_range: [f64; 2],
+    pub kind: CurveKind,
+    pub mat: Mat,
+    pub bbox: Aabb,
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> Curve<Mat> {
+    pub fn new(cp: [Vec3; 4], width0: f64, width1: f64, kind: CurveKind, mat: Mat) -> Self {
+        Self::segment(cp, [width0, width1], [0.0, 1.0], kind, mat)
+    }
+
+    fn segment(cp: [Vec3; 4], width: [f64; 2], u_range: [f64; 2], kind: CurveKind, mat: Mat) -> Self {
+        // the curve stays inside the hull of its control points
+        let half = width[0].max(width[1]) / 2.0;
+        let pad = Vec3::new(half, half, half);
+        let hull = Aabb::surrounding_box(Aabb::set(cp[0], cp[1]), Aabb::set(cp[2], cp[3]));
+        let bbox = Aabb::set(
+            Vec3::new(hull.x.min, hull.y.min, hull.z.min) - pad,
+            Vec3::new(hull.x.max, hull.y.max, hull.z.max) + pad,
+        );
+        Curve { cp, width, u_range, kind, mat, bbox }
+    }
+
+    /// A uniform cubic B-spline through the influence of `points`, as one Bézier segment
+    /// per four consecutive points. The width, and the normal of a ribbon, change evenly
+    /// over the whole curve.
+    pub fn b_spline(points: &[Vec3], width0: f64, width1: f64, kind: CurveKind, mat: Mat) -> HittableList {
+        let mut segments = HittableList::new();
+        if points.len() < 4 {
+            return segments;
+        }
+        let count = points.len() - 3;
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        for (i, p) in points.windows(4).enumerate() {
+            let (s0, s1) = (i as f64 / count as f64, (i + 1) as f64 / count as f64);
+            let cp = [
+                (p[0] + p[1] * 4.0 + p[2]) / 6.0,
+                (p[1] * 2.0 + p[2]) / 3.0,
+                (p[1] + p[2] * 2.0) / 3.0,
+                (p[1] + p[2] * 4.0 + p[3]) / 6.0,
+            ];
+            let kind = match kind {
+                CurveKind::Ribbon(n0, n1) => CurveKind::Ribbon(n0 * (1.0 - s0) + n1 * s0, n0 * (1.0 - s1) + n1 * s1),
+                kind => kind,
+            };
+            let width = [lerp(width0, width1, s0), lerp(width0, width1, s1)];
+            segments.add(Arc::new(Self::segment(cp, width, [s0, s1], kind, mat.clone())));
+        }
+        segments
+    }
+
+    fn width_at(&self, u: f64) -> f64 {
+        self.width[0] * (1.0 - u) + self.width[1] * u
+    }
+
+    fn ribbon_normal(&self, u: f64) -> Option<Vec3> {
+        match self.kind {
+            CurveKind::Ribbon(n0, n1) => Some((n0 * (1.0 - u) + n1 * u).unit()),
+            _ => None,
+        }
+    }
+
+    /// Nearest hit inside `ray_t` as `t` and the curve parameter there.
+    fn intersect(&self, ray: &Ray, ray_t: Interval) -> Option<(f64, f64)> {
+        let speed = ray.direction.length();
+        let frame = Onb::new(ray.direction);
+        let cp = self.cp.map(|p| frame.to_local(p - ray.origin));
+
+        // split until the pieces are flat to a twentieth of the width, see pbrt's Curve
+        let mut l0: f64 = 0.0;
+        for i in 0..2 {
+            let d = cp[i] - cp[i + 1] * 2.0 + cp[i + 2];
+            l0 = l0.max(d.x.abs()).max(d.y.abs()).max(d.z.abs());
+        }
+        let eps = self.width[0].max(self.width[1]) * 0.05;
+        let r0 = (std::f64::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)).log2().floor() / 2.0;
+        let max_depth = if r0.is_finite() { r0.clamp(0.0, 10.0) as u32 } else { 0 };
+
+        let mut z_range = Interval::set(ray_t.min * speed, ray_t.max * speed);
+        let mut nearest = None;
+        self.recursive_intersect(ray, &cp, [0.0, 1.0], max_depth, &mut z_range, &mut nearest);
+        nearest.map(|(z, u)| (z / speed, u))
+    }
+
+    fn recursive_intersect(
+        &self,
+        ray: &Ray,
+        cp: &[Vec3; 4],
+        [u0, u1]: [f64; 2],
+        depth: u32,
+        z_range: &mut Interval,
+        nearest: &mut Option<(f64, f64)>,
+    ) {
+        let half = self.width_at(u0).max(self.width_at(u1)) / 2.0;
+        let (min, max) = cp.iter().fold((cp[0], cp[0]), |(lo, hi), p| {
+            (Vec3::new(lo.x.min(p.x), lo.y.min(p.y), lo.z.min(p.z)), Vec3::new(hi.x.max(p.x), hi.y.max(p.y), hi.z.max(p.z)))
+        });
+        // the ray is the `z` axis, so its footprint is the origin of the `xy` plane
+        if min.x - half > 0.0 || max.x + half < 0.0 || min.y - half > 0.0 || max.y + half < 0.0 {
+            return;
+        }
+        if max.z + half < z_range.min || min.z - half > z_range.max {
+            return;
+        }
+
+        if depth > 0 {
+            let split = split_bezier(cp);
+            let mid = (u0 + u1) / 2.0;
+            self.recursive_intersect(ray, &[split[0], split[1], split[2], split[3]], [u0, mid], depth - 1, z_range, nearest);
+            self.recursive_intersect(ray, &[split[3], split[4], split[5], split[6]], [mid, u1], depth - 1, z_range, nearest);
+            return;
+        }
+
+        // the ray has to pass between the lines through both ends, across the segment
+        let edge = (cp[1].y - cp[0].y) * -cp[0].y + cp[0].x * (cp[0].x - cp[1].x);
+        if edge < 0.0 {
+            return;
+        }
+        let edge = (cp[2].y - cp[3].y) * -cp[3].y + cp[3].x * (cp[3].x - cp[2].x);
+        if edge < 0.0 {
+            return;
+        }
+        let (sx, sy) = (cp[3].x - cp[0].x, cp[3].y - cp[0].y);
+        let denom = sx * sx + sy * sy;
+        if denom == 0.0 {
+            return;
+        }
+        let w = (-cp[0].x * sx - cp[0].y * sy) / denom;
+        let u = (u0 + (u1 - u0) * w).clamp(u0, u1);
+        let mut hit_width = self.width_at(u);
+        if let Some(normal) = self.ribbon_normal(u) {
+            hit_width *= (normal * ray.direction.unit()).abs();
+        }
+        let pc = eval_bezier(cp, w.clamp(0.0, 1.0));
+        if pc.x * pc.x + pc.y * pc.y > hit_width * hit_width * 0.25 {
+            return;
+        }
+        if !z_range.surrounds(pc.z) {
+            return;
+        }
+        z_range.max = pc.z;
+        *nearest = Some((pc.z, u));
+    }
+}
+
+/// The point at `u`, by de Casteljau.
+fn eval_bezier(cp: &[Vec3; 4], u: f64) -> Vec3 {
+    let lerp = |a: Vec3, b: Vec3| a * (1.0 - u) + b * u;
+    let (a, b, c) = (lerp(cp[0], cp[1]), lerp(cp[1], cp[2]), lerp(cp[2], cp[3]));
+    let (d, e) = (lerp(a, b), lerp(b, c));
+    lerp(d, e)
+}
+
+fn bezier_derivative(cp: &[Vec3; 4], u: f64) -> Vec3 {
+    let s = 1.0 - u;
+    ((cp[1] - cp[0]) * (s * s) + (cp[2] - cp[1]) * (2.0 * s * u) + (cp[3] - cp[2]) * (u * u)) * 3.0
+}
+
+/// Control points of both halves, the middle one shared.
+fn split_bezier(cp: &[Vec3; 4]) -> [Vec3; 7] {
+    [
+        cp[0],
+        (cp[0] + cp[1]) / 2.0,
+        (cp[0] + cp[1] * 2.0 + cp[2]) / 4.0,
+        (cp[0] + cp[1] * 3.0 + cp[2] * 3.0 + cp[3]) / 8.0,
+        (cp[1] + cp[2] * 2.0 + cp[3]) / 4.0,
+        (cp[2] + cp[3]) / 2.0,
+        cp[3],
+    ]
+}
+
+/// Unit vectors across a curve with `tangent` seen along `direction`: one facing back
+/// along the ray and one to the side, `cross(facing, tangent)`. `None` when looking
+/// straight down the curve.
+pub(crate) fn curve_frame(direction: Vec3, tangent: Vec3) -> Option<(Vec3, Vec3)> {
+    let wo = -direction;
+    let facing = wo - tangent * (wo * tangent);
+    if facing.near_zero() {
+        return None;
+    }
+    let facing = facing.unit();
+    Some((facing, Vec3::cross(facing, tangent)))
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> Hittable for Curve<Mat> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (t, u) = match self.intersect(ray, ray_t) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let derivative = bezier_derivative(&self.cp, u);
+        if derivative.near_zero() {
+            return false;
+        }
+        let tangent = derivative.unit();
+        let (facing, side) = match curve_frame(ray.direction.unit(), tangent) {
+            Some(frame) => frame,
+            None => return false,
+        };
+        let p = ray.at(t);
+        // signed distance from the center line as a fraction of the half width
+        let h = ((p - eval_bezier(&self.cp, u)) * side / (self.width_at(u) / 2.0)).clamp(-1.0, 1.0);
+        let normal = match self.kind {
+            CurveKind::Flat => facing,
+            CurveKind::Cylinder => facing * (1.0 - h * h).sqrt() + side * h,
+            CurveKind::Ribbon(_, _) => self.ribbon_normal(u).unwrap_or(facing),
+        };
+
+        rec.t = t;
+        rec.p = p;
+        rec.u = self.u_range[0] + (self.u_range[1] - self.u_range[0]) * u;
+        rec.v = (h + 1.0) / 2.0;
+        rec.tangent = tangent;
+        // v crosses the full width from one side to the other
+        rec.dpdu = derivative / (self.u_range[1] - self.u_range[0]);
+        rec.dpdv = side * self.width_at(u);
+        rec.color = Vec3::ones();
+        rec.material = &self.mat;
+        rec.set_face_normal(*ray, normal);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.intersect(ray, ray_t).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    // A straight curve along `x` getting thinner from 0.4 to 0.2.
+    fn straight(kind: CurveKind) -> Curve<Lambertian> {
+        let cp = [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(-1.0 / 3.0, 0.0, 0.0), Vec3::new(1.0 / 3.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)];
+        Curve::new(cp, 0.4, 0.2, kind, Lambertian::new(Vec3::ones()))
+    }
+
+    #[test]
+    fn width_varies_along_the_curve() {
+        let curve = straight(CurveKind::Flat);
+        let ray_t = Interval::set(0.001, f64::INFINITY);
+        let ray = |x: f64, y: f64| Ray::new(Vec3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let (t, u) = curve.intersect(&ray(0.0, 0.14), ray_t).unwrap();
+        assert!((t - 5.0).abs() < 1e-9);
+        assert!((u - 0.5).abs() < 1e-6);
+        assert!(curve.intersect(&ray(0.9, 0.14), ray_t).is_none());
+        assert!(curve.intersect(&ray(-0.9, 0.14), ray_t).is_some());
+        assert!(curve.intersect(&ray(1.2, 0.0), ray_t).is_none());
+    }
+
+    #[test]
+    fn ribbon_seen_edge_on_disappears() {
+        let ray = Ray::new(Vec3::new(0.0, 0.05, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let ray_t = Interval::set(0.001, f64::INFINITY);
+        let facing = straight(CurveKind::Ribbon(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, 1.0)));
+        assert!(facing.intersect(&ray, ray_t).is_some());
+        let edge_on = straight(CurveKind::Ribbon(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)));
+        assert!(edge_on.intersect(&ray, ray_t).is_none());
+    }
+
+    #[test]
+    fn cylinder_normal_follows_the_offset() {
+        let curve = straight(CurveKind::Cylinder);
+        let ray = Ray::new(Vec3::new(0.0, 0.1, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(curve.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        // two thirds out from the center line of a curve 0.3 wide here
+        let h = 2.0 * rec.v - 1.0;
+        assert!((h.abs() - 2.0 / 3.0).abs() < 1e-6);
+        assert!(rec.normal.y > 0.0 && rec.normal.z > 0.0);
+        assert!((rec.tangent - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::camera::random;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+/// A material with holes: where the alpha texture is transparent, triangles and quads
+/// drawn with it are not hit at all, neither by camera rays nor by shadow rays.
+#[derive(Clone)]
+pub struct Cutout<Mat: Material> {
+    pub material: Mat,
+    /// Opacity as the average of the channels, see `ImageTexture::opacity`.
+    pub alpha: Texture,
+    /// Alpha at or above this is solid and below it a hole, like the `MASK` mode of glTF.
+    /// Without one, partial alpha lets that fraction of the rays through at random.
+    pub cutoff: Option<f64>,
+}
+
+impl<Mat: Material> Cutout<Mat> {
+    pub fn new(material: Mat, alpha: Texture) -> Self {
+        Self { material, alpha, cutoff: None }
+    }
+
+    pub fn with_cutoff(mut self, cutoff: f64) -> Self {
+        self.cutoff = Some(cutoff);
+        self
+    }
+}
+
+impl<Mat: Material> Material for Cutout<Mat> {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
+        self.material.scatter(r_in, hit_record)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
+        self.material.emitted(u, v, p)
+    }
+
+    fn eval(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Vec3 {
+        self.material.eval(r_in, hit_record, wi)
+    }
+
+    fn is_opaque(&self, u: f64, v: f64, p: &Vec3) -> bool {
+        let c = self.alpha.value(u, v, p);
+        let alpha = (c.x + c.y + c.z) / 3.0;
+        let opaque = match self.cutoff {
+            Some(cutoff) => alpha >= cutoff,
+            // an alpha of one is always hit and one of zero never
+            None => alpha >= 1.0 || random() < alpha,
+        };
+        opaque && self.material.is_opaque(u, v, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::interval::Interval;
+    use crate::material::Lambertian;
+    use crate::quad::quad;
+    use crate::texture::{ImageTexture, TextureFilter};
+    use crate::triangle_mesh::TriangleMesh;
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use std::sync::Arc;
+
+    // transparent on the left half, solid on the right
+    fn half_alpha() -> Texture {
+        let image = RgbaImage::from_fn(2, 1, |x, _| Rgba([255, 255, 255, if x == 0 { 0 } else { 255 }]));
+        let texture = ImageTexture::opacity_from_image(DynamicImage::ImageRgba8(image));
+        Texture::ImageTex(texture.with_filter(TextureFilter::Nearest))
+    }
+
+    fn unit_square<Mat: Material + Clone + Sync + Send + 'static>(z: f64, mat: Mat) -> quad<Mat> {
+        quad::new(Vec3::new(0.0, 0.0, z), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), mat)
+    }
+
+    fn toward(x: f64) -> Ray {
+        Ray::new(Vec3::new(x, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn holes_let_rays_through_to_what_is_behind() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(unit_square(0.0, Cutout::new(Lambertian::new(Vec3::ones()), half_alpha()).with_cutoff(0.5))));
+        world.add(Arc::new(unit_square(-1.0, Lambertian::new(Vec3::ones()))));
+        let ray_t = Interval::set(0.001, f64::INFINITY);
+
+        let mut rec = HitRecord::new();
+        assert!(world.hit(&toward(0.25), ray_t, &mut rec));
+        assert!((rec.t - 2.0).abs() < 1e-9);
+        assert!(world.hit(&toward(0.75), ray_t, &mut rec));
+        assert!((rec.t - 1.0).abs() < 1e-9);
+
+        let front = &world.objects[0];
+        assert!(!front.occluded(&toward(0.25), ray_t));
+        assert!(front.occluded(&toward(0.75), ray_t));
+    }
+
+    #[test]
+    fn partial_alpha_stops_that_share_of_rays() {
+        let square = unit_square(0.0, Cutout::new(Lambertian::new(Vec3::ones()), Texture::SolidColor(Vec3::new(0.25, 0.25, 0.25))));
+        let trials = 20000;
+        let stopped = (0..trials).filter(|_| square.occluded(&toward(0.5), Interval::set(0.001, f64::INFINITY))).count();
+        assert!((stopped as f64 / trials as f64 - 0.25).abs() < 0.02, "{} of {}", stopped, trials);
+    }
+
+    #[test]
+    fn mesh_cutouts_read_the_vertex_uvs() {
+        // one triangle whose uvs only cover the transparent half of the texture
+        let positions = vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+        let uvs = vec![(0.0, 0.0), (0.4, 0.0), (0.0, 1.0)];
+        let mat = Cutout::new(Lambertian::new(Vec3::ones()), half_alpha()).with_cutoff(0.5);
+        let hole = Arc::new(TriangleMesh::new(positions.clone(), Vec::new(), uvs, vec![[0, 1, 2]], mat)).triangles();
+        let ray = Ray::new(Vec3::new(0.2, 0.2, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(!hole.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+
+        // the same triangle on the solid half is hit
+        let uvs = vec![(0.6, 0.0), (1.0, 0.0), (0.6, 1.0)];
+        let solid = Cutout::new(Lambertian::new(Vec3::ones()), half_alpha()).with_cutoff(0.5);
+        let leaf = Arc::new(TriangleMesh::new(positions, Vec::new(), uvs, vec![[0, 1, 2]], solid)).triangles();
+        assert!(leaf.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+    }
+}
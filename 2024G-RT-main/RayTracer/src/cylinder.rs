@@ -0,0 +1,193 @@
+use crate::AABB::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use num_traits::float::FloatConst;
+
+#[derive(Clone, Copy)]
+enum Part {
+    Side,
+    Bottom,
+    Top,
+}
+
+/// A capped cylinder, intersected in the frame where its axis is `z` from 0 to `height`.
+pub struct Cylinder<Mat: Material + Sync + Send> {
+    pub base: Vec3,
+    pub height: f64,
+    pub radius: f64,
+    pub onb: Onb,
+    pub mat: Mat,
+    pub bbox: Aabb,
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> Cylinder<Mat> {
+    /// A cylinder from the center of its bottom cap at `base` to the center of its top cap.
+    pub fn new(base: Vec3, top: Vec3, radius: f64, mat: Mat) -> Self {
+        assert!((top - base).length() > 0.0, "Cylinder needs its top apart from its base");
+        let onb = Onb::new(top - base);
+        let radius = radius.max(0.0);
+        let extent = onb.circle_extent(radius);
+        let bbox = Aabb::surrounding_box(Aabb::set(base - extent, base + extent), Aabb::set(top - extent, top + extent));
+        Cylinder {
+            base,
+            height: (top - base).length(),
+            radius,
+            onb,
+            mat,
+            bbox,
+        }
+    }
+
+    /// Nearest `t` inside `ray_t` and the part of the cylinder hit there.
+    fn intersect(&self, ray: &Ray, ray_t: Interval) -> Option<(f64, Part)> {
+        let local = self.onb.local_ray(self.base, ray);
+        let (o, d) = (local.origin, local.direction);
+        let r2 = self.radius * self.radius;
+        let mut nearest: Option<(f64, Part)> = None;
+        let mut consider = |t: f64, part: Part| {
+            if ray_t.surrounds(t) && nearest.is_none_or(|(best, _)| t < best) {
+                nearest = Some((t, part));
+            }
+        };
+
+        let a = d.x * d.x + d.y * d.y;
+        if a > 0.0 {
+            let half_b = o.x * d.x + o.y * d.y;
+            let c = o.x * o.x + o.y * o.y - r2;
+            let discriminant = half_b * half_b - a * c;
+            if discriminant >= 0.0 {
+                let sqrtd = discriminant.sqrt();
+                for t in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
+                    let z = o.z + t * d.z;
+                    if (0.0..=self.height).contains(&z) {
+                        consider(t, Part::Side);
+                    }
+                }
+            }
+        }
+        if d.z != 0.0 {
+            for (z, part) in [(0.0, Part::Bottom), (self.height, Part::Top)] {
+                let t = (z - o.z) / d.z;
+                let p = local.at(t);
+                if p.x * p.x + p.y * p.y <= r2 {
+                    consider(t, part);
+                }
+            }
+        }
+        nearest
+    }
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> Hittable for Cylinder<Mat> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (t, part) = match self.intersect(ray, ray_t) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let p = self.onb.local_ray(self.base, ray).at(t);
+        // the sides wrap u around the axis, the caps are mapped like a disk
+        let (normal, u, v, dpdu, dpdv) = match part {
+            Part::Side => (
+                Vec3::new(p.x, p.y, 0.0) / self.radius,
+                (f64::atan2(p.y, p.x) + f64::PI()) / (2.0 * f64::PI()),
+                p.z / self.height,
+                Vec3::new(-p.y, p.x, 0.0) * (2.0 * f64::PI()),
+                Vec3::new(0.0, 0.0, self.height),
+            ),
+            Part::Bottom | Part::Top => (
+                Vec3::new(0.0, 0.0, if let Part::Top = part { 1.0 } else { -1.0 }),
+                p.x / (2.0 * self.radius) + 0.5,
+                p.y / (2.0 * self.radius) + 0.5,
+                Vec3::new(2.0 * self.radius, 0.0, 0.0),
+                Vec3::new(0.0, 2.0 * self.radius, 0.0),
+            ),
+        };
+
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.u = u;
+        rec.v = v;
+        rec.dpdu = self.onb.to_world(dpdu);
+        rec.dpdv = self.onb.to_world(dpdv);
+        rec.tangent = Vec3::zero();
+        rec.color = Vec3::ones();
+        rec.material = &self.mat;
+        rec.set_face_normal(*ray, self.onb.to_world(normal));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.intersect(ray, ray_t).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::assert_near;
+    use crate::material::Lambertian;
+
+    // Along z from 0 to 2, so the local frame is the world frame.
+    fn cylinder() -> Cylinder<Lambertian> {
+        Cylinder::new(Vec3::zero(), Vec3::new(0.0, 0.0, 2.0), 1.0, Lambertian::new(Vec3::ones()))
+    }
+
+    fn hit<'a>(object: &'a Cylinder<Lambertian>, origin: Vec3, direction: Vec3) -> Option<HitRecord<'a>> {
+        let mut rec = HitRecord::new();
+        object.hit(&Ray::new(origin, direction), Interval::set(0.001, f64::INFINITY), &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn side_hits() {
+        let cylinder = cylinder();
+        let rec = hit(&cylinder, Vec3::new(5.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert_near(rec.normal, Vec3::new(1.0, 0.0, 0.0), 1e-9);
+        assert!((rec.u - 0.5).abs() < 1e-9 && (rec.v - 0.5).abs() < 1e-9);
+
+        // u goes around the axis and v up along it
+        let rec = hit(&cylinder, Vec3::new(0.0, 5.0, 1.5), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert_near(rec.p, Vec3::new(0.0, 1.0, 1.5), 1e-9);
+        assert_near(rec.normal, Vec3::new(0.0, 1.0, 0.0), 1e-9);
+        assert!((rec.u - 0.75).abs() < 1e-9 && (rec.v - 0.75).abs() < 1e-9);
+
+        // from inside the normal faces back at the ray
+        let rec = hit(&cylinder, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-9 && !rec.front_face);
+        assert_near(rec.normal, Vec3::new(-1.0, 0.0, 0.0), 1e-9);
+
+        // passing above the top
+        assert!(hit(&cylinder, Vec3::new(5.0, 0.0, 2.5), Vec3::new(-1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn cap_hits() {
+        let cylinder = cylinder();
+        let rec = hit(&cylinder, Vec3::new(0.5, 0.25, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert_near(rec.normal, Vec3::new(0.0, 0.0, 1.0), 1e-9);
+        assert!((rec.u - 0.75).abs() < 1e-9 && (rec.v - 0.625).abs() < 1e-9);
+
+        let rec = hit(&cylinder, Vec3::new(0.5, 0.25, -5.0), Vec3::new(0.0, 0.0, 1.0)).unwrap();
+        assert!((rec.t - 5.0).abs() < 1e-9 && rec.front_face);
+        assert_near(rec.normal, Vec3::new(0.0, 0.0, -1.0), 1e-9);
+        assert!((rec.u - 0.75).abs() < 1e-9 && (rec.v - 0.625).abs() < 1e-9);
+
+        // straight down past the rim
+        assert!(hit(&cylinder, Vec3::new(0.8, 0.8, 5.0), Vec3::new(0.0, 0.0, -1.0)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cylinder needs its top apart from its base")]
+    fn flat_cylinders_are_rejected() {
+        Cylinder::new(Vec3::ones(), Vec3::ones(), 1.0, Lambertian::new(Vec3::ones()));
+    }
+}
@@ -0,0 +1,525 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use gltf::camera::Projection;
+use gltf::image::Format;
+use gltf::khr_lights_punctual::Kind;
+use gltf::material::AlphaMode;
+use gltf::mesh::Mode;
+use gltf::texture::{MagFilter, MinFilter, Sampler, WrappingMode};
+use image::{DynamicImage, ImageBuffer, Pixel, Rgb, Rgba};
+use crate::camera::Camera;
+use crate::hittable_list::HittableList;
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::linear_bvh::LinearBvh;
+use crate::mat4::Mat4;
+use crate::material::{phong_exponent, Dielectric, DiffuseLight, Glossy, Lambertian, Material, Metal, MetallicRoughness};
+use crate::cutout::Cutout;
+use crate::normal_map::{NormalMap, NormalMapped};
+use crate::texture::{ColorSpace, ImageTexture, Texture, TextureFilter, WrapMode};
+use crate::triangle_mesh::TriangleMesh;
+use crate::vec3::Vec3;
+
+#[derive(Debug)]
+pub enum GltfError {
+    Gltf(gltf::Error),
+    /// The scene has no triangles to build a BVH over.
+    NoTriangles,
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GltfError::Gltf(e) => Some(e),
+            GltfError::NoTriangles => None,
+        }
+    }
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Gltf(e) => write!(f, "Failed to load glTF file: {}", e),
+            GltfError::NoTriangles => write!(f, "The glTF scene has no triangles"),
+        }
+    }
+}
+
+impl From<gltf::Error> for GltfError {
+    fn from(e: gltf::Error) -> Self {
+        GltfError::Gltf(e)
+    }
+}
+
+/// Everything a glTF scene brings along, ready to hand to `Camera::render`.
+pub struct GltfScene {
+    /// All meshes in world space, in one BVH.
+    pub world: HittableList,
+    pub lights: Vec<Arc<dyn Light + Sync + Send>>,
+    pub cameras: Vec<GltfCamera>,
+}
+
+/// A perspective camera node, in the terms `Camera::new` takes.
+#[derive(Clone, Copy, Debug)]
+pub struct GltfCamera {
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vup: Vec3,
+    /// Vertical field of view in degrees.
+    pub vfov: f64,
+    pub aspect_ratio: Option<f64>,
+}
+
+impl GltfCamera {
+    /// A pinhole camera with this view, 16:9 if the file leaves the aspect ratio open.
+    pub fn camera(&self, image_width: u32, sample_per_pixel: u32) -> Camera {
+        let aspect_ratio = self.aspect_ratio.unwrap_or(16.0 / 9.0);
+        let focus_dist = (self.lookat - self.lookfrom).length();
+        Camera::new(image_width, aspect_ratio, sample_per_pixel, self.vfov, self.lookfrom, self.lookat, self.vup, 0.0, focus_dist)
+    }
+}
+
+/// Loads a `.gltf` (with its `.bin` and images) or `.glb` file.
+///
+/// Node transforms are baked into the vertices, so every mesh ends up in world space.
+/// Metallic-roughness materials are mapped onto the closest material we have: emission
+/// becomes a `DiffuseLight`, transmission a `Dielectric`, and a metallic-roughness texture
+/// a `MetallicRoughness` that reads it per texel. Without one, metals become a `Metal` with
+/// the roughness as fuzz, and the rest `Glossy` with a 4% dielectric highlight, or
+/// `Lambertian` when fully rough. Textures are multiplied by their factors. Punctual
+/// lights keep their intensity in glTF units, orthographic cameras are skipped.
+pub(crate) fn load_gltf<P: AsRef<Path>>(path: P) -> Result<GltfScene, GltfError> {
+    let (document, buffers, images) = gltf::import(path)?;
+    let mut loader = Loader {
+        buffers: &buffers,
+        images: &images,
+        triangles: HittableList::new(),
+        lights: Vec::new(),
+        cameras: Vec::new(),
+    };
+    if let Some(scene) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in scene.nodes() {
+            loader.node(&node, Mat4::identity());
+        }
+    }
+    if loader.triangles.objects.is_empty() {
+        return Err(GltfError::NoTriangles);
+    }
+    Ok(GltfScene {
+        world: HittableList::set(Arc::new(LinearBvh::set(loader.triangles))),
+        lights: loader.lights,
+        cameras: loader.cameras,
+    })
+}
+
+struct Loader<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [gltf::image::Data],
+    triangles: HittableList,
+    lights: Vec<Arc<dyn Light + Sync + Send>>,
+    cameras: Vec<GltfCamera>,
+}
+
+impl Loader<'_> {
+    fn node(&mut self, node: &gltf::Node, parent: Mat4) {
+        // glTF matrices are column-major
+        let local = node.transform().matrix();
+        let local = Mat4::new(std::array::from_fn(|row| std::array::from_fn(|col| local[col][row] as f64)));
+        let matrix = parent * local;
+
+        let position = matrix.transform_point(Vec3::zero());
+        let forward = matrix.transform_vector(Vec3::new(0.0, 0.0, -1.0)).unit();
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                self.primitive(&primitive, &matrix);
+            }
+        }
+        if let Some(camera) = node.camera() {
+            match camera.projection() {
+                Projection::Perspective(perspective) => self.cameras.push(GltfCamera {
+                    lookfrom: position,
+                    lookat: position + forward,
+                    vup: matrix.transform_vector(Vec3::new(0.0, 1.0, 0.0)).unit(),
+                    vfov: (perspective.yfov() as f64).to_degrees(),
+                    aspect_ratio: perspective.aspect_ratio().map(|a| a as f64),
+                }),
+                Projection::Orthographic(_) => {
+                    eprintln!("WARNING: orthographic camera '{}' is not supported.", camera.name().unwrap_or(""));
+                }
+            }
+        }
+        if let Some(light) = node.light() {
+            let intensity = to_vec3(light.color()) * light.intensity() as f64;
+            let light: Arc<dyn Light + Sync + Send> = match light.kind() {
+                Kind::Directional => Arc::new(DirectionalLight::new(forward, intensity)),
+                Kind::Point => Arc::new(PointLight::new(position, intensity)),
+                Kind::Spot { inner_cone_angle, outer_cone_angle } => Arc::new(SpotLight::new(
+                    position,
+                    position + forward,
+                    intensity,
+                    (outer_cone_angle as f64).to_degrees(),
+                    (inner_cone_angle as f64).to_degrees(),
+                )),
+            };
+            self.lights.push(light);
+        }
+        for child in node.children() {
+            self.node(&child, matrix);
+        }
+    }
+
+    fn primitive(&mut self, primitive: &gltf::Primitive, matrix: &Mat4) {
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let positions: Vec<Vec3> = match reader.read_positions() {
+            Some(positions) => positions.map(|p| matrix.transform_point(to_vec3(p))).collect(),
+            None => return,
+        };
+        let count = positions.len() as u32;
+        let order: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..count).collect(),
+        };
+        let indices: Vec<[u32; 3]> = match primitive.mode() {
+            Mode::Triangles => order.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+            // every other strip triangle is reversed to keep the winding
+            Mode::TriangleStrip => (2..order.len())
+                .map(|i| if i % 2 == 0 { [order[i - 2], order[i - 1], order[i]] } else { [order[i - 1], order[i - 2], order[i]] })
+                .collect(),
+            Mode::TriangleFan => (2..order.len()).map(|i| [order[0], order[i - 1], order[i]]).collect(),
+            _ => return,
+        };
+        if indices.iter().flatten().any(|&i| i >= count) {
+            eprintln!("WARNING: skipping a primitive with out of range indices.");
+            return;
+        }
+        let normals: Vec<Vec3> = match (reader.read_normals(), matrix.inverse()) {
+            (Some(normals), Some(inverse)) => normals.map(|n| inverse.transform_normal(to_vec3(n)).unit()).collect(),
+            _ => Vec::new(),
+        };
+        let material = primitive.material();
+        let pbr = material.pbr_metallic_roughness();
+        let tex_coord = pbr.base_color_texture().map_or(0, |info| info.tex_coord());
+        let uvs: Vec<(f64, f64)> = match reader.read_tex_coords(tex_coord) {
+            // glTF puts v = 0 at the top of the image, `ImageTexture` at the bottom
+            Some(uvs) => uvs.into_f32().map(|[u, v]| (u as f64, 1.0 - v as f64)).collect(),
+            None => Vec::new(),
+        };
+        // an attribute that doesn't cover every vertex is dropped rather than trusted
+        let normals = if normals.is_empty() || normals.len() == positions.len() { normals } else {
+            eprintln!("WARNING: ignoring {} normals for {} vertices.", normals.len(), positions.len());
+            Vec::new()
+        };
+        let uvs = if uvs.is_empty() || uvs.len() == positions.len() { uvs } else {
+            eprintln!("WARNING: ignoring {} texture coordinates for {} vertices.", uvs.len(), positions.len());
+            Vec::new()
+        };
+        // only textures on the same texture coordinates as the base color can be read
+        let image_texture = |texture: gltf::Texture, coords: u32, color_space: ColorSpace| {
+            if uvs.is_empty() || coords != tex_coord {
+                return None;
+            }
+            let image = decoded_image(&self.images[texture.source().index()])?;
+            Some(sampled(ImageTexture::from_image(image, color_space), &texture.sampler()))
+        };
+
+        let base = pbr.base_color_factor();
+        let base_color = Vec3::new(base[0] as f64, base[1] as f64, base[2] as f64);
+        let texture = pbr.base_color_texture()
+            .and_then(|info| image_texture(info.texture(), info.tex_coord(), ColorSpace::Srgb))
+            .map(|texture| Texture::ImageTex(texture.with_tint(base_color)))
+            .unwrap_or(Texture::SolidColor(base_color));
+        // the alpha of the base color texture times that of the factor, or the factor alone
+        let alpha = (material.alpha_mode() != AlphaMode::Opaque).then(|| {
+            pbr.base_color_texture()
+                .filter(|_| !uvs.is_empty())
+                .and_then(|info| {
+                    let image = decoded_image(&self.images[info.texture().source().index()]).filter(|image| image.color().has_alpha())?;
+                    let texture = ImageTexture::opacity_from_image(image).with_tint(Vec3::new(base[3] as f64, base[3] as f64, base[3] as f64));
+                    Some(Texture::ImageTex(sampled(texture, &info.texture().sampler())))
+                })
+                .unwrap_or(Texture::SolidColor(Vec3::new(base[3] as f64, base[3] as f64, base[3] as f64)))
+        });
+        let alpha_cutoff = (material.alpha_mode() == AlphaMode::Mask).then(|| material.alpha_cutoff().unwrap_or(0.5) as f64);
+        let normal_map = material.normal_texture()
+            .and_then(|info| {
+                let normals = Texture::ImageTex(image_texture(info.texture(), info.tex_coord(), ColorSpace::Linear)?);
+                Some(NormalMap::Tangent { normals, scale: info.scale() as f64 })
+            });
+        let emission = to_vec3(material.emissive_factor()) * material.emissive_strength().unwrap_or(1.0) as f64;
+        let emissive = material.emissive_texture()
+            .and_then(|info| image_texture(info.texture(), info.tex_coord(), ColorSpace::Srgb))
+            .map(|texture| Texture::ImageTex(texture.with_tint(emission)))
+            .unwrap_or(Texture::SolidColor(emission));
+        // roughness in the green channel and metalness in the blue one, both linear
+        let metal_rough = pbr.metallic_roughness_texture()
+            .and_then(|info| image_texture(info.texture(), info.tex_coord(), ColorSpace::Linear));
+        let metallic = pbr.metallic_factor() as f64;
+        let roughness = pbr.roughness_factor() as f64;
+
+        let mesh = Mesh { positions, normals, uvs, indices, normal_map, alpha, alpha_cutoff };
+        if emission.x > 0.0 || emission.y > 0.0 || emission.z > 0.0 {
+            self.add(mesh, DiffuseLight::new(emissive));
+        } else if material.transmission().is_some_and(|t| t.transmission_factor() > 0.0) {
+            self.add(mesh, Dielectric::new(material.ior().unwrap_or(1.5) as f64));
+        } else if let Some(metal_rough) = metal_rough {
+            self.add(mesh, MetallicRoughness::new(texture, Texture::ImageTex(metal_rough), metallic, roughness));
+        } else if metallic >= 0.5 {
+            self.add(mesh, Metal::set_texture(texture, roughness));
+        } else if roughness < 0.95 {
+            self.add(mesh, Glossy::new(texture, Vec3::new(0.04, 0.04, 0.04), phong_exponent(roughness)));
+        } else {
+            self.add(mesh, Lambertian::set_texture(texture));
+        }
+    }
+
+    fn add<Mat: Material + Sync + Send + 'static>(&mut self, mut mesh: Mesh, mat: Mat) {
+        match mesh.normal_map.take() {
+            Some(map) => self.add_cutout(mesh, NormalMapped::new(mat, map)),
+            None => self.add_cutout(mesh, mat),
+        }
+    }
+
+    fn add_cutout<Mat: Material + Sync + Send + 'static>(&mut self, mut mesh: Mesh, mat: Mat) {
+        match mesh.alpha.take() {
+            Some(alpha) => {
+                let cutout = Cutout { material: mat, alpha, cutoff: mesh.alpha_cutoff };
+                self.add_triangles(mesh, cutout)
+            }
+            None => self.add_triangles(mesh, mat),
+        }
+    }
+
+    fn add_triangles<Mat: Material + Sync + Send + 'static>(&mut self, mesh: Mesh, mat: Mat) {
+        let mesh = Arc::new(TriangleMesh::new(mesh.positions, mesh.normals, mesh.uvs, mesh.indices, mat));
+        for triangle in mesh.triangles().objects {
+            self.triangles.add(triangle);
+        }
+    }
+}
+
+/// Vertex buffers of one primitive before a material is picked for them.
+struct Mesh {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    indices: Vec<[u32; 3]>,
+    normal_map: Option<NormalMap>,
+    /// Opacity when the material is not opaque, with the cutoff of the `MASK` mode.
+    alpha: Option<Texture>,
+    alpha_cutoff: Option<f64>,
+}
+
+fn to_vec3(c: [f32; 3]) -> Vec3 {
+    Vec3::new(c[0] as f64, c[1] as f64, c[2] as f64)
+}
+
+/// The texture with the wrap modes and filters of a glTF sampler. Only samplers that ask
+/// for mipmaps, or say nothing, get trilinear filtering.
+fn sampled(texture: ImageTexture, sampler: &Sampler) -> ImageTexture {
+    let wrap = |mode| match mode {
+        WrappingMode::ClampToEdge => WrapMode::Clamp,
+        WrappingMode::MirroredRepeat => WrapMode::Mirror,
+        WrappingMode::Repeat => WrapMode::Repeat,
+    };
+    let texture = texture.with_wrap(wrap(sampler.wrap_s()), wrap(sampler.wrap_t()));
+    match (sampler.mag_filter(), sampler.min_filter()) {
+        (Some(MagFilter::Nearest), _) | (_, Some(MinFilter::Nearest)) => texture.with_filter(TextureFilter::Nearest),
+        (_, Some(MinFilter::Linear)) => texture.with_filter(TextureFilter::Bilinear),
+        _ => texture,
+    }
+}
+
+/// Converts the decoded pixels gltf hands out into an image of the same depth, 8-bit,
+/// 16-bit or float, with alpha if they have it.
+fn decoded_image(data: &gltf::image::Data) -> Option<DynamicImage> {
+    let (channels, size) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    if data.pixels.len() < (data.width * data.height) as usize * channels * size {
+        return None;
+    }
+    // bytes of channel `c` of a pixel, gray images repeat their one channel
+    let bytes = |x: u32, y: u32, c: usize| {
+        let offset = (y * data.width + x) as usize * channels * size + if channels < 3 { 0 } else { c } * size;
+        &data.pixels[offset..offset + size]
+    };
+    Some(match size {
+        1 => pixels(data, channels == 4, |x, y, c| bytes(x, y, c)[0]),
+        2 => pixels(data, channels == 4, |x, y, c| u16::from_ne_bytes([bytes(x, y, c)[0], bytes(x, y, c)[1]])),
+        _ => pixels(data, channels == 4, |x, y, c| f32::from_ne_bytes(std::array::from_fn(|i| bytes(x, y, c)[i]))),
+    })
+}
+
+/// An RGB or RGBA image of `data`'s size with the channels `channel` reads.
+fn pixels<P: image::Primitive>(data: &gltf::image::Data, alpha: bool, channel: impl Fn(u32, u32, usize) -> P) -> DynamicImage
+where
+    Rgb<P>: Pixel<Subpixel = P>,
+    Rgba<P>: Pixel<Subpixel = P>,
+    DynamicImage: From<ImageBuffer<Rgb<P>, Vec<P>>> + From<ImageBuffer<Rgba<P>, Vec<P>>>,
+{
+    if alpha {
+        return ImageBuffer::from_fn(data.width, data.height, |x, y| Rgba([0, 1, 2, 3].map(|c| channel(x, y, c)))).into();
+    }
+    ImageBuffer::from_fn(data.width, data.height, |x, y| Rgb([0, 1, 2].map(|c| channel(x, y, c)))).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::assert_near;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::temp_dir::TempDir;
+    use image::RgbImage;
+
+    // One triangle in the plane x + z = 1 with its normals, under a translated parent and
+    // a child scaled by two along x, textured with a single yellow texel.
+    fn triangle_scene(mode: u32) -> String {
+        r#"{
+            "asset": {"version": "2.0"},
+            "scene": 0,
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"translation": [10, 0, 0], "children": [1]}, {"scale": [2, 1, 1], "mesh": 0}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2}, "material": 0, "mode": MODE}]}],
+            "materials": [{"pbrMetallicRoughness": {
+                "baseColorFactor": [0.5, 1, 0.25, 1], "baseColorTexture": {"index": 0}, "metallicFactor": 0, "roughnessFactor": 1}}],
+            "textures": [{"source": 0}],
+            "images": [{"uri": "texel.png"}],
+            "buffers": [{"uri": "triangle.bin", "byteLength": 96}],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 72, "byteLength": 24}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 1]},
+                {"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3"},
+                {"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2"}]
+        }"#
+        .replace("MODE", &mode.to_string())
+    }
+
+    // Writes the scene with its buffer and image to a directory of its own and loads it.
+    fn load(name: &str, json: &str) -> Result<GltfScene, GltfError> {
+        let dir = TempDir::new(name);
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let floats = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, s, 0.0, s, s, 0.0, s, s, 0.0, s, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0f32];
+        dir.write("triangle.bin", floats.iter().flat_map(|f| f.to_le_bytes()).collect::<Vec<u8>>());
+        RgbImage::from_pixel(1, 1, Rgb([255, 255, 0])).save(dir.join("texel.png")).unwrap();
+        // metallic-roughness texels, fully rough and either metal or not
+        RgbImage::from_pixel(1, 1, Rgb([0, 255, 255])).save(dir.join("metal.png")).unwrap();
+        RgbImage::from_pixel(1, 1, Rgb([0, 255, 0])).save(dir.join("dielectric.png")).unwrap();
+        load_gltf(dir.write("scene.gltf", json))
+    }
+
+    #[test]
+    fn nodes_place_the_triangle_and_its_normals() {
+        let scene = load("gltf_nodes", &triangle_scene(4)).unwrap();
+        // the corners (1,0,0), (0,0,1), (1,1,0) scaled by 2 along x and moved 10 along x
+        let center = (Vec3::new(12.0, 0.0, 0.0) + Vec3::new(10.0, 0.0, 1.0) + Vec3::new(12.0, 1.0, 0.0)) / 3.0;
+        // the plane x / 2 + z = 1, a normal only scaled along x as well would lean the wrong way
+        let normal = Vec3::new(0.5, 0.0, 1.0).unit();
+        let ray = Ray::new(center + normal * 5.0, -normal);
+        let mut rec = HitRecord::new();
+        assert!(scene.world.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.t - 5.0).abs() < 1e-6);
+        assert_near(rec.p, center, 1e-6);
+        assert_near(rec.normal, normal, 1e-6);
+        assert_near(rec.geometric_normal, normal, 1e-6);
+    }
+
+    #[test]
+    fn base_color_factor_tints_the_texture() {
+        let scene = load("gltf_factor", &triangle_scene(4)).unwrap();
+        let normal = Vec3::new(0.5, 0.0, 1.0).unit();
+        let ray = Ray::new(Vec3::new(34.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0) + normal * 5.0, -normal);
+        let mut rec = HitRecord::new();
+        assert!(scene.world.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        // a yellow texel times the factor (0.5, 1, 0.25), through a Lambertian facing the light
+        let albedo = rec.material.eval(&ray, &rec, normal) * std::f64::consts::PI;
+        assert_near(albedo, Vec3::new(0.5, 1.0, 0.0), 1e-6);
+    }
+
+    // the material of the triangle, hit head on at its centroid
+    fn centroid_hit(scene: &GltfScene) -> (Ray, HitRecord<'_>) {
+        let normal = Vec3::new(0.5, 0.0, 1.0).unit();
+        let ray = Ray::new(Vec3::new(34.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0) + normal * 5.0, -normal);
+        let mut rec = HitRecord::new();
+        assert!(scene.world.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        (ray, rec)
+    }
+
+    #[test]
+    fn metallic_roughness_textures_are_read_per_texel() {
+        let with_texture = |image: &str| triangle_scene(4)
+            .replace(r#""metallicFactor": 0"#, r#""metallicFactor": 1, "metallicRoughnessTexture": {"index": 1}"#)
+            .replace(r#""textures": [{"source": 0}]"#, r#""textures": [{"source": 0}, {"source": 1}]"#)
+            .replace(r#""images": [{"uri": "texel.png"}]"#, &format!(r#""images": [{{"uri": "texel.png"}}, {{"uri": "{}"}}]"#, image));
+        let normal = Vec3::new(0.5, 0.0, 1.0).unit();
+
+        // a metal factor the texture takes back is the textured glossy dielectric
+        let scene = load("gltf_dielectric", &with_texture("dielectric.png")).unwrap();
+        let (ray, rec) = centroid_hit(&scene);
+        let glossy = Glossy::new(Texture::SolidColor(Vec3::new(0.5, 1.0, 0.0)), Vec3::new(0.04, 0.04, 0.04), phong_exponent(1.0));
+        assert_near(rec.material.eval(&ray, &rec, normal), glossy.eval(&ray, &rec, normal), 1e-6);
+
+        // a metal texel reflects like a mirror, with nothing to add for light from elsewhere
+        let scene = load("gltf_metal", &with_texture("metal.png")).unwrap();
+        let (ray, rec) = centroid_hit(&scene);
+        assert_near(rec.material.eval(&ray, &rec, normal), Vec3::zero(), 1e-6);
+    }
+
+    #[test]
+    fn emissive_textures_are_tinted_by_the_emissive_factor() {
+        let json = triangle_scene(4).replace(r#""materials": [{"#, r#""materials": [{"emissiveFactor": [1, 0.5, 1], "emissiveTexture": {"index": 0}, "#);
+        let scene = load("gltf_emissive", &json).unwrap();
+        let (_, rec) = centroid_hit(&scene);
+        assert_near(rec.material.emitted(rec.u, rec.v, &rec.p), Vec3::new(1.0, 0.5, 0.0), 1e-6);
+    }
+
+    #[test]
+    fn attributes_missing_vertices_are_dropped() {
+        let json = triangle_scene(4).replace(r#"{"bufferView": 1, "componentType": 5126, "count": 3"#, r#"{"bufferView": 1, "componentType": 5126, "count": 2"#);
+        let scene = load("gltf_short_normals", &json).unwrap();
+        let (_, rec) = centroid_hit(&scene);
+        assert_near(rec.normal, rec.geometric_normal, 1e-6);
+    }
+
+    #[test]
+    fn deep_images_keep_their_precision() {
+        let data = |format, pixels: Vec<u8>| gltf::image::Data { pixels, format, width: 1, height: 1 };
+        // 1000 / 65535 is between two 8-bit steps
+        let shorts = [1000u16, 2000, 3000, 65535].iter().flat_map(|c| c.to_ne_bytes()).collect();
+        match decoded_image(&data(Format::R16G16B16A16, shorts)) {
+            Some(DynamicImage::ImageRgba16(image)) => assert_eq!(image.get_pixel(0, 0).0, [1000, 2000, 3000, 65535]),
+            other => panic!("{:?}", other.map(|image| image.color())),
+        }
+        // float texels above one stay there
+        let floats = [2.5f32, 0.125, 0.0].iter().flat_map(|c| c.to_ne_bytes()).collect();
+        match decoded_image(&data(Format::R32G32B32FLOAT, floats)) {
+            Some(DynamicImage::ImageRgb32F(image)) => assert_eq!(image.get_pixel(0, 0).0, [2.5, 0.125, 0.0]),
+            other => panic!("{:?}", other.map(|image| image.color())),
+        }
+        // gray repeats into all three channels
+        match decoded_image(&data(Format::R16, 1234u16.to_ne_bytes().to_vec())) {
+            Some(DynamicImage::ImageRgb16(image)) => assert_eq!(image.get_pixel(0, 0).0, [1234; 3]),
+            other => panic!("{:?}", other.map(|image| image.color())),
+        }
+        assert!(decoded_image(&data(Format::R16, vec![0])).is_none());
+    }
+
+    #[test]
+    fn scenes_without_triangles_are_an_error() {
+        // the same mesh drawn as points
+        assert!(matches!(load("gltf_points", &triangle_scene(0)), Err(GltfError::NoTriangles)));
+    }
+}
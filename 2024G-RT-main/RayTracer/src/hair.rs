@@ -0,0 +1,361 @@
+use crate::camera::random;
+use crate::curve::curve_frame;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+/// Scattering orders modeled separately: R, TT and TRT. Everything after is one more lobe.
+const P_MAX: usize = 3;
+
+/// Absorption of eumelanin and pheomelanin per unit concentration, from d'Eon et al. 2011.
+const EUMELANIN_SIGMA_A: Vec3 = Vec3 { x: 0.419, y: 0.697, z: 1.37 };
+const PHEOMELANIN_SIGMA_A: Vec3 = Vec3 { x: 0.187, y: 0.4, z: 1.05 };
+
+/// The hair scattering model of Chiang et al. 2016 as pbrt-v3 implements it: light is
+/// reflected off the surface of a rough dielectric cylinder, or refracted in, absorbed
+/// on the way through and let out again after zero, one or more internal reflections.
+/// Each path gets a longitudinal lobe shifted by the tilt of the cuticle scales and an
+/// azimuthal lobe around the direction a smooth cylinder would send it.
+///
+/// Meant for `Curve`s, it needs `HitRecord::tangent` and reads where across the fiber
+/// the ray hit from `v`.
+#[derive(Clone)]
+pub struct HairBsdf {
+    sigma_a: Vec3,
+    eta: f64,
+    /// Variance of the longitudinal lobes for R, TT, TRT and the rest.
+    v: [f64; P_MAX + 1],
+    /// Scale of the logistic azimuthal lobes.
+    s: f64,
+    /// sin and cos of twice, once and four times the scale tilt, the shifts for R, TT and TRT.
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl HairBsdf {
+    /// `beta_m` and `beta_n` are the longitudinal and azimuthal roughness between 0 and 1,
+    /// `alpha` the tilt of the scales in degrees, about 2 for human hair.
+    pub fn new(sigma_a: Vec3, beta_m: f64, beta_n: f64, alpha: f64) -> Self {
+        let v0 = (0.726 * beta_m + 0.812 * beta_m * beta_m + 3.7 * beta_m.powi(20)).powi(2);
+        let v = [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0];
+        let s = (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n * beta_n + 5.372 * beta_n.powi(22));
+        let mut sin_2k_alpha = [alpha.to_radians().sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [(1.0 - sin_2k_alpha[0] * sin_2k_alpha[0]).max(0.0).sqrt(), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+        Self { sigma_a, eta: 1.55, v, s, sin_2k_alpha, cos_2k_alpha }
+    }
+
+    /// Hair colored by its melanin, from about 0 for blond to 8 for black. Pheomelanin
+    /// makes it red.
+    pub fn from_melanin(eumelanin: f64, pheomelanin: f64, beta_m: f64, beta_n: f64) -> Self {
+        Self::new(EUMELANIN_SIGMA_A * eumelanin + PHEOMELANIN_SIGMA_A * pheomelanin, beta_m, beta_n, 2.0)
+    }
+
+    /// Hair whose multiple scattering comes out at about `color`, for dyed or fur colors.
+    pub fn from_color(color: Vec3, beta_m: f64, beta_n: f64) -> Self {
+        let b = beta_n;
+        let denom = 5.969 - 0.215 * b + 2.532 * b.powi(2) - 10.73 * b.powi(3) + 5.574 * b.powi(4) + 0.245 * b.powi(5);
+        let sigma = |c: f64| (c.clamp(1e-4, 1.0).ln() / denom).powi(2);
+        Self::new(Vec3::new(sigma(color.x), sigma(color.y), sigma(color.z)), beta_m, beta_n, 2.0)
+    }
+
+    /// `wo` and `wi` in the hair frame: `x` along the fiber, `z` facing back along the
+    /// camera ray, where `h` is measured along `y`.
+    fn f(&self, h: f64, wo: Vec3, wi: Vec3) -> Vec3 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (gamma_o, gamma_t, transmittance) = self.refraction(h, sin_theta_o, cos_theta_o);
+        let ap = ap(cos_theta_o, self.eta, h, transmittance);
+        let phi = phi_i - phi_o;
+
+        let mut sum = Vec3::zero();
+        for (p, ap) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            let mp = mp(cos_theta_i, cos_theta_op, sin_theta_i, sin_theta_op, self.v[p]);
+            sum += *ap * (mp * np(phi, p, self.s, gamma_o, gamma_t));
+        }
+        let mp = mp(cos_theta_i, cos_theta_o, sin_theta_i, sin_theta_o, self.v[P_MAX]);
+        sum + ap[P_MAX] * (mp / (2.0 * PI))
+    }
+
+    /// `gamma_o`, `gamma_t` and the transmittance of one pass through the fiber.
+    fn refraction(&self, h: f64, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64, Vec3) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = (1.0 - sin_theta_t * sin_theta_t).max(0.0).sqrt();
+        let etap = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        let sin_gamma_t = (h / etap).clamp(-1.0, 1.0);
+        let cos_gamma_t = (1.0 - sin_gamma_t * sin_gamma_t).max(0.0).sqrt();
+        let length = 2.0 * cos_gamma_t / cos_theta_t;
+        let transmittance = Vec3::new((-self.sigma_a.x * length).exp(), (-self.sigma_a.y * length).exp(), (-self.sigma_a.z * length).exp());
+        (h.clamp(-1.0, 1.0).asin(), sin_gamma_t.asin(), transmittance)
+    }
+
+    /// `theta_o` rotated by the scale tilt seen by path `p`.
+    fn tilt(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin, cos) = match p {
+            0 => (-self.sin_2k_alpha[1], self.cos_2k_alpha[1]),
+            1 => (self.sin_2k_alpha[0], self.cos_2k_alpha[0]),
+            2 => (self.sin_2k_alpha[2], self.cos_2k_alpha[2]),
+            _ => return (sin_theta_o, cos_theta_o),
+        };
+        (sin_theta_o * cos + cos_theta_o * sin, (cos_theta_o * cos - sin_theta_o * sin).abs())
+    }
+
+    /// How likely each path is picked, by the luminance of its attenuation.
+    fn ap_pdf(&self, h: f64, sin_theta_o: f64, cos_theta_o: f64) -> [f64; P_MAX + 1] {
+        let (_, _, transmittance) = self.refraction(h, sin_theta_o, cos_theta_o);
+        let luminance = ap(cos_theta_o, self.eta, h, transmittance).map(|a| 0.2126 * a.x + 0.7152 * a.y + 0.0722 * a.z);
+        let total: f64 = luminance.iter().sum();
+        luminance.map(|l| if total > 0.0 { l / total } else { 1.0 / (P_MAX + 1) as f64 })
+    }
+
+    fn pdf(&self, h: f64, wo: Vec3, wi: Vec3) -> f64 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (gamma_o, gamma_t, _) = self.refraction(h, sin_theta_o, cos_theta_o);
+        let ap_pdf = self.ap_pdf(h, sin_theta_o, cos_theta_o);
+        let phi = phi_i - phi_o;
+
+        let mut pdf = 0.0;
+        for (p, ap) in ap_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            pdf += mp(cos_theta_i, cos_theta_op, sin_theta_i, sin_theta_op, self.v[p]) * ap * np(phi, p, self.s, gamma_o, gamma_t);
+        }
+        pdf + mp(cos_theta_i, cos_theta_o, sin_theta_i, sin_theta_o, self.v[P_MAX]) * ap_pdf[P_MAX] / (2.0 * PI)
+    }
+
+    /// Picks a path, then `theta_i` from its longitudinal and `phi_i` from its azimuthal lobe.
+    fn sample(&self, h: f64, wo: Vec3) -> Vec3 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let ap_pdf = self.ap_pdf(h, sin_theta_o, cos_theta_o);
+        let mut pick = random();
+        let mut p = 0;
+        while p < P_MAX && pick >= ap_pdf[p] {
+            pick -= ap_pdf[p];
+            p += 1;
+        }
+
+        let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+        let u = random().max(1e-5);
+        let cos_theta = 1.0 + self.v[p] * (u + (1.0 - u) * (-2.0 / self.v[p]).exp()).ln();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let cos_phi = (2.0 * PI * random()).cos();
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = (1.0 - sin_theta_i * sin_theta_i).max(0.0).sqrt();
+
+        let (gamma_o, gamma_t, _) = self.refraction(h, sin_theta_o, cos_theta_o);
+        let dphi = if p < P_MAX {
+            phi(p, gamma_o, gamma_t) + sample_trimmed_logistic(random(), self.s, -PI, PI)
+        } else {
+            2.0 * PI * random()
+        };
+        let phi_i = phi_o + dphi;
+        Vec3::new(sin_theta_i, cos_theta_i * phi_i.cos(), cos_theta_i * phi_i.sin())
+    }
+}
+
+/// The hair frame at a hit, `None` for a record without a tangent or a ray along the fiber.
+fn hair_frame(r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Vec3, Vec3)> {
+    if hit_record.tangent.near_zero() {
+        return None;
+    }
+    let (facing, side) = curve_frame(r_in.direction.unit(), hit_record.tangent)?;
+    Some((hit_record.tangent, side, facing))
+}
+
+fn to_hair(frame: &(Vec3, Vec3, Vec3), w: Vec3) -> Vec3 {
+    Vec3::new(w * frame.0, w * frame.1, w * frame.2)
+}
+
+impl Material for HairBsdf {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
+        let frame = hair_frame(r_in, hit_record)?;
+        let h = 2.0 * hit_record.v - 1.0;
+        let wo = to_hair(&frame, -r_in.direction.unit());
+        let wi = self.sample(h, wo);
+        let pdf = self.pdf(h, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let direction = frame.0 * wi.x + frame.1 * wi.y + frame.2 * wi.z;
+        // the cosine is part of the longitudinal lobes, unlike on surfaces
+        Some((Ray::new_time(hit_record.p, direction, r_in.time), self.f(h, wo, wi) / pdf))
+    }
+    fn eval(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Vec3 {
+        let frame = match hair_frame(r_in, hit_record) {
+            Some(frame) => frame,
+            None => return Vec3::zero(),
+        };
+        let h = 2.0 * hit_record.v - 1.0;
+        self.f(h, to_hair(&frame, -r_in.direction.unit()), to_hair(&frame, wi.unit()))
+    }
+}
+
+/// sin and cos of the angle to the normal plane and the angle around the fiber.
+fn angles(w: Vec3) -> (f64, f64, f64) {
+    let sin_theta = w.x.clamp(-1.0, 1.0);
+    (sin_theta, (1.0 - sin_theta * sin_theta).max(0.0).sqrt(), w.z.atan2(w.y))
+}
+
+/// Longitudinal scattering, a normalized von Mises-Fisher lobe.
+fn mp(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        // sinh(1 / v) overflows for smooth hair, so work with logarithms
+        (log_i0(a) - b - 1.0 / v + std::f64::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+/// Modified Bessel function of the first kind, by its series.
+fn i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial: f64 = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f64;
+        }
+        value += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+/// Attenuation of each path: Fresnel reflection, then transmission through the fiber
+/// between internal reflections. The last entry sums all longer paths.
+fn ap(cos_theta_o: f64, eta: f64, h: f64, transmittance: Vec3) -> [Vec3; P_MAX + 1] {
+    let cos_gamma_o = (1.0 - h * h).max(0.0).sqrt();
+    let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, eta);
+    let mut ap = [Vec3::zero(); P_MAX + 1];
+    ap[0] = Vec3::new(f, f, f);
+    ap[1] = transmittance * (1.0 - f).powi(2);
+    for p in 2..P_MAX {
+        ap[p] = Vec3::elemul(ap[p - 1], transmittance) * f;
+    }
+    let tf = transmittance * f;
+    ap[P_MAX] = Vec3::new(
+        ap[P_MAX - 1].x * tf.x / (1.0 - tf.x),
+        ap[P_MAX - 1].y * tf.y / (1.0 - tf.y),
+        ap[P_MAX - 1].z * tf.z / (1.0 - tf.z),
+    );
+    ap
+}
+
+/// Unpolarized Fresnel reflectance from air into a dielectric of index `eta`.
+fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0).sqrt() / eta;
+    if sin_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin_theta_t * sin_theta_t).max(0.0).sqrt();
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// Where a smooth fiber sends path `p` around its axis.
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    2.0 * p as f64 * gamma_t - 2.0 * gamma_o + p as f64 * PI
+}
+
+/// Azimuthal scattering, a logistic lobe around `phi`.
+fn np(phi_diff: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi_diff - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s, -PI, PI)
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let e = (-x.abs() / s).exp();
+    e / (s * (1.0 + e) * (1.0 + e))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x: f64, s: f64, a: f64, b: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64, a: f64, b: f64) -> f64 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    x.clamp(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniform_sphere() -> Vec3 {
+        let z = 1.0 - 2.0 * random();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * random();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    // Without absorption every path lets all light out again, so the lobes integrate to
+    // one over the sphere, as in pbrt's white furnace test. Importance sampled, uniform
+    // directions are too noisy for smooth hair.
+    #[test]
+    fn white_furnace() {
+        let count = 20_000;
+        for beta in [0.1, 0.3, 0.6, 0.9] {
+            let hair = HairBsdf::new(Vec3::zero(), beta, beta, 2.0);
+            let mut sum = Vec3::zero();
+            for _ in 0..count {
+                let (h, wo) = (2.0 * random() - 1.0, uniform_sphere());
+                let wi = hair.sample(h, wo);
+                sum += hair.f(h, wo, wi) / hair.pdf(h, wo, wi) / count as f64;
+            }
+            assert!((sum.y - 1.0).abs() < 0.02, "beta {beta}: {}", sum.y);
+        }
+    }
+
+    #[test]
+    fn sampling_matches_pdf() {
+        let hair = HairBsdf::from_melanin(1.3, 0.0, 0.3, 0.3);
+        let wo = Vec3::new(0.3, 0.0, (1.0f64 - 0.09).sqrt());
+        let h = 0.4;
+        // the pdf integrates to one, and samples weighted by f / pdf average to the albedo
+        let count = 100_000;
+        let mut integral = 0.0;
+        for _ in 0..count {
+            integral += hair.pdf(h, wo, uniform_sphere()) * 4.0 * PI / count as f64;
+        }
+        assert!((integral - 1.0).abs() < 0.05, "{integral}");
+        let mut sampled = Vec3::zero();
+        let mut uniform = Vec3::zero();
+        for _ in 0..count {
+            let wi = hair.sample(h, wo);
+            sampled += hair.f(h, wo, wi) / hair.pdf(h, wo, wi) / count as f64;
+            uniform += hair.f(h, wo, uniform_sphere()) * (4.0 * PI / count as f64);
+        }
+        assert!((sampled.y - uniform.y).abs() < 0.05, "{} {}", sampled.y, uniform.y);
+    }
+}
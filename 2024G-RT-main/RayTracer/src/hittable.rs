@@ -2,21 +2,43 @@ use num_traits::FloatConst;
 use crate::AABB::Aabb;
 use crate::vec3::Vec3;
 use crate::interval::Interval;
-use crate::material::Material;
+use crate::material::{Lambertian, Material};
+use crate::texture::Texture;
 use crate::ray::Ray;
 
 
-pub struct HitRecord {
+/// Stands in for the material of a record nothing has been written to yet.
+static NO_MATERIAL: Lambertian = Lambertian { tex: Texture::SolidColor(Vec3 { x: 0.0, y: 0.0, z: 0.0 }) };
+
+/// The material is borrowed from the object that was hit, so filling a record never allocates.
+pub struct HitRecord<'a> {
     pub t: f64,
     pub u: f64,
     pub v: f64,
     pub p: Vec3,
+    /// Shading normal, always on the side the ray came from.
     pub normal: Vec3,
+    /// Normal of the actual surface, on the same side as `normal`. The two only differ
+    /// on triangles with interpolated vertex normals.
+    pub geometric_normal: Vec3,
     pub front_face: bool,
-    pub material: Box<dyn Material>,
+    /// Unit direction along the surface for anisotropic materials, the direction of the
+    /// curve on curves. Zero where the surface has none.
+    pub tangent: Vec3,
+    /// Tangent and bitangent of the texture parameterization: how far `p` moves per unit of
+    /// `u` and of `v`. Zero on surfaces whose `u`, `v` are not smooth enough to bend normals with.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    /// Interpolated vertex color, white on everything that has none.
+    pub color: Vec3,
+    /// How far `u` and `v` move to the neighbouring pixel across and down, for filtering
+    /// textures. Only camera rays have them, they are zero everywhere else.
+    pub duv_dx: (f64, f64),
+    pub duv_dy: (f64, f64),
+    pub material: &'a dyn Material,
 }
 
-impl HitRecord {
+impl<'a> HitRecord<'a> {
     pub fn new() -> Self {
         Self {
             t: 0.0,
@@ -24,8 +46,15 @@ impl HitRecord {
             v: 0.0,
             p: Vec3::new(0.0, 0.0, 0.0),
             normal: Vec3::new(0.0, 0.0, 0.0),
+            geometric_normal: Vec3::new(0.0, 0.0, 0.0),
             front_face: false,
-            material: Box::new(crate::material::Lambertian::new(Vec3::new(0.0, 0.0, 0.0))),
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            dpdu: Vec3::new(0.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 0.0),
+            color: Vec3::new(1.0, 1.0, 1.0),
+            duv_dx: (0.0, 0.0),
+            duv_dy: (0.0, 0.0),
+            material: &NO_MATERIAL,
         }
     }
     pub(crate) fn set_face_normal(&mut self, ray: crate::ray::Ray, outward_normal: Vec3) {
@@ -35,12 +64,24 @@ impl HitRecord {
         } else {
             -outward_normal
         };
+        self.geometric_normal = self.normal;
+    }
+    /// Whether `direction` leaves on the ray's side of the real surface. A shading normal
+    /// can send a reflected ray into the surface, which shows up as light leaking through.
+    pub fn above_surface(&self, direction: Vec3) -> bool {
+        direction * self.geometric_normal > 0.0
     }
 }
 
 pub trait Hittable: {
-    fn hit(&self, ray: &crate::ray::Ray, ray_t: Interval,rev:&mut HitRecord) -> bool;
+    fn hit<'a>(&'a self, ray: &crate::ray::Ray, ray_t: Interval,rev:&mut HitRecord<'a>) -> bool;
     fn bounding_box(&self) -> Option<Aabb>;
+    /// Whether anything blocks `ray` within `ray_t`. Unlike `hit` it may stop at the
+    /// first intersection found and never fills a record, so use it for shadow rays.
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        let mut rec = HitRecord::new();
+        self.hit(ray, ray_t, &mut rec)
+    }
 }
 
 pub struct Translate<Obj: Hittable+ Sync + Send> {
@@ -61,7 +102,7 @@ impl<Obj: Hittable+ Sync + Send> Translate<Obj> {
 }
 
 impl<Obj: Hittable+ Sync + Send> Hittable for Translate<Obj> {
-    fn hit(&self, ray: &crate::ray::Ray, ray_t: Interval, rec: &mut HitRecord) -> bool{
+    fn hit<'a>(&'a self, ray: &crate::ray::Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool{
         let moved_ray = Ray::new_time(ray.origin - self.offset, ray.direction, ray.time);
         let hit = self.obj.hit(&moved_ray, ray_t,rec);
         if hit {
@@ -72,6 +113,10 @@ impl<Obj: Hittable+ Sync + Send> Hittable for Translate<Obj> {
     fn bounding_box(&self) -> Option<Aabb> {
         Some(self.bbox)
     }
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        let moved_ray = Ray::new_time(ray.origin - self.offset, ray.direction, ray.time);
+        self.obj.occluded(&moved_ray, ray_t)
+    }
 }
 
 pub struct RotateY<Obj: Hittable+ Sync + Send> {
@@ -92,7 +137,7 @@ impl<Obj: Hittable+ Sync + Send> RotateY<Obj> {
         for i in 0..2 {
             for j in 0..2 {
                 for k in 0..2 {
-                    let x = i as f64 * bbox.x.max + (1.0 - i as f64) * bbox.y.min;
+                    let x = i as f64 * bbox.x.max + (1.0 - i as f64) * bbox.x.min;
                     let y = j as f64 * bbox.y.max + (1.0 - j as f64) * bbox.y.min;
                     let z = k as f64 * bbox.z.max + (1.0 - k as f64) * bbox.z.min;
                     let newx = cos_theta * x + sin_theta * z;
@@ -119,29 +164,191 @@ impl<Obj: Hittable+ Sync + Send> RotateY<Obj> {
     }
 }
 
-impl<Obj: Hittable+ Sync + Send> Hittable for RotateY<Obj> {
-    fn hit(&self, ray: &Ray, ray_t: Interval,rec:&mut HitRecord) ->bool {
+impl<Obj: Hittable+ Sync + Send> RotateY<Obj> {
+    /// `ray` moved from world space into object space.
+    fn local_ray(&self, ray: &Ray) -> Ray {
         let mut origin = ray.origin;
         let mut direction = ray.direction;
         origin.x = self.cos_theta * ray.origin.x - self.sin_theta * ray.origin.z;
         origin.z = self.sin_theta * ray.origin.x + self.cos_theta * ray.origin.z;
         direction.x = self.cos_theta * ray.direction.x - self.sin_theta * ray.direction.z;
         direction.z = self.sin_theta * ray.direction.x + self.cos_theta * ray.direction.z;
-        let r=Ray::new_time(origin,direction,ray.time);
+        Ray::new_time(origin,direction,ray.time)
+    }
+}
+
+impl<Obj: Hittable+ Sync + Send> Hittable for RotateY<Obj> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval,rec:&mut HitRecord<'a>) ->bool {
+        let r=self.local_ray(ray);
         if !self.obj.hit(&r,ray_t,rec){
             return false;
         }
         let mut p:Vec3=rec.p;
         p.x=self.cos_theta*rec.p.x+self.sin_theta*rec.p.z;
         p.z=-self.sin_theta*rec.p.x+self.cos_theta*rec.p.z;
-        let mut normal:Vec3=rec.normal;
-        normal.x=self.cos_theta*rec.normal.x+self.sin_theta*rec.normal.z;
-        normal.z=-self.sin_theta*rec.normal.x+self.cos_theta*rec.normal.z;
+        let rotate=|n:Vec3|Vec3::new(self.cos_theta*n.x+self.sin_theta*n.z,n.y,-self.sin_theta*n.x+self.cos_theta*n.z);
         rec.p=p;
-        rec.normal=normal;
+        rec.normal=rotate(rec.normal);
+        rec.geometric_normal=rotate(rec.geometric_normal);
+        rec.tangent=rotate(rec.tangent);
+        rec.dpdu=rotate(rec.dpdu);
+        rec.dpdv=rotate(rec.dpdv);
         true
     }
     fn bounding_box(&self) -> Option<Aabb> {
         return Some(self.bbox);
     }
-}
\ No newline at end of file
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.obj.occluded(&self.local_ray(ray), ray_t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::bvh::BvhNode;
+    use crate::cone::Cone;
+    use crate::constant_medium::ConstantMedium;
+    use crate::curve::{Curve, CurveKind};
+    use crate::cylinder::Cylinder;
+    use crate::hittable_list::HittableList;
+    use crate::linear_bvh::LinearBvh;
+    use crate::material::Isotropic;
+    use crate::quad::quad;
+    use crate::sdf::{Sdf, SdfShape};
+    use crate::simd_mesh::SimdMesh;
+    use crate::sphere::Sphere;
+    use crate::torus::Torus;
+    use crate::triangle::Triangle;
+    use crate::triangle_mesh::TriangleMesh;
+
+    // rays down -z from a grid in front of the origin, none of them grazing an edge
+    fn assert_occluded_agrees(object: &dyn Hittable) {
+        for i in 0..17 {
+            for j in 0..17 {
+                let origin = Vec3::new(-2.01 + 0.25 * i as f64, -2.01 + 0.25 * j as f64, 5.0);
+                let ray = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0));
+                for ray_t in [Interval::set(0.001, f64::INFINITY), Interval::set(0.001, 3.0), Interval::set(8.0, f64::INFINITY)] {
+                    let mut rec = HitRecord::new();
+                    assert_eq!(object.hit(&ray, ray_t, &mut rec), object.occluded(&ray, ray_t), "{:?} in {}..{}", origin, ray_t.min, ray_t.max);
+                }
+            }
+        }
+    }
+
+    fn spheres() -> HittableList {
+        let mut list = HittableList::new();
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (0.0, 1.0)] {
+            list.add(Arc::new(Sphere::new(Vec3::new(x, y, -x), 0.7, Lambertian::new(Vec3::ones()))));
+        }
+        list
+    }
+
+    #[test]
+    fn occluded_agrees_with_hit() {
+        let white = Lambertian::new(Vec3::ones());
+        assert_occluded_agrees(&Sphere::new(Vec3::zero(), 0.9, white.clone()));
+        assert_occluded_agrees(&quad::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.5, 0.0), white.clone()));
+        assert_occluded_agrees(&BvhNode::set(spheres()));
+        assert_occluded_agrees(&LinearBvh::set(spheres()));
+        // dense enough to scatter right where the ray enters
+        let boundary = Arc::new(Sphere::new(Vec3::zero(), 0.9, white));
+        assert_occluded_agrees(&ConstantMedium::new(boundary, 1e9, Isotropic::new(Vec3::ones())));
+    }
+
+    fn down(x: f64, y: f64) -> Ray {
+        Ray::new(Vec3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    // Objects reached first in a BVH write into the record that nearer ones then reuse.
+    #[test]
+    fn every_surface_writes_its_own_tangents_and_color() {
+        let white = || Lambertian::new(Vec3::ones());
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let triangle = [Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+        let mesh = Arc::new(TriangleMesh::new(triangle.to_vec(), Vec::new(), Vec::new(), vec![[0, 1, 2]], white())).triangles();
+        let cp = [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(-0.3, 0.0, 0.0), Vec3::new(0.3, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)];
+        let fog = ConstantMedium::new(Arc::new(Sphere::new(Vec3::zero(), 1.0, white())), 1e9, Isotropic::new(Vec3::ones()));
+        let objects: Vec<(&str, Box<dyn Hittable>)> = vec![
+            ("sphere", Box::new(Sphere::new(Vec3::zero(), 1.0, white()))),
+            ("quad", Box::new(quad::new(Vec3::new(-1.0, -1.0, 0.0), x * 2.0, y * 2.0, white()))),
+            ("triangle", Box::new(Triangle::new(triangle[0], triangle[1], triangle[2], white()))),
+            ("mesh", Box::new(mesh)),
+            ("simd mesh", Box::new(SimdMesh::<_>::new(vec![triangle], white()))),
+            ("cylinder", Box::new(Cylinder::new(-x, x, 1.0, white()))),
+            ("cone", Box::new(Cone::new(-x, x, 1.0, white()))),
+            ("torus", Box::new(Torus::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 1.0, 0.4, white()))),
+            ("sdf", Box::new(SdfShape::new(Sdf::sphere(1.0), Vec3::zero(), white()))),
+            ("curve", Box::new(Curve::new(cp, 0.2, 0.2, CurveKind::Flat, white()))),
+            ("medium", Box::new(fog)),
+        ];
+        let stale = Vec3::new(9.0, 9.0, 9.0);
+        for (name, object) in &objects {
+            let mut rec = HitRecord::new();
+            (rec.tangent, rec.dpdu, rec.dpdv, rec.color) = (stale, stale, stale, stale);
+            let ray = if *name == "torus" { down(1.0, 0.1) } else { down(0.1, 0.05) };
+            assert!(object.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec), "{}", name);
+            for (field, value) in [("tangent", rec.tangent), ("dpdu", rec.dpdu), ("dpdv", rec.dpdv), ("color", rec.color)] {
+                assert!(value != stale, "{} kept the {} of an earlier hit", name, field);
+            }
+            assert_eq!(rec.color, Vec3::ones(), "{}", name);
+            assert!(*name == "curve" || rec.tangent.near_zero(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn nearer_hits_in_a_bvh_replace_the_tangents_of_farther_ones() {
+        let white = || Lambertian::new(Vec3::ones());
+        // a cylinder along x in front of a sphere, both under the ray
+        for near_first in [false, true] {
+            let mut list = HittableList::new();
+            let far: Arc<dyn Hittable + Sync + Send> = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -3.0), 1.0, white()));
+            let near: Arc<dyn Hittable + Sync + Send> = Arc::new(Cylinder::new(Vec3::new(-1.0, 0.0, 2.0), Vec3::new(1.0, 0.0, 2.0), 0.5, white()));
+            if near_first {
+                list.add(near);
+                list.add(far);
+            } else {
+                list.add(far);
+                list.add(near);
+            }
+            let bvh = LinearBvh::set(list);
+            let mut rec = HitRecord::new();
+            assert!(bvh.hit(&down(0.3, 0.2), Interval::set(0.001, f64::INFINITY), &mut rec));
+            assert!((rec.p.z - (2.0 + (0.25f64 - 0.04).sqrt())).abs() < 1e-9);
+            // v runs along the axis and u around it, a quarter turn per radius times pi / 2
+            assert!((rec.dpdv - Vec3::new(2.0, 0.0, 0.0)).length() < 1e-9);
+            assert!((rec.dpdu * Vec3::new(1.0, 0.0, 0.0)).abs() < 1e-9 && (rec.dpdu * rec.normal).abs() < 1e-9);
+            assert!((rec.dpdu.length() - std::f64::consts::PI).abs() < 1e-9);
+        }
+    }
+
+    // Stepping along dpdu or dpdv and back onto the surface moves u or v by the step.
+    fn assert_tangents_match_uvs(name: &str, object: &dyn Hittable, ray: Ray) {
+        let mut rec = HitRecord::new();
+        assert!(object.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec), "{}", name);
+        let eps = 1e-6;
+        for (d, du, dv) in [(rec.dpdu, eps, 0.0), (rec.dpdv, 0.0, eps)] {
+            let target = rec.p + d * eps;
+            let mut moved = HitRecord::new();
+            assert!(object.hit(&Ray::new(target + rec.normal, -rec.normal), Interval::set(0.001, f64::INFINITY), &mut moved));
+            assert!((moved.u - rec.u - du).abs() < 1e-9 && (moved.v - rec.v - dv).abs() < 1e-9, "{}: {} {}", name, moved.u - rec.u, moved.v - rec.v);
+        }
+    }
+
+    #[test]
+    fn tangents_of_round_shapes_follow_their_uvs() {
+        let white = || Lambertian::new(Vec3::ones());
+        let (base, top) = (Vec3::new(0.2, -1.0, 0.3), Vec3::new(-0.1, 1.0, 0.1));
+        let cylinder = Cylinder::new(base, top, 0.8, white());
+        assert_tangents_match_uvs("cylinder side", &cylinder, Ray::new(Vec3::new(5.0, 0.2, 0.4), Vec3::new(-1.0, 0.0, 0.1)));
+        assert_tangents_match_uvs("cylinder cap", &cylinder, Ray::new(Vec3::new(0.1, 5.0, 0.2), Vec3::new(0.0, -1.0, 0.0)));
+        let cone = Cone::new(base, top, 0.8, white());
+        assert_tangents_match_uvs("cone side", &cone, Ray::new(Vec3::new(5.0, -0.2, 0.3), Vec3::new(-1.0, 0.0, -0.05)));
+        assert_tangents_match_uvs("cone base", &cone, Ray::new(Vec3::new(0.1, -5.0, 0.2), Vec3::new(0.0, 1.0, 0.0)));
+        let torus = Torus::new(Vec3::zero(), Vec3::new(0.2, 0.1, 1.0), 1.0, 0.4, white());
+        assert_tangents_match_uvs("torus outside", &torus, Ray::new(Vec3::new(5.0, 0.3, 0.1), Vec3::new(-1.0, 0.0, 0.0)));
+        assert_tangents_match_uvs("torus top", &torus, Ray::new(Vec3::new(0.7, 0.6, 5.0), Vec3::new(0.0, 0.0, -1.0)));
+    }
+}
@@ -40,7 +40,7 @@ impl HittableList {
 
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &crate::ray::Ray, ray_t: Interval, rec: &mut HitRecord) -> bool{
+    fn hit<'a>(&'a self, ray: &crate::ray::Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool{
         let mut rec_temp=HitRecord::new();
         let mut hit_anything=false;
         let mut closest_so_far=ray_t.max;
@@ -59,7 +59,14 @@ impl Hittable for HittableList {
             rec.v = rec_temp.v;
             rec.p = rec_temp.p;
             rec.normal = rec_temp.normal;
+            rec.geometric_normal = rec_temp.geometric_normal;
             rec.front_face = rec_temp.front_face;
+            rec.tangent = rec_temp.tangent;
+            rec.dpdu = rec_temp.dpdu;
+            rec.dpdv = rec_temp.dpdv;
+            rec.color = rec_temp.color;
+            rec.duv_dx = rec_temp.duv_dx;
+            rec.duv_dy = rec_temp.duv_dy;
             rec.material = rec_temp.material;
         }
         return hit_anything;
@@ -67,5 +74,8 @@ impl Hittable for HittableList {
     fn bounding_box(&self) -> Option<Aabb> {
         return self.bbox;
     }
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.objects.iter().any(|object| object.occluded(ray, ray_t))
+    }
 
 }
\ No newline at end of file
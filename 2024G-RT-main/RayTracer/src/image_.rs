@@ -1,5 +1,8 @@
-use image::{DynamicImage, GenericImageView,  Pixel};
+use image::codecs::hdr::HdrDecoder;
+use image::{DynamicImage, Rgb32FImage};
 use std::env;
+use std::fs::File;
+use std::io::BufReader;
 use std::path::Path;
 
 #[derive(Clone)]
@@ -18,6 +21,11 @@ impl RtwImage {
         image
     }
 
+    /// Wraps an image that is already decoded, e.g. one embedded in a glTF file.
+    pub(crate) fn from_image(image: DynamicImage) -> Self {
+        RtwImage { data: Some(image) }
+    }
+
     fn load_image(&self, image_filename: &str) -> Option<DynamicImage> {
         let search_paths = [
             "", "images/", "../images/", "../../images/",
@@ -34,13 +42,27 @@ impl RtwImage {
             };
 
             if Path::new(&path).exists() {
-                return image::open(&path).ok();
+                return Self::open(&path);
             }
         }
 
         None
     }
 
+    /// `image::open` tone maps Radiance `.hdr` files down to 8 bits, those are read as
+    /// floats here. OpenEXR files already come out as floats.
+    fn open(path: &str) -> Option<DynamicImage> {
+        let is_hdr = Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+        if !is_hdr {
+            return image::open(path).ok();
+        }
+        let decoder = HdrDecoder::new(BufReader::new(File::open(path).ok()?)).ok()?;
+        let meta = decoder.metadata();
+        let pixels = decoder.read_image_hdr().ok()?;
+        let image = Rgb32FImage::from_raw(meta.width, meta.height, pixels.iter().flat_map(|pixel| pixel.0).collect())?;
+        Some(DynamicImage::ImageRgb32F(image))
+    }
+
     pub(crate) fn width(&self) -> u32 {
         self.data.as_ref().map_or(0, |img| img.width())
     }
@@ -49,12 +71,32 @@ impl RtwImage {
         self.data.as_ref().map_or(0, |img| img.height())
     }
 
-    pub(crate) fn pixel_data(&self, x: u32, y: u32) -> [u8; 3] {
-        if let Some(ref img) = self.data {
-            let pixel = img.get_pixel(x, y).to_rgb();
-            return pixel.0;
+    /// Whether the file holds floating-point values, as `.hdr` and `.exr` files do. Those
+    /// are linear light and can go past one.
+    pub(crate) fn is_float(&self) -> bool {
+        matches!(self.data, Some(DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)))
+    }
+
+    /// Every pixel row by row from the top, 8 and 16-bit images scaled to [0, 1] and
+    /// float images as they are. Alpha is dropped.
+    pub(crate) fn rgb_f32(&self) -> Vec<[f32; 3]> {
+        match self.data {
+            Some(ref img) => img.to_rgb32f().pixels().map(|pixel| pixel.0).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub(crate) fn has_alpha(&self) -> bool {
+        self.data.as_ref().is_some_and(|img| img.color().has_alpha())
+    }
+
+    /// The alpha channel of every pixel in the order of `rgb_f32`, repeated into all three
+    /// channels. Images without one are opaque.
+    pub(crate) fn alpha_f32(&self) -> Vec<[f32; 3]> {
+        match self.data {
+            Some(ref img) => img.to_rgba32f().pixels().map(|pixel| [pixel.0[3]; 3]).collect(),
+            None => Vec::new(),
         }
-        [255, 0, 255]
     }
 }
 
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use crate::AABB::Aabb;
+use crate::bvh::BvhOptions;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::linear_bvh::LinearBvh;
+use crate::mat4::Mat4;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::transform::Transform;
+
+/// One copy of a shared bottom-level BVH, placed by a matrix and optionally drawn with
+/// a material of its own. A copy costs a transform and a box, never the geometry.
+pub struct Instance {
+    transform: Transform,
+    material: Option<Arc<dyn Material + Sync + Send>>,
+}
+
+impl Instance {
+    /// Panics if `matrix` is not invertible, like `Transform`.
+    pub fn new(blas: Arc<dyn Hittable + Sync + Send>, matrix: Mat4) -> Self {
+        Self {
+            transform: Transform::new(blas, matrix),
+            material: None,
+        }
+    }
+
+    /// Draw every surface of this copy with `material` instead of its own. The `Arc`
+    /// can be shared by any number of instances.
+    pub fn with_material(mut self, material: Arc<dyn Material + Sync + Send>) -> Self {
+        self.material = Some(material);
+        self
+    }
+}
+
+impl Hittable for Instance {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        if !self.transform.hit(ray, ray_t, rec) {
+            return false;
+        }
+        if let Some(material) = &self.material {
+            rec.material = material.as_ref();
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.transform.bounding_box()
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.transform.occluded(ray, ray_t)
+    }
+}
+
+/// The top level of a two-level acceleration structure: a BVH over instances, each of
+/// which points into a shared bottom-level BVH.
+pub struct Tlas {
+    bvh: LinearBvh,
+}
+
+impl Tlas {
+    pub fn new(instances: Vec<Instance>) -> Self {
+        let mut list = HittableList::new();
+        for instance in instances {
+            list.add(Arc::new(instance));
+        }
+        // entering an instance means transforming the ray and walking another BVH,
+        // so every instance gets a leaf of its own
+        let options = BvhOptions { max_leaf_size: 1, ..BvhOptions::default() };
+        Self {
+            bvh: LinearBvh::with_options(list, options),
+        }
+    }
+}
+
+impl Hittable for Tlas {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.bvh.hit(ray, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.bvh.occluded(ray, ray_t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn instances_share_one_blas() {
+        let blas: Arc<dyn Hittable + Sync + Send> = Arc::new(Sphere::new(Vec3::zero(), 1.0, Lambertian::new(Vec3::ones())));
+        let light: Arc<dyn Material + Sync + Send> = Arc::new(DiffuseLight::set_color(Vec3::ones()));
+        let tlas = Tlas::new(vec![
+            Instance::new(blas.clone(), Mat4::translate(Vec3::new(0.0, 0.0, -5.0))),
+            Instance::new(blas.clone(), Mat4::translate(Vec3::new(0.0, 0.0, -10.0)) * Mat4::scale(Vec3::new(2.0, 2.0, 2.0)))
+                .with_material(light),
+        ]);
+        assert_eq!(Arc::strong_count(&blas), 3);
+
+        let p = Vec3::zero();
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        assert!(tlas.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert_eq!(rec.material.emitted(0.0, 0.0, &p), Vec3::zero());
+
+        // past the first copy the scaled one is found, with its own material
+        let ray = Ray::new(Vec3::new(0.0, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(tlas.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.p.z - (-10.0 + 1.75f64.sqrt())).abs() < 1e-9);
+        assert_eq!(rec.material.emitted(0.0, 0.0, &p), Vec3::ones());
+    }
+}
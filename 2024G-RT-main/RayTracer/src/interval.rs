@@ -40,6 +40,11 @@ impl Interval {
     pub fn intersect(lhs:&Interval,rhs: &Interval) -> Interval {
         Interval { min: lhs.min.min(rhs.min), max: lhs.max.max(rhs.max) }
     }
+    /// The part both intervals cover, empty (`min > max`) if they are disjoint.
+    /// Unlike `intersect`, which returns the interval spanning both.
+    pub fn overlap(lhs:&Interval,rhs:&Interval) -> Interval {
+        Interval { min: lhs.min.max(rhs.min), max: lhs.max.min(rhs.max) }
+    }
     pub fn clamp(&self,x:f64)->f64{
         if x<self.min{
             self.min
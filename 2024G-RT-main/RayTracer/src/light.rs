@@ -0,0 +1,182 @@
+use crate::vec3::Vec3;
+
+/// Incoming light at a shading point from one delta light.
+pub struct LightSample {
+    /// Unit direction from the shading point towards the light.
+    pub wi: Vec3,
+    /// Distance to the light, `f64::INFINITY` for directional lights.
+    pub distance: f64,
+    /// Radiance arriving along `wi`, already divided by the squared distance.
+    pub radiance: Vec3,
+}
+
+/// Lights that have no surface, so a path can never hit them by chance.
+/// They are only reached through shadow rays fired by `Camera::ray_color`.
+pub trait Light {
+    fn sample(&self, p: Vec3) -> Option<LightSample>;
+}
+
+#[derive(Clone, Copy)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub intensity: Vec3,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> Self {
+        Self { position, intensity }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let dist2 = to_light.squared_length();
+        if dist2 == 0.0 {
+            return None;
+        }
+        let distance = dist2.sqrt();
+        Some(LightSample {
+            wi: to_light / distance,
+            distance,
+            radiance: self.intensity / dist2,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub intensity: Vec3,
+    pub cos_total_width: f64,
+    pub cos_falloff_start: f64,
+}
+
+impl SpotLight {
+    /// `total_width` is the half angle of the cone in degrees, light fades out smoothly
+    /// between `falloff_start` and `total_width`.
+    pub fn new(position: Vec3, lookat: Vec3, intensity: Vec3, total_width: f64, falloff_start: f64) -> Self {
+        let falloff_start = falloff_start.min(total_width);
+        Self {
+            position,
+            direction: (lookat - position).unit(),
+            intensity,
+            cos_total_width: total_width.to_radians().cos(),
+            cos_falloff_start: falloff_start.to_radians().cos(),
+        }
+    }
+
+    fn falloff(&self, w: Vec3) -> f64 {
+        let cos_theta = w * self.direction;
+        if cos_theta < self.cos_total_width {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        let delta = (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        delta * delta * (3.0 - 2.0 * delta)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let dist2 = to_light.squared_length();
+        if dist2 == 0.0 {
+            return None;
+        }
+        let distance = dist2.sqrt();
+        let wi = to_light / distance;
+        let falloff = self.falloff(-wi);
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            distance,
+            radiance: self.intensity * falloff / dist2,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct DirectionalLight {
+    /// Direction the light travels in, e.g. `(0,-1,0)` for a sun straight overhead.
+    pub direction: Vec3,
+    pub radiance: Vec3,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, radiance: Vec3) -> Self {
+        Self {
+            direction: direction.unit(),
+            radiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: Vec3) -> Option<LightSample> {
+        Some(LightSample {
+            wi: -self.direction,
+            distance: f64::INFINITY,
+            radiance: self.radiance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::assert_near;
+
+    #[test]
+    fn point_light_falls_off_with_the_squared_distance() {
+        let light = PointLight::new(Vec3::new(0.0, 4.0, 0.0), Vec3::new(16.0, 16.0, 16.0));
+        let near = light.sample(Vec3::new(0.0, 2.0, 0.0)).unwrap();
+        assert_near(near.wi, Vec3::new(0.0, 1.0, 0.0), 1e-9);
+        assert!((near.distance - 2.0).abs() < 1e-12);
+        assert_near(near.radiance, Vec3::new(4.0, 4.0, 4.0), 1e-9);
+        let far = light.sample(Vec3::new(3.0, 0.0, 0.0)).unwrap();
+        assert_near(far.wi, Vec3::new(-0.6, 0.8, 0.0), 1e-9);
+        assert!((far.distance - 5.0).abs() < 1e-12);
+        assert_near(far.radiance, Vec3::new(16.0, 16.0, 16.0) / 25.0, 1e-9);
+        assert!(light.sample(light.position).is_none());
+    }
+
+    #[test]
+    fn spot_light_cone_and_falloff() {
+        let light = SpotLight::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::ones(), 30.0, 20.0);
+        // a point on the unit sphere around the light, `degrees` off its axis
+        let at = |degrees: f64| {
+            let a = f64::to_radians(degrees);
+            Vec3::new(a.sin(), 0.0, -a.cos())
+        };
+        let inside = light.sample(at(10.0)).unwrap();
+        assert_near(inside.radiance, Vec3::ones(), 1e-9);
+        assert_near(inside.wi, -at(10.0), 1e-9);
+        assert!((inside.distance - 1.0).abs() < 1e-12);
+
+        let (cos_total, cos_start) = (30f64.to_radians().cos(), 20f64.to_radians().cos());
+        let delta = (25f64.to_radians().cos() - cos_total) / (cos_start - cos_total);
+        let fading = light.sample(at(25.0)).unwrap();
+        assert!((fading.radiance.x - delta * delta * (3.0 - 2.0 * delta)).abs() < 1e-12);
+        assert!(fading.radiance.x > 0.0 && fading.radiance.x < 1.0);
+
+        assert!(light.sample(at(35.0)).is_none());
+        assert!(light.sample(-at(0.0)).is_none());
+    }
+
+    #[test]
+    fn directional_light_comes_from_against_its_direction() {
+        let light = DirectionalLight::new(Vec3::new(0.0, -2.0, 0.0), Vec3::new(3.0, 3.0, 3.0));
+        for p in [Vec3::zero(), Vec3::new(100.0, -5.0, 7.0)] {
+            let sample = light.sample(p).unwrap();
+            assert_near(sample.wi, Vec3::new(0.0, 1.0, 0.0), 1e-9);
+            assert_eq!(sample.distance, f64::INFINITY);
+            assert_near(sample.radiance, Vec3::new(3.0, 3.0, 3.0), 1e-9);
+        }
+    }
+}
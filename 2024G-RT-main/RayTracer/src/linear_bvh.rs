@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use crate::AABB::Aabb;
+use crate::bvh::{BvhNode, BvhOptions, SplitMethod, PARALLEL_BUILD_THRESHOLD};
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Below this depth SAH gives way to median splits, which keeps the tree shallow enough
+/// for the fixed traversal stack even on degenerate input.
+const MAX_SAH_DEPTH: usize = 64;
+const STACK_SIZE: usize = 128;
+
+#[derive(Clone, Copy)]
+struct LinearNode {
+    bbox: Aabb,
+    /// Leaf: index of the first object. Interior: index of the second child,
+    /// the first child always directly follows its parent.
+    offset: u32,
+    /// Number of objects in a leaf, 0 for interior nodes.
+    count: u16,
+    /// Split axis of an interior node.
+    axis: u8,
+}
+
+enum BuildNode {
+    Leaf { bbox: Aabb, start: usize, count: usize },
+    Interior { bbox: Aabb, axis: usize, children: Box<(BuildNode, BuildNode)> },
+}
+
+/// A BVH stored as one depth-first array of nodes, with up to `max_leaf_size`
+/// objects per leaf. Traversal is iterative and visits the nearer child first.
+pub struct LinearBvh {
+    nodes: Vec<LinearNode>,
+    objects: Vec<Arc<dyn Hittable + Sync + Send>>,
+}
+
+impl LinearBvh {
+    pub fn set(list: HittableList) -> LinearBvh {
+        LinearBvh::with_options(list, BvhOptions::default())
+    }
+
+    pub fn with_options(list: HittableList, options: BvhOptions) -> LinearBvh {
+        let mut objects = list.objects;
+        assert!(!objects.is_empty(), "LinearBvh needs at least one object");
+        let options = BvhOptions {
+            max_leaf_size: options.max_leaf_size.clamp(1, u16::MAX as usize),
+            ..options
+        };
+        let root = LinearBvh::build(&mut objects, 0, 0, &options);
+        let mut nodes = Vec::with_capacity(2 * objects.len());
+        LinearBvh::flatten(&root, &mut nodes);
+        LinearBvh { nodes, objects }
+    }
+
+    fn build(objects: &mut [Arc<dyn Hittable + Sync + Send>], start: usize, depth: usize, options: &BvhOptions) -> BuildNode {
+        let bbox = objects.iter()
+            .map(|object| object.bounding_box().unwrap())
+            .reduce(Aabb::surrounding_box)
+            .unwrap();
+        let count = objects.len();
+        let median_leaf = options.split == SplitMethod::Median && count <= options.max_leaf_size;
+        if count == 1 || median_leaf {
+            return BuildNode::Leaf { bbox, start, count };
+        }
+        let median = BvhOptions {
+            split: SplitMethod::Median,
+            ..*options
+        };
+        let options = if depth >= MAX_SAH_DEPTH { &median } else { options };
+        let (mid, axis) = match BvhNode::split(objects, &bbox, options) {
+            Some(split) => split,
+            None => return BuildNode::Leaf { bbox, start, count },
+        };
+        let (left_objects, right_objects) = objects.split_at_mut(mid);
+        let (left, right) = if count >= PARALLEL_BUILD_THRESHOLD {
+            rayon::join(
+                || LinearBvh::build(left_objects, start, depth + 1, options),
+                || LinearBvh::build(right_objects, start + mid, depth + 1, options),
+            )
+        } else {
+            (
+                LinearBvh::build(left_objects, start, depth + 1, options),
+                LinearBvh::build(right_objects, start + mid, depth + 1, options),
+            )
+        };
+        BuildNode::Interior { bbox, axis, children: Box::new((left, right)) }
+    }
+
+    fn flatten(node: &BuildNode, nodes: &mut Vec<LinearNode>) -> usize {
+        let index = nodes.len();
+        match node {
+            BuildNode::Leaf { bbox, start, count } => {
+                nodes.push(LinearNode {
+                    bbox: *bbox,
+                    offset: *start as u32,
+                    count: *count as u16,
+                    axis: 0,
+                });
+            }
+            BuildNode::Interior { bbox, axis, children } => {
+                nodes.push(LinearNode {
+                    bbox: *bbox,
+                    offset: 0,
+                    count: 0,
+                    axis: *axis as u8,
+                });
+                LinearBvh::flatten(&children.0, nodes);
+                let second = LinearBvh::flatten(&children.1, nodes);
+                nodes[index].offset = second as u32;
+            }
+        }
+        index
+    }
+}
+
+impl Hittable for LinearBvh {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let inv_dir = Vec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        let dir_is_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        let mut closest_so_far = ray_t.max;
+        let mut hit_anything = false;
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit_inv(ray.origin, inv_dir, Interval::set(ray_t.min, closest_so_far)) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for object in &self.objects[start..start + node.count as usize] {
+                        if object.hit(ray, Interval::set(ray_t.min, closest_so_far), rec) {
+                            hit_anything = true;
+                            closest_so_far = rec.t;
+                        }
+                    }
+                } else {
+                    // descend into the child on the ray's side of the split, keep the other for later
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.nodes[0].bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        // any hit ends the search, so the child order does not matter
+        let inv_dir = Vec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit_inv(ray.origin, inv_dir, ray_t) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    if self.objects[start..start + node.count as usize].iter().any(|object| object.occluded(ray, ray_t)) {
+                        return true;
+                    }
+                } else {
+                    stack[stack_len] = node.offset as usize;
+                    stack_len += 1;
+                    current += 1;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                return false;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+    }
+}
@@ -18,6 +18,29 @@ mod obj_read;
 mod triangle;
 mod obj;
 mod mtl;
+mod light;
+mod mat4;
+mod transform;
+mod linear_bvh;
+mod simd_mesh;
+mod triangle_mesh;
+mod gltf_read;
+mod ply_read;
+mod stl_read;
+mod onb;
+mod cylinder;
+mod cone;
+mod torus;
+mod csg;
+mod sdf;
+mod curve;
+mod hair;
+mod subdivision;
+mod instance;
+mod normal_map;
+mod cutout;
+#[cfg(test)]
+mod temp_dir;
 
 
 use std::sync::Arc;
@@ -27,6 +50,24 @@ use crate::hittable::{RotateY, Translate};
 use crate::hittable_list::HittableList;
 use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Metal};
 use crate::texture::Texture;
+use crate::light::{DirectionalLight, PointLight, SpotLight};
+use crate::mat4::Mat4;
+use crate::transform::{AnimatedTransform, Keyframe, Transform};
+use crate::linear_bvh::LinearBvh;
+use crate::simd_mesh::{Lanes, SimdMesh};
+use crate::triangle_mesh::{ImportOptions, TriangleMesh};
+use crate::csg::Csg;
+use crate::sdf::{Sdf, SdfShape};
+use crate::curve::{Curve, CurveKind};
+use crate::hair::HairBsdf;
+use crate::subdivision::{Displacement, Subdivision, SubdivisionScheme};
+use crate::normal_map::{NormalMap, NormalMapped};
+use crate::cutout::Cutout;
+use crate::instance::{Instance, Tlas};
+use wide::{f32x4, f32x8, f64x4};
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use std::time::Instant;
 
 
 const AUTHOR: &str = "name";
@@ -268,7 +309,10 @@ fn cornell_smoke(path:&str){
     camera.render(world, path, quality);
 }
 fn final_scene(path:&str) {
-    let mut boxes1 = HittableList::new();
+    // every ground box is a stretched copy of the same unit box
+    let ground = Lambertian::new(Vec3::new(0.48, 0.83, 0.53));
+    let unit_box: Arc<dyn Hittable + Sync + Send> = Arc::new(quad::quad::bx(Vec3::zero(),Vec3::ones(),ground));
+    let mut boxes1 = Vec::new();
     let boxes_per_side = 20;
     for i in 0..boxes_per_side{
         for j in 0..boxes_per_side{
@@ -279,12 +323,12 @@ fn final_scene(path:&str) {
             let x1=x0+w;
             let y1=camera::random()*100.0+1.0;
             let z1=z0+w;
-            let ground = Lambertian::new(Vec3::new(0.48, 0.83, 0.53));
-            boxes1.add(Arc::new(quad::quad::bx(Vec3::new(x0,y0,z0),Vec3::new(x1,y1,z1),ground)));
+            let matrix = Mat4::translate(Vec3::new(x0,y0,z0))*Mat4::scale(Vec3::new(x1-x0,y1-y0,z1-z0));
+            boxes1.push(Instance::new(unit_box.clone(),matrix));
         }
     }
     let mut world = HittableList::new();
-    world.add(Arc::new(bvh::BvhNode::set(boxes1)));
+    world.add(Arc::new(Tlas::new(boxes1)));
 
     let light = DiffuseLight::set_color(Vec3::new(7.0, 7.0, 7.0));
     world.add(Arc::new(quad::quad::<DiffuseLight>::new(Vec3::new(123.0, 554.0, 147.0), Vec3::new(300.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 265.0), light)));
@@ -319,7 +363,7 @@ fn final_scene(path:&str) {
         let white = Lambertian::new(Vec3::new(0.73, 0.73, 0.73));
         boxes2.add(Arc::new(sphere::Sphere::new(Vec3::random()*165.0,10.0,white)));
     }
-    world.add(Arc::new(Translate::new(RotateY::new(bvh::BvhNode::set(boxes2),15.0),Vec3::new(-100.0,270.0,395.0))));
+    world.add(Arc::new(Translate::new(RotateY::new(LinearBvh::set(boxes2),15.0),Vec3::new(-100.0,270.0,395.0))));
     let vfov=40.0;
     let lookfrom = Vec3::new(478.0, 278.0, -600.0);
     let lookat = Vec3::new(278.0, 278.0,0.0);
@@ -336,7 +380,7 @@ fn final_scene(path:&str) {
 }
 fn obj_test(path:&str){
     let material=material::Metal::new(Vec3::new(	119.0/256.0,136.0/256.0,153.0/256.0),0.4);
-    let mut world = obj_read::load_obj_to_hittable_list("input/TANK.obj",material);
+    let mut world = obj_read::load_obj_to_hittable_list("input/TANK.obj",material,ImportOptions::default()).expect("input/TANK.obj");
     let light = material::DiffuseLight::set_color(Vec3::new(3.0, 3.0, 3.0));
     world.add(Arc::new(quad::quad::<material::DiffuseLight>::new(Vec3::new(0.0, 30.0, 7.0), Vec3::new(0.0, -40.0, 0.0), Vec3::new(20.0, 0.0, -2.0), light)));
     let green = material::Lambertian::new(Vec3::new(0.12, 0.45, 0.15));
@@ -359,6 +403,650 @@ fn obj_test(path:&str){
     camera.render(world, path, quality);
 
 }
+fn delta_lights(path:&str){
+    let mut world = HittableList::new();
+    let checker = Texture::Checker(Box::new(texture::CheckerTexture::color(0.32, Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9))));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-1000.0,0.0),1000.0,Lambertian::set_texture(checker))));
+    let pertext = Texture::Noise(texture::NoiseTexture::new(4.0));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,2.0,0.0),2.0,Lambertian::set_texture(pertext))));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(-4.0,1.0,2.0),1.0,Metal::new(Vec3::new(0.7,0.6,0.5),0.0))));
+    let white = Lambertian::new(Vec3::new(0.73, 0.73, 0.73));
+    world.add(Arc::new(quad::quad::bx(Vec3::new(2.5,0.0,-3.5),Vec3::new(4.5,2.5,-1.5),white)));
+    let vfov=20.0;
+    let lookfrom = Vec3::new(26.0,6.0,6.0);
+    let lookat = Vec3::new(0.0,2.0,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.set_background(Vec3::new(0.02,0.02,0.05));
+    camera.add_light(Arc::new(PointLight::new(Vec3::new(6.0,7.0,6.0),Vec3::new(60.0,60.0,60.0))));
+    camera.add_light(Arc::new(SpotLight::new(Vec3::new(-2.0,9.0,-6.0),Vec3::new(0.0,2.0,0.0),Vec3::new(200.0,120.0,60.0),20.0,15.0)));
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,0.5),Vec3::new(0.2,0.2,0.3))));
+    camera.render(world, path, quality);
+}
+fn cornell_transforms(path:&str){
+    let mut world = HittableList::new();
+    let red = Lambertian::new(Vec3::new(0.65, 0.05, 0.05));
+    let white = Lambertian::new(Vec3::new(0.73, 0.73, 0.73));
+    let green = Lambertian::new(Vec3::new(0.12, 0.45, 0.15));
+    let light = DiffuseLight::set_color(Vec3::new(15.0, 15.0, 15.0));
+    world.add(Arc::new(quad::quad::<Lambertian>::new(Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0,00.0), Vec3::new(0.0, 0.0,555.0), green)));
+    world.add(Arc::new(quad::quad::<Lambertian>::new(Vec3::new(0.0, 0.0, 0.0),Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 555.0),  red)));
+    world.add(Arc::new(quad::quad::<Lambertian>::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 555.0), white.clone())));
+    world.add(Arc::new(quad::quad::<Lambertian>::new(Vec3::new(0.0, 0.0, 555.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0,0.0), white.clone())));
+    world.add(Arc::new(quad::quad::<Lambertian>::new(Vec3::new(555.0, 555.0, 555.0), Vec3::new(-555.0, 0.0, 0.0), Vec3::new(0.0, 0.0,-555.0),white.clone())));
+    world.add(Arc::new(quad::quad::<DiffuseLight>::new(Vec3::new(343.0, 554.8, 332.0), Vec3::new(-130.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -105.0), light)));
+
+    // one unit box shared by every instance below
+    let unit_box: Arc<dyn hittable::Hittable + Sync + Send> = Arc::new(BvhNode::set(quad::quad::bx(Vec3::new(-0.5,-0.5,-0.5),Vec3::new(0.5,0.5,0.5),white)));
+    let tall = Mat4::translate(Vec3::new(370.0,165.0,350.0))*Mat4::rotate(Vec3::new(0.0,1.0,0.0),15.0)*Mat4::scale(Vec3::new(165.0,330.0,165.0));
+    world.add(Arc::new(Transform::new(unit_box.clone(),tall)));
+    let tumbled = Mat4::translate(Vec3::new(180.0,120.0,160.0))*Mat4::rotate(Vec3::new(1.0,0.0,1.0),35.0)*Mat4::scale(Vec3::new(120.0,120.0,120.0));
+    world.add(Arc::new(Transform::new(unit_box.clone(),tumbled)));
+    let sheared = Mat4::translate(Vec3::new(420.0,40.0,120.0))*Mat4::new([
+        [80.0, 40.0, 0.0, 0.0],
+        [0.0, 80.0, 0.0, 0.0],
+        [0.0, 0.0, 80.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+    world.add(Arc::new(Transform::new(unit_box,sheared)));
+    let vfov=40.0;
+    let lookfrom = Vec3::new(278.0, 278.0, -800.0);
+    let lookat = Vec3::new(278.0, 278.0,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(600, 1.0, 200, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.set_background(Vec3::new(0.0,0.0,0.0));
+    camera.render(world, path, quality);
+}
+fn motion_blur(path:&str){
+    let mut world = HittableList::new();
+    let checker = Texture::Checker(Box::new(texture::CheckerTexture::color(0.32, Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9))));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-1000.0,0.0),1000.0,Lambertian::set_texture(checker))));
+
+    let y_axis = Vec3::new(0.0,1.0,0.0);
+    let orange = Lambertian::new(Vec3::new(0.8, 0.4, 0.1));
+    let spinning_box: Arc<dyn hittable::Hittable + Sync + Send> = Arc::new(BvhNode::set(quad::quad::bx(Vec3::new(-1.0,-1.0,-1.0),Vec3::new(1.0,1.0,1.0),orange)));
+    world.add(Arc::new(AnimatedTransform::new(spinning_box,vec![
+        Keyframe::new(0.0,Vec3::new(-2.5,1.0,0.0),y_axis,0.0,Vec3::ones()),
+        Keyframe::new(1.0,Vec3::new(-2.5,1.0,0.0),y_axis,45.0,Vec3::ones()),
+    ])));
+
+    let teal = Lambertian::new(Vec3::new(0.2, 0.7, 0.7));
+    let triangle: Arc<dyn hittable::Hittable + Sync + Send> = Arc::new(triangle::Triangle::new(Vec3::new(-1.0,0.0,0.0),Vec3::new(1.0,0.0,0.0),Vec3::new(0.0,1.7,0.0),teal));
+    world.add(Arc::new(AnimatedTransform::new(triangle,vec![
+        Keyframe::new(0.0,Vec3::new(1.5,0.5,0.0),y_axis,0.0,Vec3::ones()),
+        Keyframe::new(0.5,Vec3::new(2.5,1.0,0.0),Vec3::new(0.0,0.0,1.0),-30.0,Vec3::ones()),
+        Keyframe::new(1.0,Vec3::new(3.5,0.5,0.0),Vec3::new(0.0,0.0,1.0),-60.0,Vec3::new(1.2,1.2,1.2)),
+    ])));
+    let vfov=30.0;
+    let lookfrom = Vec3::new(0.0,3.0,14.0);
+    let lookat = Vec3::new(0.0,1.0,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.set_shutter(0.0,1.0);
+    camera.render(world, path, quality);
+}
+fn textured_mesh(path:&str){
+    let spot_texture = Texture::ImageTex(texture::ImageTexture::new("input/spot.png"));
+    let mut world = obj_read::load_obj_to_hittable_list("input/cow.obj",Lambertian::set_texture(spot_texture),ImportOptions::default()).expect("input/cow.obj");
+    let ground = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-1000.72,0.0),1000.0,ground)));
+    let vfov=30.0;
+    let lookfrom = Vec3::new(3.0,1.0,-1.8);
+    let lookat = Vec3::new(0.0,0.0,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,-1.0),Vec3::new(1.5,1.5,1.5))));
+    camera.render(world, path, quality);
+}
+/// The rifle of sr.obj with the materials of sr.mtl, scaled to 2 units and framed automatically.
+fn obj_materials(path:&str){
+    let options = ImportOptions { recenter: true, target_size: Some(2.0), ..ImportOptions::default() };
+    let mut world = obj_read::load_obj("input/sr.obj",Some("input/sr.mtl"),options).expect("input/sr.obj");
+    let vfov=30.0;
+    let (lookfrom,lookat) = camera::Camera::framing(&world.bbox.unwrap(),Vec3::new(0.2,0.25,1.0),vfov,16.0/9.0);
+    let ground = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+    let floor = world.bbox.unwrap().y.min;
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,floor-1000.0,0.0),1000.0,ground)));
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,-1.0),Vec3::new(1.5,1.5,1.5))));
+    camera.render(world, path, quality);
+}
+/// input/scene.gltf seen through its own camera and lights.
+fn gltf_scene(path:&str){
+    let scene = gltf_read::load_gltf("input/scene.gltf").expect("input/scene.gltf");
+    let mut camera = scene.cameras[0].camera(400, 100);
+    camera.set_background(Vec3::new(0.1,0.12,0.15));
+    for light in scene.lights{
+        camera.add_light(light);
+    }
+    let quality = 100;
+    camera.render(scene.world, path, quality);
+}
+/// A vertex colored PLY cow next to an STL torus.
+fn ply_stl_meshes(path:&str){
+    let mut world = ply_read::load_ply_to_hittable_list("input/cow.ply",Lambertian::set_texture(Texture::VertexColor),ImportOptions::default()).expect("input/cow.ply");
+    let torus = stl_read::load_stl_to_hittable_list("input/torus.stl",Metal::new(Vec3::new(0.8,0.8,0.85),0.05),ImportOptions::default()).expect("input/torus.stl");
+    world.add(Arc::new(Translate::new(torus,Vec3::new(0.0,-0.5,-1.4))));
+    let ground = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-1000.72,0.0),1000.0,ground)));
+    let vfov=30.0;
+    let lookfrom = Vec3::new(3.5,1.5,-2.5);
+    let lookat = Vec3::new(0.0,0.0,-0.5);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,-1.0),Vec3::new(1.5,1.5,1.5))));
+    camera.render(world, path, quality);
+}
+/// Every analytic primitive next to each other: the plane shapes in the back, the round solids in front.
+fn analytic_shapes(path:&str){
+    let mut world = HittableList::new();
+    let ground = Lambertian::set_texture(Texture::color(0.5,Vec3::new(0.2,0.3,0.1),Vec3::new(0.9,0.9,0.9)));
+    world.add(Arc::new(quad::quad::<Lambertian>::disk(Vec3::new(0.0,0.0,0.0),Vec3::new(0.0,1.0,0.0),8.0,ground)));
+    let red = Lambertian::new(Vec3::new(0.8,0.2,0.2));
+    let green = Lambertian::new(Vec3::new(0.2,0.7,0.3));
+    let blue = Lambertian::new(Vec3::new(0.2,0.3,0.8));
+    world.add(Arc::new(quad::quad::<Lambertian>::triangle(Vec3::new(-4.5,0.0,-3.0),Vec3::new(2.5,0.0,0.0),Vec3::new(1.25,2.5,0.0),red)));
+    world.add(Arc::new(quad::quad::<Lambertian>::ellipse(Vec3::new(0.0,1.3,-3.0),Vec3::new(1.3,0.0,0.0),Vec3::new(0.0,1.0,0.0),green)));
+    world.add(Arc::new(quad::quad::<Lambertian>::annulus(Vec3::new(3.3,1.3,-3.0),Vec3::new(1.2,0.0,0.0),Vec3::new(0.0,1.2,0.0),0.5,blue)));
+
+    let orange = Lambertian::new(Vec3::new(0.9,0.5,0.1));
+    world.add(Arc::new(cylinder::Cylinder::new(Vec3::new(-3.0,0.0,0.5),Vec3::new(-3.0,1.8,0.5),0.8,orange)));
+    let glass = Dielectric::new(1.5);
+    world.add(Arc::new(cone::Cone::new(Vec3::new(0.0,0.0,0.8),Vec3::new(0.0,2.2,0.8),0.9,glass)));
+    let gold = Metal::new(Vec3::new(0.9,0.75,0.4),0.1);
+    world.add(Arc::new(torus::Torus::new(Vec3::new(3.0,0.9,0.5),Vec3::new(0.0,0.6,1.0),0.8,0.3,gold)));
+    let vfov=35.0;
+    let lookfrom = Vec3::new(0.0,5.0,12.0);
+    let lookat = Vec3::new(0.0,1.0,-0.5);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,-1.0),Vec3::new(1.5,1.5,1.5))));
+    camera.render(world, path, quality);
+}
+/// A glass lens, a drilled block and a rounded die with its pips cut out, all built with CSG.
+fn csg_shapes(path:&str){
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3::new(0.5,0.5,0.5));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-1000.0,0.0),1000.0,ground)));
+
+    // two overlapping spheres make a biconvex lens
+    let glass = Dielectric::new(1.5);
+    let lens = Csg::intersection(
+        sphere::Sphere::new(Vec3::new(-3.0,1.2,-2.2),2.5,glass),
+        sphere::Sphere::new(Vec3::new(-3.0,1.2,2.2),2.5,glass),
+    );
+    world.add(Arc::new(lens));
+
+    let steel = Metal::new(Vec3::new(0.7,0.7,0.75),0.2);
+    let block = quad::quad::bx(Vec3::new(-0.9,0.0,-0.9),Vec3::new(0.9,1.8,0.9),steel.clone());
+    let drill_z = cylinder::Cylinder::new(Vec3::new(0.0,0.9,-1.5),Vec3::new(0.0,0.9,1.5),0.5,steel.clone());
+    let drill_x = cylinder::Cylinder::new(Vec3::new(-1.5,0.9,0.0),Vec3::new(1.5,0.9,0.0),0.5,steel);
+    world.add(Arc::new(Csg::difference(block,Csg::union(drill_z,drill_x))));
+
+    // a cube with its edges rounded off by a sphere
+    let ivory = Lambertian::new(Vec3::new(0.9,0.85,0.75));
+    let cube = quad::quad::bx(Vec3::new(2.2,0.0,-0.8),Vec3::new(3.8,1.6,0.8),ivory.clone());
+    let die = Csg::intersection(cube,sphere::Sphere::new(Vec3::new(3.0,0.8,0.0),1.1,ivory.clone()));
+    let mut pips = HittableList::new();
+    for (y,x) in [(0.4,-0.4),(0.8,0.0),(1.2,0.4)]{
+        pips.add(Arc::new(sphere::Sphere::new(Vec3::new(3.0+x,y,0.85),0.15,ivory.clone())));
+    }
+    world.add(Arc::new(Csg::difference(die,pips)));
+
+    let vfov=30.0;
+    let lookfrom = Vec3::new(1.0,4.0,10.0);
+    let lookat = Vec3::new(0.0,0.9,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,-1.0),Vec3::new(1.5,1.5,1.5))));
+    camera.render(world, path, quality);
+}
+/// Shapes only a distance field can make: a blob of blended spheres, a twisted bar,
+/// a grid of rounded cubes and a capsule melted into a torus.
+fn sdf_shapes(path:&str){
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3::new(0.5,0.5,0.5));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-1000.0,0.0),1000.0,ground)));
+
+    let blob = Sdf::sphere(0.7)
+        .smooth_union(Sdf::sphere(0.45).translate(Vec3::new(0.6,0.5,0.1)),0.4)
+        .smooth_union(Sdf::sphere(0.4).translate(Vec3::new(-0.5,0.6,-0.2)),0.4)
+        .smooth_union(Sdf::sphere(0.3).translate(Vec3::new(0.1,1.0,0.3)),0.3)
+        .smooth_difference(Sdf::sphere(0.35).translate(Vec3::new(0.0,0.2,0.75)),0.15);
+    world.add(Arc::new(SdfShape::new(blob,Vec3::new(-3.0,0.7,0.0),Lambertian::new(Vec3::new(0.8,0.3,0.3)))));
+
+    let bar = Sdf::cuboid(Vec3::new(0.5,1.55,0.5)).round(0.07).twist(0.85).scale(0.7);
+    world.add(Arc::new(SdfShape::new(bar,Vec3::new(-1.0,1.15,0.0),Metal::new(Vec3::new(0.8,0.8,0.85),0.1))));
+
+    let grid = Sdf::cuboid(Vec3::new(0.2,0.2,0.2)).smooth_intersection(Sdf::sphere(0.26),0.05).repeat(Vec3::new(0.5,0.5,0.5),[1,1,1]);
+    world.add(Arc::new(SdfShape::new(grid,Vec3::new(1.0,0.7,0.0),Lambertian::new(Vec3::new(0.3,0.5,0.8)))));
+
+    let ring = Sdf::torus(0.6,0.15)
+        .smooth_union(Sdf::capsule(Vec3::new(0.0,-0.7,0.0),Vec3::new(0.0,0.7,0.0),0.15),0.3)
+        .union(Sdf::sphere(0.25))
+        .difference(Sdf::cylinder(0.08,1.0))
+        .intersection(Sdf::cylinder(1.0,0.7));
+    world.add(Arc::new(SdfShape::new(ring,Vec3::new(3.0,0.7,0.0),Lambertian::new(Vec3::new(0.9,0.7,0.2)))));
+
+    let vfov=30.0;
+    let lookfrom = Vec3::new(0.0,4.0,11.0);
+    let lookat = Vec3::new(0.0,0.8,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,-1.0),Vec3::new(1.5,1.5,1.5))));
+    camera.render(world, path, quality);
+}
+/// A furry ball with hair BSDF strands, a patch of ribbon grass and a tube bent into an arch.
+fn curves(path:&str){
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3::new(0.45,0.4,0.35));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-1000.0,0.0),1000.0,ground)));
+
+    let center = Vec3::new(-1.3,1.0,0.0);
+    let radius = 0.8;
+    world.add(Arc::new(sphere::Sphere::new(center,radius,Lambertian::new(Vec3::new(0.25,0.15,0.08)))));
+    let hair = HairBsdf::from_melanin(1.3,0.2,0.3,0.3);
+    let dyed = HairBsdf::from_color(Vec3::new(0.2,0.45,0.8),0.3,0.3);
+    let mut strands = HittableList::new();
+    for _ in 0..4000{
+        let n = Vec3::random_unit_vector();
+        let root = center + n*radius;
+        // strands droop a little more the further out they get
+        let droop = Vec3::new(0.0,-0.12,0.0);
+        let cp = [root, root + n*0.12, root + n*0.24 + droop*0.4, root + n*0.34 + droop];
+        // a dyed crown on top
+        if n.y > 0.75 {
+            strands.add(Arc::new(Curve::new(cp,0.012,0.002,CurveKind::Flat,dyed.clone())));
+        } else {
+            strands.add(Arc::new(Curve::new(cp,0.012,0.002,CurveKind::Flat,hair.clone())));
+        }
+    }
+    world.add(Arc::new(BvhNode::set(strands)));
+
+    let green = Lambertian::new(Vec3::new(0.25,0.6,0.15));
+    let mut grass = HittableList::new();
+    for _ in 0..600{
+        let base = Vec3::new(0.4+1.6*camera::random(),0.0,-0.8+1.6*camera::random());
+        let lean = Vec3::new(camera::random()-0.5,0.0,camera::random()-0.5)*0.5;
+        let height = 0.5+0.4*camera::random();
+        let points: Vec<Vec3> = (0..6).map(|i| {
+            let s = i as f64/5.0;
+            base + Vec3::new(0.0,height*s,0.0) + lean*(s*s)
+        }).collect();
+        // blades face a random way around the vertical
+        let angle = 2.0*std::f64::consts::PI*camera::random();
+        let normal = Vec3::new(angle.cos(),0.0,angle.sin());
+        let blade = Curve::b_spline(&points,0.05,0.0,CurveKind::Ribbon(normal,normal),green.clone());
+        for segment in blade.objects{
+            grass.add(segment);
+        }
+    }
+    world.add(Arc::new(BvhNode::set(grass)));
+
+    let copper = Metal::new(Vec3::new(0.85,0.5,0.3),0.15);
+    let arch = [Vec3::new(2.6,0.0,0.8),Vec3::new(2.6,1.9,0.5),Vec3::new(2.6,1.9,-0.5),Vec3::new(2.6,0.0,-0.8)];
+    world.add(Arc::new(Curve::new(arch,0.15,0.15,CurveKind::Cylinder,copper)));
+
+    let vfov=30.0;
+    let lookfrom = Vec3::new(0.5,3.0,9.0);
+    let lookat = Vec3::new(0.4,0.8,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,-1.0),Vec3::new(1.5,1.5,1.5))));
+    camera.render(world, path, quality);
+}
+/// Spot's control cage as stored, smoothed by Catmull-Clark, and smoothed by Loop with
+/// the height map pushed out along the normals.
+fn subdivision_surfaces(path:&str){
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-1000.72,0.0),1000.0,ground)));
+
+    let spot = || Lambertian::set_texture(Texture::ImageTex(texture::ImageTexture::new("input/spot.png")));
+    let cage = obj_read::load_obj_mesh("input/spot_control_mesh.obj",spot(),ImportOptions::default()).expect("input/spot_control_mesh.obj");
+    let catmull_clark = ImportOptions { subdivision: Some(Subdivision { scheme: SubdivisionScheme::CatmullClark, levels: 3 }), ..ImportOptions::default() };
+    let smooth = obj_read::load_obj_mesh("input/spot_control_mesh.obj",spot(),catmull_clark).expect("input/spot_control_mesh.obj");
+    let looped = ImportOptions { subdivision: Some(Subdivision { scheme: SubdivisionScheme::Loop, levels: 4 }), ..ImportOptions::default() };
+    let height = Displacement::new(Texture::ImageTex(texture::ImageTexture::data("input/hmap.jpg")),0.06);
+    let bumpy = obj_read::load_obj_mesh("input/spot_control_mesh.obj",spot(),looped).expect("input/spot_control_mesh.obj").displaced(&height);
+    for (mesh,offset) in [(cage,-1.6),(smooth,0.0),(bumpy,1.6)]{
+        let bvh = LinearBvh::set(Arc::new(mesh).triangles());
+        world.add(Arc::new(Translate::new(bvh,Vec3::new(0.0,0.0,offset))));
+    }
+
+    let vfov=30.0;
+    let lookfrom = Vec3::new(7.0,2.0,-1.0);
+    let lookat = Vec3::new(0.0,0.1,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,-1.0),Vec3::new(1.5,1.5,1.5))));
+    camera.render(world, path, quality);
+}
+/// Thousands of trees and deer, each kind stored once and placed by instances in a
+/// two-level BVH. The crowns and the deer take one of a few shared materials.
+fn forest(path:&str){
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3::new(0.35,0.3,0.2));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-10000.0,0.0),10000.0,ground)));
+
+    // a tree of unit height with its foot at the origin, trunk and crown apart so the crown can be recolored
+    let bark = Lambertian::new(Vec3::new(0.3,0.2,0.12));
+    let trunk: Arc<dyn Hittable + Sync + Send> = Arc::new(cylinder::Cylinder::new(Vec3::zero(),Vec3::new(0.0,0.35,0.0),0.04,bark));
+    let needles = Lambertian::new(Vec3::new(0.1,0.35,0.12));
+    let mut tiers = HittableList::new();
+    tiers.add(Arc::new(cone::Cone::new(Vec3::new(0.0,0.2,0.0),Vec3::new(0.0,0.75,0.0),0.3,needles.clone())));
+    tiers.add(Arc::new(cone::Cone::new(Vec3::new(0.0,0.5,0.0),Vec3::new(0.0,1.0,0.0),0.22,needles)));
+    let crown: Arc<dyn Hittable + Sync + Send> = Arc::new(LinearBvh::set(tiers));
+    let greens: Vec<Arc<dyn material::Material + Sync + Send>> = [Vec3::new(0.08,0.3,0.1),Vec3::new(0.15,0.4,0.1),Vec3::new(0.1,0.25,0.18)]
+        .into_iter()
+        .map(|c| Arc::new(Lambertian::new(c)) as Arc<dyn material::Material + Sync + Send>)
+        .collect();
+
+    let options = ImportOptions { recenter: true, target_size: Some(1.2), ..ImportOptions::default() };
+    let deer = obj_read::load_obj_to_hittable_list("input/deer.obj",Lambertian::new(Vec3::new(0.5,0.35,0.2)),options).expect("input/deer.obj");
+    let feet = deer.bbox.unwrap().y.min;
+    let deer: Arc<dyn Hittable + Sync + Send> = Arc::new(deer);
+    let coats: Vec<Arc<dyn material::Material + Sync + Send>> = [Vec3::new(0.45,0.3,0.15),Vec3::new(0.6,0.45,0.3),Vec3::new(0.85,0.85,0.8)]
+        .into_iter()
+        .map(|c| Arc::new(Lambertian::new(c)) as Arc<dyn material::Material + Sync + Send>)
+        .collect();
+
+    let mut instances = Vec::new();
+    let yaw = || Mat4::rotate(Vec3::new(0.0,1.0,0.0),360.0*camera::random());
+    for _ in 0..3000{
+        let at = Vec3::new(120.0*camera::random()-60.0,0.0,80.0*camera::random()-65.0);
+        let height = 3.0+3.0*camera::random();
+        let matrix = Mat4::translate(at)*yaw()*Mat4::scale(Vec3::new(height,height,height));
+        let green = greens[(camera::random()*greens.len() as f64) as usize % greens.len()].clone();
+        instances.push(Instance::new(trunk.clone(),matrix));
+        instances.push(Instance::new(crown.clone(),matrix).with_material(green));
+    }
+    for _ in 0..1000{
+        let at = Vec3::new(80.0*camera::random()-40.0,0.0,60.0*camera::random()-40.0);
+        let size = 0.8+0.4*camera::random();
+        let matrix = Mat4::translate(at)*yaw()*Mat4::scale(Vec3::new(size,size,size))*Mat4::translate(Vec3::new(0.0,-feet,0.0));
+        let coat = coats[(camera::random()*coats.len() as f64) as usize % coats.len()].clone();
+        instances.push(Instance::new(deer.clone(),matrix).with_material(coat));
+    }
+    world.add(Arc::new(Tlas::new(instances)));
+
+    let vfov=35.0;
+    let lookfrom = Vec3::new(0.0,2.5,30.0);
+    let lookat = Vec3::new(0.0,1.5,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-2.0,-1.0),Vec3::new(1.5,1.5,1.5))));
+    camera.render(world, path, quality);
+}
+/// A tiled checkerboard running off to the horizon, looked up texel by texel on the left
+/// and mipmapped on the right, with a row of ever smaller earths above it.
+fn texture_filtering(path:&str){
+    let mut world = HittableList::new();
+    let squares = image::RgbImage::from_fn(64,64,|x,y| if (x/8+y/8)%2==0 { image::Rgb([230,230,230]) } else { image::Rgb([40,40,120]) });
+    let checker = texture::ImageTexture::from_image(image::DynamicImage::ImageRgb8(squares),texture::ColorSpace::Srgb);
+    let filters = [(texture::TextureFilter::Nearest,-20.0),(texture::TextureFilter::Trilinear,0.0)];
+    for (filter,x0) in filters{
+        let tiles = Lambertian::set_texture(Texture::ImageTex(checker.clone().with_filter(filter)));
+        let positions = vec![Vec3::new(x0,0.0,5.0),Vec3::new(x0+20.0,0.0,5.0),Vec3::new(x0+20.0,0.0,-200.0),Vec3::new(x0,0.0,-200.0)];
+        let uvs = vec![(0.0,0.0),(4.0,0.0),(4.0,41.0),(0.0,41.0)];
+        let plane = Arc::new(TriangleMesh::new(positions,Vec::new(),uvs,vec![[0,1,2],[0,2,3]],tiles));
+        for triangle in plane.triangles().objects{
+            world.add(triangle);
+        }
+    }
+    let earth = Lambertian::set_texture(Texture::ImageTex(texture::ImageTexture::new("input/earthmap.png")));
+    for i in 0..6{
+        let distance = 4.0*2f64.powi(i);
+        world.add(Arc::new(sphere::Sphere::new(Vec3::new(-2.5+0.8*i as f64,1.5,-distance),0.6,earth.clone())));
+    }
+
+    let vfov=40.0;
+    let lookfrom = Vec3::new(0.0,1.5,5.0);
+    let lookat = Vec3::new(0.0,0.8,-10.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    // few samples, so what the filtering does is not hidden by supersampling
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 4, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.set_background(Vec3::new(0.7,0.8,1.0));
+    camera.render(world, path, quality);
+}
+/// Surface detail without extra geometry: the cobblestones of hmap.jpg as a normal map on
+/// the floor and a sphere, the earth bumped by its own brightness, and the cow bumped with
+/// the same image read as heights like the bump shader of Games101.
+fn normal_mapping(path:&str){
+    let mut world = HittableList::new();
+    let cobbles = || NormalMap::Tangent { normals: Texture::ImageTex(texture::ImageTexture::data("input/hmap.jpg")), scale: 1.0 };
+    let stone = NormalMapped::new(Lambertian::new(Vec3::new(0.55,0.5,0.45)),cobbles());
+    world.add(Arc::new(quad::quad::new(Vec3::new(-4.0,0.0,4.0),Vec3::new(8.0,0.0,0.0),Vec3::new(0.0,0.0,-8.0),stone)));
+    let clay = NormalMapped::new(Lambertian::new(Vec3::new(0.8,0.4,0.2)),cobbles());
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(-1.9,0.7,0.0),0.7,clay)));
+
+    let relief = NormalMap::Bump { height: Texture::ImageTex(texture::ImageTexture::data("input/earthmap.png")), scale: 0.5 };
+    let earth = NormalMapped::new(Lambertian::set_texture(Texture::ImageTex(texture::ImageTexture::new("input/earthmap.png"))),relief);
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,0.7,0.0),0.7,earth)));
+
+    let heights = NormalMap::Bump { height: Texture::ImageTex(texture::ImageTexture::data("input/hmap.jpg")), scale: 0.02 };
+    let spot = NormalMapped::new(Lambertian::set_texture(Texture::ImageTex(texture::ImageTexture::new("input/spot.png"))),heights);
+    let cow = obj_read::load_obj_to_hittable_list("input/cow.obj",spot,ImportOptions::default()).expect("input/cow.obj");
+    world.add(Arc::new(Translate::new(LinearBvh::set(cow),Vec3::new(1.9,0.72,0.0))));
+
+    let vfov=30.0;
+    let lookfrom = Vec3::new(0.0,2.5,7.0);
+    let lookat = Vec3::new(0.0,0.5,0.0);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.set_background(Vec3::new(0.15,0.17,0.2));
+    // a low light, so the detail casts long shading
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-0.6,-0.4),Vec3::new(2.0,2.0,2.0))));
+    camera.render(world, path, quality);
+}
+/// A bush of leaf cards cut out by the alpha of a generated leaf, in front of a wall with
+/// a splash of paint whose soft edge lets a share of the rays through to the wall.
+fn cutouts(path:&str){
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3::new(0.45,0.4,0.35));
+    world.add(Arc::new(sphere::Sphere::new(Vec3::new(0.0,-1000.0,0.0),1000.0,ground)));
+
+    // a leaf growing up the texture with a pale vein, transparent around it
+    let leaf = image::RgbaImage::from_fn(64,64,|x,y|{
+        let s = (x as f64+0.5)/32.0-1.0;
+        let t = 1.0-(y as f64+0.5)/64.0;
+        let inside = s.abs() < 0.8*(std::f64::consts::PI*t).sin();
+        let green = if s.abs() < 0.06 { 170.0 } else { 80.0+60.0*t };
+        image::Rgba([(green*0.45) as u8,green as u8,(green*0.25) as u8,if inside { 255 } else { 0 }])
+    });
+    let leaf_color = texture::ImageTexture::from_image(image::DynamicImage::ImageRgba8(leaf.clone()),texture::ColorSpace::Srgb);
+    let leaf_alpha = texture::ImageTexture::opacity_from_image(image::DynamicImage::ImageRgba8(leaf));
+    let foliage = Cutout::new(Lambertian::set_texture(Texture::ImageTex(leaf_color)),Texture::ImageTex(leaf_alpha)).with_cutoff(0.5);
+    let mut bush = HittableList::new();
+    for _ in 0..600{
+        let out = Vec3::random_unit_vector();
+        let base = Vec3::new(0.0,1.0,0.0)+out*(0.6*camera::random().cbrt());
+        let along = (out+Vec3::new(0.0,0.6,0.0)+Vec3::random_unit_vector()*0.4).unit()*0.3;
+        let across = Vec3::cross(along,Vec3::random_unit_vector()).unit()*0.18;
+        bush.add(Arc::new(quad::quad::new(base-across*0.5,across,along,foliage.clone())));
+    }
+    world.add(Arc::new(LinearBvh::set(bush)));
+    let bark = Lambertian::new(Vec3::new(0.3,0.2,0.12));
+    world.add(Arc::new(cylinder::Cylinder::new(Vec3::zero(),Vec3::new(0.0,1.0,0.0),0.05,bark)));
+
+    let wall = Lambertian::new(Vec3::new(0.75,0.75,0.7));
+    world.add(Arc::new(quad::quad::new(Vec3::new(-4.0,0.0,-1.5),Vec3::new(8.0,0.0,0.0),Vec3::new(0.0,3.0,0.0),wall)));
+    // opaque in the middle, fading out towards the rim
+    let splash = image::RgbaImage::from_fn(128,128,|x,y|{
+        let r = ((x as f64-63.5).powi(2)+(y as f64-63.5).powi(2)).sqrt()/64.0;
+        let alpha = ((1.0-r)*3.0).clamp(0.0,1.0);
+        image::Rgba([200,40,30,(alpha*255.0) as u8])
+    });
+    let paint = texture::ImageTexture::opacity_from_image(image::DynamicImage::ImageRgba8(splash));
+    let decal = Cutout::new(Lambertian::new(Vec3::new(0.6,0.05,0.04)),Texture::ImageTex(paint));
+    world.add(Arc::new(quad::quad::new(Vec3::new(0.6,0.8,-1.499),Vec3::new(1.4,0.0,0.0),Vec3::new(0.0,1.4,0.0),decal)));
+
+    let vfov=40.0;
+    let lookfrom = Vec3::new(0.5,1.3,4.0);
+    let lookat = Vec3::new(0.3,1.0,-0.5);
+    let vup=Vec3::new(0.0,1.0,0.0);
+
+    let defocus_angle=0.0;
+    let focus_dist=10.0;
+    let mut camera = camera::Camera::new(400, 16.0/9.0, 100, vfov, lookfrom, lookat, vup, defocus_angle, focus_dist);
+    let quality = 100;
+    camera.set_background(Vec3::new(0.5,0.6,0.75));
+    camera.add_light(Arc::new(DirectionalLight::new(Vec3::new(-1.0,-1.5,-1.0),Vec3::new(2.0,2.0,2.0))));
+    camera.render(world, path, quality);
+}
+/// The ground boxes and the sphere cluster of `final_scene`, without the nested BVHs.
+fn final_scene_primitives()->HittableList{
+    let mut objects = HittableList::new();
+    let boxes_per_side = 20;
+    for i in 0..boxes_per_side{
+        for j in 0..boxes_per_side{
+            let w=100.0;
+            let x0=-1000.0+i as f64*w;
+            let z0=-1000.0+j as f64*w;
+            let y1=camera::random()*100.0+1.0;
+            let ground = Lambertian::new(Vec3::new(0.48, 0.83, 0.53));
+            for side in quad::quad::bx(Vec3::new(x0,0.0,z0),Vec3::new(x0+w,y1,z0+w),ground).objects{
+                objects.add(side);
+            }
+        }
+    }
+    for _ in 0..1000{
+        let white = Lambertian::new(Vec3::new(0.73, 0.73, 0.73));
+        objects.add(Arc::new(sphere::Sphere::new(Vec3::random()*165.0+Vec3::new(-100.0,270.0,395.0),10.0,white)));
+    }
+    objects
+}
+/// Traces the same camera rays through the pointer tree `BvhNode` and the flattened `LinearBvh`.
+fn bvh_benchmark(){
+    let gray = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+    let scenes=[
+        ("final_scene",None,final_scene_primitives(),Vec3::new(478.0, 278.0, -600.0),Vec3::new(278.0, 278.0,0.0)),
+        ("cow.obj",Some("input/cow.obj"),obj_read::load_obj_triangles("input/cow.obj",gray.clone()).expect("input/cow.obj"),Vec3::new(1.5,0.8,3.0),Vec3::new(0.0,0.1,0.0)),
+        ("deer.obj",Some("input/deer.obj"),obj_read::load_obj_triangles("input/deer.obj",gray).expect("input/deer.obj"),Vec3::new(0.0,800.0,3000.0),Vec3::new(0.0,600.0,0.0)),
+    ];
+    for (name,obj_path,objects,lookfrom,lookat) in scenes{
+        let camera = camera::Camera::new(256, 1.0, 1, 40.0, lookfrom, lookat, Vec3::new(0.0,1.0,0.0), 0.0, 10.0);
+        let mut rays=Vec::new();
+        for y in 0..camera.image_height{
+            for x in 0..camera.image_width{
+                for _ in 0..4{
+                    rays.push(camera.get_ray(x as f64,y as f64));
+                }
+            }
+        }
+        let copy=|| {
+            let mut list=HittableList::new();
+            for object in objects.objects.iter(){
+                list.add(object.clone());
+            }
+            list
+        };
+        // best of five runs
+        let trace=|bvh:&dyn Hittable|{
+            let mut best=None;
+            let mut hits=0;
+            for _ in 0..5{
+                let start=Instant::now();
+                hits=0;
+                for ray in rays.iter(){
+                    let mut rec=HitRecord::new();
+                    if bvh.hit(ray,Interval::set(0.001,f64::INFINITY),&mut rec){
+                        hits+=1;
+                    }
+                }
+                let elapsed=start.elapsed();
+                best=Some(best.map_or(elapsed,|b:std::time::Duration|b.min(elapsed)));
+            }
+            (best.unwrap(),hits)
+        };
+        let start=Instant::now();
+        let tree=bvh::BvhNode::set(copy());
+        let tree_build=start.elapsed();
+        let (tree_trace,tree_hits)=trace(&tree);
+        let start=Instant::now();
+        let linear=LinearBvh::set(copy());
+        let linear_build=start.elapsed();
+        let (linear_trace,linear_hits)=trace(&linear);
+        println!("{}: {} objects, {} rays",name,objects.objects.len(),rays.len());
+        println!("  BvhNode   build {:>10.3?}  trace {:>10.3?}  hits {}",tree_build,tree_trace,tree_hits);
+        println!("  LinearBvh build {:>10.3?}  trace {:>10.3?}  hits {}  ({:.2}x)",linear_build,linear_trace,linear_hits,tree_trace.as_secs_f64()/linear_trace.as_secs_f64());
+        if let Some(path)=obj_path{
+            // load time includes parsing the OBJ file again
+            fn load<L:Lanes>(path:&str)->(SimdMesh<Lambertian,L>,std::time::Duration){
+                let start=Instant::now();
+                let mesh=obj_read::load_obj_to_simd_mesh(path,Lambertian::new(Vec3::new(0.5,0.5,0.5))).expect(path);
+                (mesh,start.elapsed())
+            }
+            let report=|label:&str,mesh:&dyn Hittable,load:std::time::Duration|{
+                let (mesh_trace,mesh_hits)=trace(mesh);
+                println!("  {} load {:>10.3?}  trace {:>10.3?}  hits {}  ({:.2}x)",label,load,mesh_trace,mesh_hits,tree_trace.as_secs_f64()/mesh_trace.as_secs_f64());
+            };
+            let (mesh,time)=load::<f64x4>(path);
+            report("SimdMesh f64x4",&mesh,time);
+            let (mesh,time)=load::<f32x4>(path);
+            report("SimdMesh f32x4",&mesh,time);
+            let (mesh,time)=load::<f32x8>(path);
+            report("SimdMesh f32x8",&mesh,time);
+        }
+    }
+}
 fn main() {
     let path = "output1/TANK.png";
     let mode=10;
@@ -373,6 +1061,23 @@ fn main() {
         8 => cornell_smoke(path),
         9 => final_scene(path),
         10 => obj_test(path),
+        11 => delta_lights(path),
+        12 => cornell_transforms(path),
+        13 => motion_blur(path),
+        14 => bvh_benchmark(),
+        15 => textured_mesh(path),
+        16 => obj_materials(path),
+        17 => gltf_scene(path),
+        18 => ply_stl_meshes(path),
+        19 => analytic_shapes(path),
+        20 => csg_shapes(path),
+        21 => sdf_shapes(path),
+        22 => curves(path),
+        23 => subdivision_surfaces(path),
+        24 => forest(path),
+        25 => texture_filtering(path),
+        26 => normal_mapping(path),
+        27 => cutouts(path),
         _ => bouncing_spheres(path),
     }
 
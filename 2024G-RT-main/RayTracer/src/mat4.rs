@@ -0,0 +1,249 @@
+use std::ops::Mul;
+use crate::vec3::Vec3;
+
+/// Row-major 4x4 matrix for affine transforms, points are column vectors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scale(s: Vec3) -> Self {
+        Self::new([
+            [s.x, 0.0, 0.0, 0.0],
+            [0.0, s.y, 0.0, 0.0],
+            [0.0, 0.0, s.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Rotation of `angle` degrees about `axis` (right handed).
+    pub fn rotate(axis: Vec3, angle: f64) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        Self::new([
+            [t * a.x * a.x + cos, t * a.x * a.y - sin * a.z, t * a.x * a.z + sin * a.y, 0.0],
+            [t * a.x * a.y + sin * a.z, t * a.y * a.y + cos, t * a.y * a.z - sin * a.x, 0.0],
+            [t * a.x * a.z - sin * a.y, t * a.y * a.z + sin * a.x, t * a.z * a.z + cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting, `None` for singular matrices.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&x, &y| a[x][col].abs().total_cmp(&a[y][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = a[row][col];
+                if factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+        Some(Self::new(inv))
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            Vec3::new(x, y, z)
+        } else {
+            Vec3::new(x, y, z) / w
+        }
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    /// Normals go through the inverse transpose; `self` must be the inverse of the
+    /// matrix the surface was transformed with.
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        )
+    }
+}
+
+/// Unit quaternion used to interpolate rotations between keyframes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub w: f64,
+    pub v: Vec3,
+}
+
+impl Quat {
+    /// Rotation of `angle` degrees about `axis`, same convention as `Mat4::rotate`.
+    pub fn from_axis_angle(axis: Vec3, angle: f64) -> Self {
+        let (sin, cos) = (angle.to_radians() / 2.0).sin_cos();
+        Self { w: cos, v: axis.unit() * sin }
+    }
+
+    fn dot(&self, other: &Quat) -> f64 {
+        self.w * other.w + self.v * other.v
+    }
+
+    fn normalize(self) -> Self {
+        let len = self.dot(&self).sqrt();
+        Self { w: self.w / len, v: self.v / len }
+    }
+
+    /// Spherical linear interpolation along the shorter arc.
+    pub fn slerp(a: Quat, b: Quat, t: f64) -> Self {
+        let mut b = b;
+        let mut cos_theta = a.dot(&b);
+        if cos_theta < 0.0 {
+            b = Quat { w: -b.w, v: -b.v };
+            cos_theta = -cos_theta;
+        }
+        if cos_theta > 0.9995 {
+            // nearly parallel, fall back to a normalized lerp
+            return Quat { w: a.w + (b.w - a.w) * t, v: a.v + (b.v - a.v) * t }.normalize();
+        }
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        let wa = ((1.0 - t) * theta).sin() / sin_theta;
+        let wb = (t * theta).sin() / sin_theta;
+        Quat { w: a.w * wa + b.w * wb, v: a.v * wa + b.v * wb }
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self { w: self.w, v: -self.v }
+    }
+
+    pub fn to_mat4(self) -> Mat4 {
+        let (w, x, y, z) = (self.w, self.v.x, self.v.y, self.v.z);
+        Mat4::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Mat4::new(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::assert_near;
+
+    #[test]
+    fn test_inverse() {
+        let m = Mat4::translate(Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::rotate(Vec3::new(1.0, 1.0, 0.0), 33.0)
+            * Mat4::scale(Vec3::new(2.0, 0.5, 3.0));
+        let p = Vec3::new(0.3, -4.0, 7.0);
+        let inv = m.inverse().unwrap();
+        assert_near(inv.transform_point(m.transform_point(p)), p, 1e-9);
+        assert_near((m * inv).transform_point(p), p, 1e-9);
+    }
+
+    #[test]
+    fn test_singular() {
+        assert!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn test_rotate() {
+        let m = Mat4::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        assert_near(m.transform_vector(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0), 1e-9);
+        assert_near(m.transform_point(Vec3::new(0.0, 1.0, 5.0)), Vec3::new(-1.0, 0.0, 5.0), 1e-9);
+    }
+
+    #[test]
+    fn test_quat_matches_rotate() {
+        let axis = Vec3::new(1.0, 2.0, -0.5);
+        let m = Mat4::rotate(axis, 70.0);
+        let q = Quat::from_axis_angle(axis, 70.0).to_mat4();
+        let p = Vec3::new(0.4, -1.0, 2.5);
+        assert_near(m.transform_point(p), q.transform_point(p), 1e-9);
+    }
+
+    #[test]
+    fn test_slerp() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let half = Quat::slerp(Quat::from_axis_angle(axis, 0.0), Quat::from_axis_angle(axis, 120.0), 0.5);
+        let p = Vec3::new(1.0, 0.0, 0.0);
+        assert_near(half.to_mat4().transform_point(p), Mat4::rotate(axis, 60.0).transform_point(p), 1e-9);
+    }
+
+    #[test]
+    fn test_normal() {
+        // A plane with normal (1,1,0) squashed along x keeps its normal perpendicular to the surface.
+        let m = Mat4::scale(Vec3::new(0.5, 1.0, 1.0));
+        let inv = m.inverse().unwrap();
+        let tangent = m.transform_vector(Vec3::new(1.0, -1.0, 0.0));
+        let normal = inv.transform_normal(Vec3::new(1.0, 1.0, 0.0));
+        assert!((tangent * normal).abs() < 1e-12);
+    }
+}
@@ -3,6 +3,7 @@ use crate::ray::Ray;
 use crate::vec3::Vec3;
 use crate::camera::random;
 use crate::texture::Texture;
+use num_traits::FloatConst;
 
 
 pub trait Material{
@@ -10,6 +11,16 @@ pub trait Material{
     fn emitted(&self, _u: f64, _v: f64, _p: &Vec3) -> Vec3 {
         Vec3::new(0.0, 0.0, 0.0)
     }
+    /// BRDF (or phase function) times the cosine term for light arriving from `wi`.
+    /// Only needed for direct lighting from delta lights; specular materials keep the default.
+    fn eval(&self, _r_in: &Ray, _hit_record: &HitRecord, _wi: Vec3) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+    /// Whether the surface is there at a candidate hit, asked by triangles and quads before
+    /// they report it. Where this says no the ray goes on as if it had missed.
+    fn is_opaque(&self, _u: f64, _v: f64, _p: &Vec3) -> bool {
+        true
+    }
 }
 #[derive(Clone)]
 pub struct Lambertian {
@@ -29,24 +40,37 @@ impl Material for Lambertian {
         if scatter_direction.near_zero() {
             scatter_direction = hit_record.normal;
         }
+        if !hit_record.above_surface(scatter_direction) {
+            return None;
+        }
         let scattered = Ray::new_time(hit_record.p, scatter_direction, _r_in.time);
-        let attenuation = self.tex.value(hit_record.u, hit_record.v, &hit_record.p);
+        let attenuation = self.tex.sample(hit_record);
         Some((scattered, attenuation))
     }
+    fn eval(&self, _r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Vec3 {
+        let cos_theta = hit_record.normal * wi;
+        if cos_theta <= 0.0 || !hit_record.above_surface(wi) {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        self.tex.sample(hit_record) * (cos_theta / f64::PI())
+    }
 
 }
 #[derive(Clone)]
 pub struct Metal {
-    pub albedo: Vec3,
+    pub tex: Texture,
     pub fuzz: f64,
 }
 impl Metal {
     pub fn new(albedo: Vec3, fuzz: f64) -> Self {
+        Self::set_texture(Texture::SolidColor(albedo), fuzz)
+    }
+    pub fn set_texture(tex: Texture, fuzz: f64) -> Self {
         let fuzz = match fuzz < 1.0 {
             true => fuzz,
             false => 1.0,
         };
-        Self { albedo, fuzz }
+        Self { tex, fuzz }
     }
 }
 impl Material for Metal {
@@ -54,8 +78,8 @@ impl Material for Metal {
         let mut reflected = Vec3::reflect(r_in.direction.unit(), hit_record.normal);
         reflected =reflected.unit()+Vec3::random_unit_vector()*self.fuzz;
         let scattered = Ray::new_time(hit_record.p, reflected, r_in.time);
-        let attenuation = self.albedo;
-        if scattered.direction * hit_record.normal > 0.0 {
+        let attenuation = self.tex.sample(hit_record);
+        if scattered.direction * hit_record.normal > 0.0 && hit_record.above_surface(scattered.direction) {
             Some((scattered, attenuation))
         } else {
             None
@@ -141,7 +165,124 @@ impl  Isotropic {
 impl Material for Isotropic {
     fn scatter(&self, _r_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
         let scattered = Ray::new_time(hit_record.p, Vec3::random_in_unit_sphere(), _r_in.time);
-        let attenuation = self.tex.value(hit_record.u, hit_record.v, &hit_record.p);
+        let attenuation = self.tex.sample(hit_record);
         Some((scattered, attenuation))
     }
-}
\ No newline at end of file
+    fn eval(&self, _r_in: &Ray, hit_record: &HitRecord, _wi: Vec3) -> Vec3 {
+        self.tex.sample(hit_record) / (4.0 * f64::PI())
+    }
+}
+/// Diffuse base plus a Phong specular lobe, the usual `Kd`/`Ks`/`Ns` model of MTL files.
+#[derive(Clone)]
+pub struct Glossy {
+    pub tex: Texture,
+    pub specular: Vec3,
+    /// Phong exponent, higher is a tighter highlight.
+    pub exponent: f64,
+}
+impl Glossy {
+    pub fn new(tex: Texture, specular: Vec3, exponent: f64) -> Self {
+        Self { tex, specular, exponent: exponent.max(0.0) }
+    }
+
+    /// Chance of sampling the specular lobe, from the relative brightness of both parts.
+    fn specular_probability(&self, diffuse: Vec3) -> f64 {
+        let brightness = |c: Vec3| (c.x + c.y + c.z) / 3.0;
+        let total = brightness(diffuse) + brightness(self.specular);
+        if total <= 0.0 { 0.0 } else { brightness(self.specular) / total }
+    }
+
+    /// Direction around `axis` with density proportional to `cos^exponent`.
+    fn sample_lobe(axis: Vec3, exponent: f64) -> Vec3 {
+        let cos_alpha = random().powf(1.0 / (exponent + 1.0));
+        let sin_alpha = (1.0 - cos_alpha * cos_alpha).max(0.0).sqrt();
+        let phi = 2.0 * f64::PI() * random();
+        let helper = if axis.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let tangent = Vec3::cross(axis, helper).unit();
+        let bitangent = Vec3::cross(axis, tangent);
+        tangent * (sin_alpha * phi.cos()) + bitangent * (sin_alpha * phi.sin()) + axis * cos_alpha
+    }
+}
+impl Material for Glossy {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
+        let diffuse = self.tex.sample(hit_record);
+        let p_specular = self.specular_probability(diffuse);
+        if random() < p_specular {
+            let reflected = Vec3::reflect(r_in.direction.unit(), hit_record.normal);
+            let direction = Glossy::sample_lobe(reflected, self.exponent);
+            let cos_theta = direction * hit_record.normal;
+            if cos_theta <= 0.0 || !hit_record.above_surface(direction) {
+                return None;
+            }
+            // normalized Phong divided by its sampling pdf
+            let weight = (self.exponent + 2.0) / (self.exponent + 1.0) * cos_theta / p_specular;
+            Some((Ray::new_time(hit_record.p, direction, r_in.time), self.specular * weight))
+        } else {
+            let mut direction = hit_record.normal + Vec3::random_unit_vector();
+            if direction.near_zero() {
+                direction = hit_record.normal;
+            }
+            if !hit_record.above_surface(direction) {
+                return None;
+            }
+            Some((Ray::new_time(hit_record.p, direction, r_in.time), diffuse / (1.0 - p_specular)))
+        }
+    }
+    fn eval(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Vec3 {
+        let cos_theta = hit_record.normal * wi;
+        if cos_theta <= 0.0 || !hit_record.above_surface(wi) {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let diffuse = self.tex.sample(hit_record) / f64::PI();
+        let reflected = Vec3::reflect(r_in.direction.unit(), hit_record.normal);
+        let cos_alpha = (reflected * wi).max(0.0);
+        let specular = self.specular * ((self.exponent + 2.0) / (2.0 * f64::PI()) * cos_alpha.powf(self.exponent));
+        (diffuse + specular) * cos_theta
+    }
+}
+
+/// Phong exponent with roughly the highlight size of a GGX lobe of the given roughness.
+pub fn phong_exponent(roughness: f64) -> f64 {
+    let alpha = roughness * roughness;
+    (2.0 / (alpha * alpha).max(1e-6) - 2.0).max(1.0)
+}
+
+/// The metallic-roughness model of glTF, read per texel: the blue channel of `metal_rough`
+/// scales `metallic` and its green channel `roughness`. The metal part reflects like a
+/// `Metal` of the base color, the rest like a `Glossy` with a 4% dielectric highlight.
+#[derive(Clone)]
+pub struct MetallicRoughness {
+    pub tex: Texture,
+    pub metal_rough: Texture,
+    pub metallic: f64,
+    pub roughness: f64,
+}
+impl MetallicRoughness {
+    pub fn new(tex: Texture, metal_rough: Texture, metallic: f64, roughness: f64) -> Self {
+        Self { tex, metal_rough, metallic, roughness }
+    }
+
+    /// How metallic the surface is at the hit, with both lobes for that texel.
+    fn lobes(&self, hit_record: &HitRecord) -> (f64, Metal, Glossy) {
+        let factors = self.metal_rough.sample(hit_record);
+        let metallic = (self.metallic * factors.z).clamp(0.0, 1.0);
+        let roughness = (self.roughness * factors.y).clamp(0.0, 1.0);
+        let base = self.tex.sample(hit_record);
+        let dielectric = Glossy::new(Texture::SolidColor(base), Vec3::new(0.04, 0.04, 0.04), phong_exponent(roughness));
+        (metallic, Metal::new(base, roughness), dielectric)
+    }
+}
+impl Material for MetallicRoughness {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
+        let (metallic, metal, dielectric) = self.lobes(hit_record);
+        if random() < metallic {
+            metal.scatter(r_in, hit_record)
+        } else {
+            dielectric.scatter(r_in, hit_record)
+        }
+    }
+    fn eval(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Vec3 {
+        let (metallic, _, dielectric) = self.lobes(hit_record);
+        dielectric.eval(r_in, hit_record, wi) * (1.0 - metallic)
+    }
+}
@@ -0,0 +1,161 @@
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+/// Surface detail that bends the shading normal without moving the surface, read through
+/// the texture coordinates and the `dpdu`, `dpdv` of the hit.
+#[derive(Clone)]
+pub enum NormalMap {
+    /// Tangent-space normals stored as `(xyz + 1) / 2`, x along `dpdu`, y along `dpdv` and z
+    /// along the normal. `scale` multiplies x and y to strengthen or flatten the detail.
+    /// Images of normals must be loaded as data, see `ImageTexture::data`.
+    Tangent { normals: Texture, scale: f64 },
+    /// Heights as the average of the channels times `scale`, in world units, raised on
+    /// the side the ray came from.
+    Bump { height: Texture, scale: f64 },
+}
+
+impl NormalMap {
+    /// The shading normal of `rec` bent by the map, on the same side as `rec.normal`.
+    /// Surfaces without a parameterization keep their normal.
+    pub fn normal(&self, rec: &HitRecord) -> Vec3 {
+        let n = rec.normal;
+        let bent = match self {
+            NormalMap::Tangent { normals, scale } => {
+                let tangent = rec.dpdu - n * (n * rec.dpdu);
+                if tangent.near_zero() {
+                    return n;
+                }
+                let tangent = tangent.unit();
+                let bitangent = Vec3::cross(n, tangent);
+                // mirrored texture coordinates run v the other way
+                let bitangent = if bitangent * rec.dpdv < 0.0 { -bitangent } else { bitangent };
+                let c = normals.sample(rec) * 2.0 - Vec3::ones();
+                tangent * (c.x * scale) + bitangent * (c.y * scale) + n * c.z
+            }
+            NormalMap::Bump { height, scale } => {
+                if rec.dpdu.near_zero() || rec.dpdv.near_zero() {
+                    return n;
+                }
+                // differences over the pixel footprint, or a small fixed step without one
+                let step = |a: f64, b: f64| if a == 0.0 && b == 0.0 { 0.0005 } else { 0.5 * (a.abs() + b.abs()) };
+                let du = step(rec.duv_dx.0, rec.duv_dy.0);
+                let dv = step(rec.duv_dx.1, rec.duv_dy.1);
+                let h = |u: f64, v: f64| {
+                    let c = height.value(u, v, &rec.p);
+                    scale * (c.x + c.y + c.z) / 3.0
+                };
+                let h0 = h(rec.u, rec.v);
+                let dpdu = rec.dpdu + n * ((h(rec.u + du, rec.v) - h0) / du);
+                let dpdv = rec.dpdv + n * ((h(rec.u, rec.v + dv) - h0) / dv);
+                let bumped = Vec3::cross(dpdu, dpdv);
+                if bumped * n < 0.0 { -bumped } else { bumped }
+            }
+        };
+        if bent.near_zero() { n } else { bent.unit() }
+    }
+}
+
+/// A material shaded with the normal of a `NormalMap` instead of the one of the surface.
+#[derive(Clone)]
+pub struct NormalMapped<Mat: Material> {
+    pub material: Mat,
+    pub map: NormalMap,
+}
+
+impl<Mat: Material> NormalMapped<Mat> {
+    pub fn new(material: Mat, map: NormalMap) -> Self {
+        Self { material, map }
+    }
+
+    fn shaded<'a>(&self, rec: &HitRecord<'a>) -> HitRecord<'a> {
+        HitRecord { normal: self.map.normal(rec), ..*rec }
+    }
+}
+
+impl<Mat: Material> Material for NormalMapped<Mat> {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Ray, Vec3)> {
+        self.material.scatter(r_in, &self.shaded(hit_record))
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
+        self.material.emitted(u, v, p)
+    }
+
+    fn eval(&self, r_in: &Ray, hit_record: &HitRecord, wi: Vec3) -> Vec3 {
+        self.material.eval(r_in, &self.shaded(hit_record), wi)
+    }
+
+    fn is_opaque(&self, u: f64, v: f64, p: &Vec3) -> bool {
+        self.material.is_opaque(u, v, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::assert_near;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::material::Lambertian;
+    use crate::quad::quad;
+    use crate::sphere::Sphere;
+    use crate::texture::{ColorSpace, ImageTexture};
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    // A unit square in the xy plane seen from +z, hit in its middle.
+    fn hit_square<'a>(square: &'a quad<Lambertian>) -> HitRecord<'a> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(square.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        rec
+    }
+
+    #[test]
+    fn flat_maps_keep_the_normal() {
+        let square = quad::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Lambertian::new(Vec3::ones()));
+        let rec = hit_square(&square);
+        let flat = NormalMap::Tangent { normals: Texture::SolidColor(Vec3::new(0.5, 0.5, 1.0)), scale: 1.0 };
+        assert_near(flat.normal(&rec), Vec3::new(0.0, 0.0, 1.0), 1e-12);
+        let level = NormalMap::Bump { height: Texture::SolidColor(Vec3::ones()), scale: 0.3 };
+        assert_near(level.normal(&rec), Vec3::new(0.0, 0.0, 1.0), 1e-12);
+    }
+
+    #[test]
+    fn tangent_normals_follow_the_parameterization() {
+        let tilted = NormalMap::Tangent { normals: Texture::SolidColor(Vec3::new(0.75, 0.5, 0.75)), scale: 1.0 };
+        let square = quad::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Lambertian::new(Vec3::ones()));
+        assert_near(tilted.normal(&hit_square(&square)), Vec3::new(1.0, 0.0, 1.0).unit(), 1e-12);
+        // u running along y turns the detail with it
+        let turned = quad::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), Lambertian::new(Vec3::ones()));
+        assert_near(tilted.normal(&hit_square(&turned)), Vec3::new(0.0, 1.0, 1.0).unit(), 1e-12);
+    }
+
+    #[test]
+    fn bumps_lean_away_from_the_rise() {
+        // heights climbing from 0 to 1 left to right
+        let ramp = RgbImage::from_fn(256, 1, |x, _| Rgb([x as u8; 3]));
+        let height = Texture::ImageTex(ImageTexture::from_image(DynamicImage::ImageRgb8(ramp), ColorSpace::Linear));
+        let square = quad::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Lambertian::new(Vec3::ones()));
+        let bump = NormalMap::Bump { height, scale: 0.5 };
+        assert_near(bump.normal(&hit_square(&square)), Vec3::new(-0.5, 0.0, 1.0).unit(), 1e-2);
+    }
+
+    #[test]
+    fn sphere_tangents_match_its_uvs() {
+        let (center, radius) = (Vec3::new(1.0, 2.0, 3.0), 2.0);
+        let sphere = Sphere::new(center, radius, Lambertian::new(Vec3::ones()));
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(Vec3::new(1.5, 2.5, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        // stepping along dpdu and dpdv and back onto the sphere moves u and v by the step
+        let eps = 1e-6;
+        let uv_at = |p: Vec3| Sphere::<Lambertian>::get_sphere_uv((p - center).unit());
+        let (u, v) = uv_at(rec.p + rec.dpdu * eps);
+        assert!((u - rec.u - eps).abs() < 1e-9 && (v - rec.v).abs() < 1e-9);
+        let (u, v) = uv_at(rec.p + rec.dpdv * eps);
+        assert!((u - rec.u).abs() < 1e-9 && (v - rec.v - eps).abs() < 1e-9);
+    }
+}
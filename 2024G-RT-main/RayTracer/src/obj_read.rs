@@ -1,107 +1,616 @@
 
+use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use crate::vec3::Vec3;
-use crate::material::{Lambertian, Material};
-use crate::hittable::{Hittable};
-use crate::bvh::BvhNode;
+use crate::material::{Dielectric, DiffuseLight, Glossy, Lambertian, Material};
+use crate::linear_bvh::LinearBvh;
 use crate::hittable_list::HittableList;
-use crate::obj::Obj;
-use crate::quad::quad;
-use crate::texture::Texture;
+use crate::obj::{IndexTuple, MtlLibsLoadError, Obj, ObjData, ObjError, ObjMaterial, SimplePolygon};
+use crate::triangle_mesh::{ImportOptions, TriangleMesh};
+use crate::subdivision::{PolyMesh, Subdivision};
+use crate::simd_mesh::{Lanes, SimdMesh};
+use crate::mtl;
+use crate::texture::{ImageTexture, Texture};
 use crate::texture::Texture::SolidColor;
 use crate::triangle::Triangle;
+use crate::cutout::Cutout;
 
-/// 从OBJ文件加载模型，创建三角形列表，并生成BVH优化的hittable_list
-pub(crate) fn load_obj_to_hittable_list<P: AsRef<Path>, Mat: Material + Sync + Send + Clone + 'static>(
+/// 加载OBJ模型时可能出现的错误
+#[derive(Debug)]
+pub enum ObjLoadError {
+    /// OBJ文件打不开或格式错误
+    Obj(ObjError),
+    /// 有MTL文件打不开或格式错误
+    Mtl(MtlLibsLoadError),
+    /// 面引用了不存在的顶点、纹理坐标或法线
+    IndexOutOfRange { kind: &'static str, index: usize, len: usize },
+    /// 文件里没有面，建不了BVH
+    NoFaces,
+}
+
+impl std::error::Error for ObjLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjLoadError::Obj(e) => Some(e),
+            ObjLoadError::Mtl(e) => Some(e),
+            ObjLoadError::IndexOutOfRange { .. } | ObjLoadError::NoFaces => None,
+        }
+    }
+}
+
+impl fmt::Display for ObjLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjLoadError::Obj(e) => write!(f, "Failed to load OBJ file: {}", e),
+            ObjLoadError::Mtl(e) => write!(f, "Failed to load MTL file: {}", e),
+            ObjLoadError::IndexOutOfRange { kind, index, len } => {
+                write!(f, "Face uses {} {} but the file only has {}", kind, index + 1, len)
+            }
+            ObjLoadError::NoFaces => write!(f, "The OBJ file has no faces"),
+        }
+    }
+}
+
+impl From<ObjError> for ObjLoadError {
+    fn from(e: ObjError) -> Self {
+        ObjLoadError::Obj(e)
+    }
+}
+
+impl From<MtlLibsLoadError> for ObjLoadError {
+    fn from(e: MtlLibsLoadError) -> Self {
+        ObjLoadError::Mtl(e)
+    }
+}
+
+/// 从OBJ文件加载模型，创建共享顶点的三角网格，并生成BVH优化的hittable_list
+pub(crate) fn load_obj_to_hittable_list<P: AsRef<Path>, Mat: Material + Sync + Send + 'static>(
     path: P,
     material: Mat,
-) -> HittableList {
-    let obj: Obj = Obj::load(path).expect("Failed to load OBJ file");
-    let mut hittable_list = HittableList::new();
-    // 遍历每个网格中的面
-    for object in &obj.data.objects{
-        for group in &object.groups {
-            for poly in &group.polys {
-                let mut vertices = Vec::new();
-
-                // 获取顶点位置
-                for &index in &poly.0 {
-                    let vertex = obj.data.position[index.0];
-                    vertices.push(Vec3::new(vertex[0] as f64, vertex[1] as f64, vertex[2]as f64));
-                }
+    options: ImportOptions,
+) -> Result<HittableList, ObjLoadError> {
+    let mesh = Arc::new(load_obj_mesh(path, material, options)?);
+    if mesh.indices.is_empty() {
+        return Err(ObjLoadError::NoFaces);
+    }
+    let bvh = LinearBvh::set(mesh.triangles());
+    Ok(HittableList::set(Arc::new(bvh)))
+}
+/// 从OBJ文件加载索引三角网格。位置/纹理/法线索引相同的顶点只存一份，
+/// 法线和UV只有在每个顶点都有时才保留。设置了`options.subdivision`时先细分
+pub(crate) fn load_obj_mesh<P: AsRef<Path>, Mat: Material + Sync + Send + 'static>(
+    path: P,
+    material: Mat,
+    options: ImportOptions,
+) -> Result<TriangleMesh<Mat>, ObjLoadError> {
+    let mut obj: Obj = Obj::load(path)?;
+    apply_options(&mut obj.data, &options);
+    let polys = obj.data.objects.iter()
+        .flat_map(|object| &object.groups)
+        .flat_map(|group| &group.polys);
+    build_mesh(&obj.data, polys, material, options.subdivision)
+}
+/// 按导入选项变换所有顶点和法线，翻转绕向时倒转每个面的顶点顺序
+fn apply_options(data: &mut ObjData, options: &ImportOptions) {
+    let to_vec3 = |p: &[f32; 3]| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64);
+    let to_f32 = |v: Vec3| [v.x as f32, v.y as f32, v.z as f32];
+    let positions: Vec<Vec3> = data.position.iter().map(to_vec3).collect();
+    let matrix = options.matrix(&positions);
+    for (p, v) in data.position.iter_mut().zip(positions) {
+        *p = to_f32(matrix.transform_point(v));
+    }
+    // 法线要用逆矩阵变换
+    if let Some(inverse) = matrix.inverse() {
+        for n in &mut data.normal {
+            *n = to_f32(inverse.transform_normal(to_vec3(n)).unit());
+        }
+    }
+    if options.flip_winding {
+        for poly in data.objects.iter_mut().flat_map(|o| &mut o.groups).flat_map(|g| &mut g.polys) {
+            poly.0.reverse();
+        }
+    }
+}
+/// 检查面引用的顶点、纹理坐标和法线都存在
+fn check_indices(data: &ObjData, poly: &SimplePolygon) -> Result<(), ObjLoadError> {
+    let check = |kind, index: usize, len: usize| {
+        if index < len {
+            Ok(())
+        } else {
+            Err(ObjLoadError::IndexOutOfRange { kind, index, len })
+        }
+    };
+    for v in &poly.0 {
+        check("vertex", v.0, data.position.len())?;
+        if let Some(vt) = v.1 {
+            check("texture coordinate", vt, data.texture.len())?;
+        }
+        if let Some(vn) = v.2 {
+            check("normal", vn, data.normal.len())?;
+        }
+    }
+    Ok(())
+}
+/// 用给定的面建立索引三角网格，只包含这些面用到的顶点。多边形先拆成三角形。
+/// 有`subdivision`时这些面当作控制网格细分，文件里的法线不再使用
+fn build_mesh<'a, Mat: Material + Sync + Send + 'static>(
+    data: &ObjData,
+    polys: impl Iterator<Item = &'a SimplePolygon>,
+    material: Mat,
+    subdivision: Option<Subdivision>,
+) -> Result<TriangleMesh<Mat>, ObjLoadError> {
+    if let Some(subdivision) = subdivision {
+        return Ok(build_cage(data, polys)?.subdivided(subdivision).into_mesh(material));
+    }
+    let position = |v: &IndexTuple| {
+        let p = data.position[v.0];
+        Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64)
+    };
 
-                if vertices.len() == 3 { // 确保是三角形
-                    hittable_list.add(Arc::new(Triangle::new(
-                        vertices[0],
-                        vertices[1],
-                        vertices[2],
-                        material.clone(),
-                    )));
-                }
+    let mut vertex_ids: HashMap<IndexTuple, u32> = HashMap::new();
+    let mut vertices: Vec<IndexTuple> = Vec::new();
+    let mut indices = Vec::new();
+    for poly in polys {
+        check_indices(data, poly)?;
+        let points: Vec<Vec3> = poly.0.iter().map(position).collect();
+        for triangle in triangulate(&points) {
+            let mut face = [0u32; 3];
+            for (id, corner) in face.iter_mut().zip(triangle) {
+                let index = poly.0[corner];
+                *id = *vertex_ids.entry(index).or_insert_with(|| {
+                    vertices.push(index);
+                    (vertices.len() - 1) as u32
+                });
             }
+            indices.push(face);
         }
     }
+    let positions = vertices.iter().map(position).collect();
+    let normals = if vertices.iter().all(|v| v.2.is_some()) {
+        vertices.iter()
+            .map(|v| {
+                let n = data.normal[v.2.unwrap()];
+                Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64).unit()
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let uvs = if vertices.iter().all(|v| v.1.is_some()) {
+        vertices.iter()
+            .map(|v| {
+                let uv = data.texture[v.1.unwrap()];
+                (uv[0] as f64, uv[1] as f64)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(TriangleMesh::new(positions, normals, uvs, indices, material))
+}
+/// 用给定的面建立细分用的控制网格。UV只有在每个角都有时才保留，少于三个角的面跳过
+fn build_cage<'a>(
+    data: &ObjData,
+    polys: impl Iterator<Item = &'a SimplePolygon>,
+) -> Result<PolyMesh, ObjLoadError> {
+    let mut faces = Vec::new();
+    let mut uv_faces = Vec::new();
+    let mut has_uvs = true;
+    for poly in polys.filter(|poly| poly.0.len() >= 3) {
+        check_indices(data, poly)?;
+        faces.push(poly.0.iter().map(|v| v.0 as u32).collect());
+        has_uvs &= poly.0.iter().all(|v| v.1.is_some());
+        uv_faces.push(poly.0.iter().map(|v| v.1.unwrap_or(0) as u32).collect());
+    }
+    let positions = data.position.iter()
+        .map(|p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+        .collect();
+    let cage = PolyMesh::new(positions, faces);
+    if !has_uvs {
+        return Ok(cage);
+    }
+    let uvs = data.texture.iter().map(|uv| (uv[0] as f64, uv[1] as f64)).collect();
+    Ok(cage.with_uvs(uvs, uv_faces))
+}
+/// 把一个平面多边形拆成三角形，返回顶点下标，绕向和多边形一致。
+/// 用耳切法，凹多边形也能正确处理；退化的多边形找不到耳朵时剩下的部分按扇形拆分
+pub(crate) fn triangulate(points: &[Vec3]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n < 3 {
+        return Vec::new();
+    }
+    // Newell法求多边形法线，决定哪一侧是凸角
+    let mut normal = Vec3::zero();
+    for i in 0..n {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        normal.x += (a.y - b.y) * (a.z + b.z);
+        normal.y += (a.z - b.z) * (a.x + b.x);
+        normal.z += (a.x - b.x) * (a.y + b.y);
+    }
+    let left_of = |a: Vec3, b: Vec3, p: Vec3| Vec3::cross(b - a, p - a) * normal >= 0.0;
 
-    let bvh = BvhNode::set(hittable_list);
-    let  mut hittable_list = HittableList::new();
-    hittable_list.add(Arc::new(bvh));
-    hittable_list
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let corners = |i: usize| [remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]];
+        let ear = (0..m).find(|&i| {
+            let [a, b, c] = corners(i);
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+            if Vec3::cross(pb - pa, pc - pb) * normal <= 0.0 {
+                return false; // 凹角或共线
+            }
+            remaining.iter()
+                .filter(|&&j| j != a && j != b && j != c)
+                .all(|&j| {
+                    let p = points[j];
+                    !(left_of(pa, pb, p) && left_of(pb, pc, p) && left_of(pc, pa, p))
+                })
+        });
+        match ear {
+            Some(i) => {
+                triangles.push(corners(i));
+                remaining.remove(i);
+            }
+            None => break,
+        }
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
 }
+/// 从OBJ文件加载模型，只返回三角形列表，不建BVH。有顶点法线时做平滑着色
+pub(crate) fn load_obj_triangles<P: AsRef<Path>, Mat: Material + Sync + Send + Clone + 'static>(
+    path: P,
+    material: Mat,
+) -> Result<HittableList, ObjLoadError> {
+    let mesh = load_obj_mesh(path, material, ImportOptions::default())?;
+    let mut hittable_list = HittableList::new();
+    for &[a, b, c] in &mesh.indices {
+        let [a, b, c] = [a as usize, b as usize, c as usize];
+        let (p0, p1, p2) = (mesh.positions[a], mesh.positions[b], mesh.positions[c]);
+        let triangle = if mesh.normals.is_empty() {
+            Triangle::new(p0, p1, p2, mesh.mat.clone())
+        } else {
+            Triangle::with_normals(p0, p1, p2, mesh.normals[a], mesh.normals[b], mesh.normals[c], mesh.mat.clone())
+        };
+        hittable_list.add(Arc::new(triangle));
+    }
+    Ok(hittable_list)
+}
+/// 从OBJ文件加载模型，生成SIMD打包的三角形网格，`L`决定顶点精度
+pub(crate) fn load_obj_to_simd_mesh<P: AsRef<Path>, Mat: Material + Sync + Send, L: Lanes>(
+    path: P,
+    material: Mat,
+) -> Result<SimdMesh<Mat, L>, ObjLoadError> {
+    let mesh = load_obj_mesh(path, Lambertian::new(Vec3::zero()), ImportOptions::default())?;
+    if mesh.indices.is_empty() {
+        return Err(ObjLoadError::NoFaces);
+    }
+    let faces = mesh.indices.iter()
+        .map(|face| face.map(|i| mesh.positions[i as usize]))
+        .collect();
+    Ok(SimdMesh::new(faces, material))
+}
+/// 加载OBJ和它引用的MTL文件。每种材质建一个三角网格，所有面放进同一个BVH。
+/// `mtl_path`为`None`时按OBJ所在目录查找所有`mtllib`，否则所有`mtllib`都从`mtl_path`读取，
+/// 用于导出时MTL文件名对不上的模型。没有`usemtl`或材质找不到的面用灰色漫反射
 pub(crate) fn load_obj<P: AsRef<Path>>(
     path: P,
-    mtl_path: P,
-) -> HittableList {
-    let mut obj: Obj = Obj::load(path).expect("Failed to load OBJ file");
-    let mtl_file = File::open(mtl_path).expect("Failed to open MTL file");
-    obj.data.material_libs[0].reload(mtl_file).expect("Failed to load MTL file");
-    let mut triangles:Vec<Arc<dyn Hittable + Sync + Send>> = Vec::new();
+    mtl_path: Option<P>,
+    options: ImportOptions,
+) -> Result<HittableList, ObjLoadError> {
+    let mut obj: Obj = Obj::load(&path)?;
+    apply_options(&mut obj.data, &options);
+    let mtl_dir = match &mtl_path {
+        Some(mtl_path) => {
+            let mtl_path = mtl_path.as_ref();
+            obj.load_mtls_fn(|_, _| File::open(mtl_path).map(BufReader::new))?;
+            Some(mtl_path.parent().unwrap_or(Path::new("")).to_owned())
+        }
+        None => {
+            obj.load_mtls()?;
+            None
+        }
+    };
+    // 贴图路径相对于定义该材质的MTL文件所在目录，mtllib可以在子目录里
+    let texture_dir = |mtl: Option<&mtl::Material>| -> PathBuf {
+        if let Some(dir) = &mtl_dir {
+            return dir.clone();
+        }
+        obj.data.material_libs.iter()
+            .find(|lib| lib.materials.iter().any(|m| mtl.is_some_and(|mtl| std::ptr::eq(m.as_ref(), mtl))))
+            .and_then(|lib| obj.path.join(&lib.filename).parent().map(Path::to_owned))
+            .unwrap_or_else(|| obj.path.clone())
+    };
+
+    // 按材质名把面分组
+    let mut faces: Vec<(Option<&ObjMaterial>, Vec<&SimplePolygon>)> = Vec::new();
     for object in &obj.data.objects{
         for group in &object.groups {
-            let mat_name=group.material.as_ref().unwrap().name();
-            let color_f32=obj.data.material_libs[0].materials.iter().find(|&x| x.name==mat_name).unwrap().kd;
-            let color=match color_f32{
-                Some(color_f32)=>Vec3::new(color_f32[0] as f64,color_f32[1] as f64,color_f32[2] as f64),
-                None=>Vec3::new(0.0,0.0,0.0),
-            };
-            for poly in &group.polys {
-                let mut vertices = Vec::new();
-
-                // 获取顶点位置
-                for &index in &poly.0 {
-                    let vertex = obj.data.position[index.0];
-                    vertices.push(Vec3::new(vertex[0] as f64, vertex[1] as f64, vertex[2]as f64));
-                }
+            let material = group.material.as_ref();
+            match faces.iter_mut().find(|(m, _)| m.map(|m| m.name()) == material.map(|m| m.name())) {
+                Some((_, polys)) => polys.extend(&group.polys),
+                None => faces.push((material, group.polys.iter().collect())),
+            }
+        }
+    }
 
-                if vertices.len() == 3 { // 确保是三角形
-                    triangles.push(Arc::new(Triangle::new(
-                        vertices[0],
-                        vertices[1],
-                        vertices[2],
-                        Lambertian::new(color),
-                    )));
-                }
-                else if vertices.len() == 4{
-                    triangles.push(Arc::new(Triangle::new(
-                        vertices[0],
-                        vertices[1]-vertices[0],
-                        vertices[3]-vertices[0],
-                        Lambertian::new(color),
-                    )));
+    let mut triangles = HittableList::new();
+    for (material, polys) in faces {
+        let mtl = match material {
+            Some(ObjMaterial::Mtl(mtl)) => Some(mtl.as_ref()),
+            Some(ObjMaterial::Ref(name)) => {
+                eprintln!("WARNING: material '{}' not found in any MTL file, using the default.", name);
+                None
+            }
+            None => None,
+        };
+        for triangle in mtl_triangles(&obj.data, polys, mtl, &texture_dir(mtl), options.subdivision)?.objects {
+            triangles.add(triangle);
+        }
+    }
+    if triangles.objects.is_empty() {
+        return Err(ObjLoadError::NoFaces);
+    }
+    Ok(HittableList::set(Arc::new(LinearBvh::set(triangles))))
+}
+/// 把MTL材质换成光线追踪材质，再用这些面建网格：
+/// Ke发光，d/Tr/illum透明时按Ni折射，有Ks和Ns时用Glossy，其余为漫反射。
+/// map_Kd贴图按OBJ的UV采样，找不到贴图时退回Kd颜色
+/// map_d贴图作镂空的不透明度，有它时d和Tr不再当作玻璃
+fn mtl_triangles(
+    data: &ObjData,
+    polys: Vec<&SimplePolygon>,
+    mtl: Option<&mtl::Material>,
+    texture_dir: &Path,
+    subdivision: Option<Subdivision>,
+) -> Result<HittableList, ObjLoadError> {
+    let polys = polys.into_iter();
+    let mtl = match mtl {
+        Some(mtl) => mtl,
+        None => return Ok(Arc::new(build_mesh(data, polys, Lambertian::new(Vec3::new(0.8, 0.8, 0.8)), subdivision)?).triangles()),
+    };
+    let color = |c: Option<[f32; 3]>| c.map(|c| Vec3::new(c[0] as f64, c[1] as f64, c[2] as f64));
+    // 导出的路径里常有Windows分隔符
+    let texture_file = |map: &String| texture_dir.join(map.replace("\\\\", "/").replace('\\', "/"));
 
-                }
+    let emission = color(mtl.ke).unwrap_or(Vec3::zero());
+    if emission.x > 0.0 || emission.y > 0.0 || emission.z > 0.0 {
+        return Ok(Arc::new(build_mesh(data, polys, DiffuseLight::set_color(emission), subdivision)?).triangles());
+    }
+    let alpha = match mtl.map_d.as_ref().map(texture_file) {
+        Some(file) if file.exists() => Some(Texture::ImageTex(ImageTexture::opacity(&file.to_string_lossy()))),
+        Some(file) => {
+            eprintln!("WARNING: opacity map '{}' not found, drawing the surface solid.", file.display());
+            None
+        }
+        None => None,
+    };
+    let transparent = alpha.is_none()
+        && (mtl.d.is_some_and(|d| d < 1.0) || mtl.tr.is_some_and(|tr| tr > 0.0) || matches!(mtl.illum, Some(4 | 6 | 7 | 9)));
+    if transparent {
+        let glass = Dielectric::new(mtl.ni.unwrap_or(1.5) as f64);
+        return Ok(Arc::new(build_mesh(data, polys, glass, subdivision)?).triangles());
+    }
+
+    let kd = color(mtl.kd).unwrap_or(Vec3::new(0.8, 0.8, 0.8));
+    let diffuse = match mtl.map_kd.as_ref().map(texture_file) {
+        Some(file) if file.exists() => Texture::ImageTex(ImageTexture::new(&file.to_string_lossy())),
+        Some(file) => {
+            eprintln!("WARNING: texture '{}' not found, using Kd instead.", file.display());
+            SolidColor(kd)
+        }
+        None => SolidColor(kd),
+    };
+    let specular = color(mtl.ks).unwrap_or(Vec3::zero());
+    let shiny = specular.x > 0.0 || specular.y > 0.0 || specular.z > 0.0;
+    match mtl.ns {
+        Some(ns) if shiny && !matches!(mtl.illum, Some(0 | 1)) => {
+            cutout_triangles(data, polys, Glossy::new(diffuse, specular, ns as f64), alpha, subdivision)
+        }
+        _ => cutout_triangles(data, polys, Lambertian::set_texture(diffuse), alpha, subdivision),
+    }
+}
+/// 有不透明度时给材质套上镂空再建网格
+fn cutout_triangles<'a, Mat: Material + Sync + Send + 'static>(
+    data: &ObjData,
+    polys: impl Iterator<Item = &'a SimplePolygon>,
+    material: Mat,
+    alpha: Option<Texture>,
+    subdivision: Option<Subdivision>,
+) -> Result<HittableList, ObjLoadError> {
+    let triangles = match alpha {
+        Some(alpha) => Arc::new(build_mesh(data, polys, Cutout::new(material, alpha), subdivision)?).triangles(),
+        None => Arc::new(build_mesh(data, polys, material, subdivision)?).triangles(),
+    };
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::temp_dir::TempDir;
+    use image::{Rgb, RgbImage};
+
+    fn area(points: &[Vec3], triangles: &[[usize; 3]]) -> f64 {
+        triangles.iter()
+            .map(|&[a, b, c]| Vec3::cross(points[b] - points[a], points[c] - points[a]).length() / 2.0)
+            .sum()
+    }
 
+    #[test]
+    fn triangulate_quad() {
+        let points = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), 2);
+        assert!((area(&points, &triangles) - 1.0).abs() < 1e-12);
+    }
+
+    // An L shape, where a fan from the first vertex would fill in the notch.
+    #[test]
+    fn triangulate_concave_polygon() {
+        let points = [
+            Vec3::new(2.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 2.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+        ];
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), 4);
+        assert!((area(&points, &triangles) - 3.0).abs() < 1e-12);
+        for [a, b, c] in triangles {
+            assert!(Vec3::cross(points[b] - points[a], points[c] - points[a]).z > 0.0);
+        }
+    }
+
+    #[test]
+    fn triangulate_keeps_winding() {
+        let points = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(1.5, 0.0, 0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+        ];
+        for [a, b, c] in triangulate(&points) {
+            let normal = Vec3::cross(points[b] - points[a], points[c] - points[a]);
+            assert!(normal.y > 0.0);
+        }
+    }
+    // One triangle per material in a row along x, seen from +z.
+    const MATERIALS: [&str; 8] = ["light", "half_opaque", "filter", "glass", "shiny", "matte", "textured", "no_highlight"];
+
+    const MTL: &str = "\
+newmtl light
+Kd 0.5 0.5 0.5
+Ke 4 3 2
+newmtl half_opaque
+Kd 0.5 0.5 0.5
+d 0.5
+Ni 1.3
+newmtl filter
+Tr 0.3
+newmtl glass
+illum 7
+newmtl shiny
+Kd 0.2 0.2 0.2
+Ks 0.8 0.6 0.4
+Ns 50
+newmtl matte
+Kd 0.5 0.25 0.125
+newmtl textured
+Kd 0.5 0.5 0.5
+map_Kd red.png
+newmtl no_highlight
+Kd 0.5 0.25 0.125
+Ks 0.8 0.8 0.8
+Ns 50
+illum 1
+";
+
+    fn row_of_triangles() -> String {
+        let mut obj = String::from("mtllib row.mtl\nvt 0 0\nvt 1 0\nvt 0 1\n");
+        for (i, name) in MATERIALS.iter().enumerate() {
+            let x = 2.0 * i as f64;
+            obj += &format!("v {} 0 0\nv {} 0 0\nv {} 1 0\nusemtl {}\n", x, x + 1.0, x, name);
+            obj += &format!("f {}/1 {}/2 {}/3\n", 3 * i + 1, 3 * i + 2, 3 * i + 3);
+        }
+        obj
+    }
+
+    #[test]
+    fn mtl_materials_map_to_ray_tracer_materials() {
+        let dir = TempDir::new("mtl");
+        dir.write("row.mtl", MTL);
+        RgbImage::from_pixel(1, 1, Rgb([255, 0, 0])).save(dir.join("red.png")).unwrap();
+        let world = load_obj(dir.write("row.obj", row_of_triangles()), None, ImportOptions::default()).unwrap();
+
+        let pi = std::f64::consts::PI;
+        // the MTL values are read as f32
+        let assert_near = |a: Vec3, b: Vec3, name: &str| assert!((a - b).length() < 1e-6, "{}: {:?} != {:?}", name, a, b);
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        let slanted = Vec3::new(0.6, 0.0, 0.8);
+        for (i, &name) in MATERIALS.iter().enumerate() {
+            let ray = Ray::new(Vec3::new(2.0 * i as f64 + 0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+            let mut rec = HitRecord::new();
+            assert!(world.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec), "{}", name);
+            let material = rec.material;
+            let emitted = material.emitted(rec.u, rec.v, &rec.p);
+            let (mirror, side) = (material.eval(&ray, &rec, up), material.eval(&ray, &rec, slanted));
+            match name {
+                // Ke makes a light whatever else is set
+                "light" => assert_near(emitted, Vec3::new(4.0, 3.0, 2.0), name),
+                // d, Tr and illum make glass, which only scatters specularly and never tints
+                "half_opaque" | "filter" | "glass" => {
+                    assert_near(emitted + mirror + side, Vec3::zero(), name);
+                    let (_, attenuation) = material.scatter(&ray, &rec).unwrap();
+                    assert_near(attenuation, Vec3::ones(), name);
+                }
+                // Ks with Ns adds a Phong highlight around the mirror direction
+                "shiny" => {
+                    let diffuse = Vec3::new(0.2, 0.2, 0.2) / pi;
+                    let highlight = Vec3::new(0.8, 0.6, 0.4) * (52.0 / (2.0 * pi));
+                    assert_near(mirror, diffuse + highlight, name);
+                    let off_peak = Vec3::new(0.8, 0.6, 0.4) * (52.0 / (2.0 * pi) * 0.8f64.powf(50.0));
+                    assert_near(side, (diffuse + off_peak) * 0.8, name);
+                    assert_near(material.eval(&ray, &rec, -slanted), Vec3::zero(), name);
+                }
+                // Kd alone, or a highlight switched off by illum 1, is diffuse
+                "matte" | "no_highlight" => {
+                    assert_near(mirror, Vec3::new(0.5, 0.25, 0.125) / pi, name);
+                    assert_near(side, Vec3::new(0.5, 0.25, 0.125) * (0.8 / pi), name);
+                }
+                // map_Kd replaces Kd
+                "textured" => assert_near(mirror, Vec3::new(1.0, 0.0, 0.0) / pi, name),
+                _ => unreachable!(),
+            }
+            if name != "light" {
+                assert_near(emitted, Vec3::zero(), name);
             }
         }
     }
 
-    let hittables: Vec<Arc<dyn Hittable + Sync + Send>> = triangles.iter().map(|t| t.clone() as Arc<dyn Hittable + Sync + Send>).collect();
-    let bvh = BvhNode::new(&hittables, 0, hittables.len());
+    #[test]
+    fn textures_are_found_next_to_their_mtl() {
+        let obj = "mtllib materials/box.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nusemtl textured\nf 1/1 2/1 3/1\n";
+        let dir = TempDir::new("mtl_dir");
+        dir.write("materials/box.mtl", "newmtl textured\nKd 0.5 0.5 0.5\nmap_Kd red.png\n");
+        RgbImage::from_pixel(1, 1, Rgb([255, 0, 0])).save(dir.join("materials/red.png")).unwrap();
+        let world = load_obj(dir.write("scene.obj", obj), None, ImportOptions::default()).unwrap();
 
-    let mut hittable_list = HittableList::new();
-    hittable_list.add(Arc::new(bvh));
+        let ray = Ray::new(Vec3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(world.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        let red = rec.material.eval(&ray, &rec, Vec3::new(0.0, 0.0, 1.0)) * std::f64::consts::PI;
+        assert!((red - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9, "{:?}", red);
+    }
 
-    hittable_list
+    #[test]
+    fn obj_without_faces_is_an_error() {
+        let dir = TempDir::new("no_faces");
+        let path = dir.write("points.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\n");
+        let white = || Lambertian::new(Vec3::ones());
+        let hittable_list = load_obj_to_hittable_list(&path, white(), ImportOptions::default());
+        let with_mtls = load_obj(&path, None, ImportOptions::default());
+        let simd = load_obj_to_simd_mesh::<_, _, wide::f64x4>(&path, white());
+        assert!(matches!(hittable_list, Err(ObjLoadError::NoFaces)));
+        assert!(matches!(with_mtls, Err(ObjLoadError::NoFaces)));
+        assert!(matches!(simd, Err(ObjLoadError::NoFaces)));
+    }
 }
@@ -0,0 +1,46 @@
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Orthonormal basis with `w` along a given axis, used by shapes that are easier
+/// to intersect in their own frame where the axis is `z`.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(axis: Vec3) -> Self {
+        let w = axis.unit();
+        let helper = if w.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = Vec3::cross(w, helper).unit();
+        let u = Vec3::cross(v, w);
+        Self { u, v, w }
+    }
+
+    /// Coordinates of the world vector `a` in this basis.
+    pub fn to_local(self, a: Vec3) -> Vec3 {
+        Vec3::new(a * self.u, a * self.v, a * self.w)
+    }
+
+    pub fn to_world(self, a: Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+
+    /// `ray` in the frame with this basis placed at `origin`. Lengths are kept, so a `t`
+    /// found in the frame is valid for the original ray.
+    pub fn local_ray(&self, origin: Vec3, ray: &Ray) -> Ray {
+        Ray::new_time(self.to_local(ray.origin - origin), self.to_local(ray.direction), ray.time)
+    }
+
+    /// Half extent along each world axis of a circle of `radius` perpendicular to `w`.
+    pub fn circle_extent(&self, radius: f64) -> Vec3 {
+        let w = self.w;
+        Vec3::new(
+            radius * (1.0 - w.x * w.x).max(0.0).sqrt(),
+            radius * (1.0 - w.y * w.y).max(0.0).sqrt(),
+            radius * (1.0 - w.z * w.z).max(0.0).sqrt(),
+        )
+    }
+}
@@ -0,0 +1,412 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use crate::hittable_list::HittableList;
+use crate::linear_bvh::LinearBvh;
+use crate::material::Material;
+use crate::obj_read::triangulate;
+use crate::triangle_mesh::{ImportOptions, TriangleMesh};
+use crate::vec3::Vec3;
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(io::Error),
+    /// The header or the data does not follow the PLY format.
+    Invalid(String),
+    /// There are no faces to build a BVH over.
+    NoFaces,
+}
+
+impl std::error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlyError::Io(e) => Some(e),
+            PlyError::Invalid(_) | PlyError::NoFaces => None,
+        }
+    }
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::Io(e) => write!(f, "Failed to read PLY file: {}", e),
+            PlyError::Invalid(message) => write!(f, "Invalid PLY file: {}", message),
+            PlyError::NoFaces => write!(f, "The PLY file has no faces"),
+        }
+    }
+}
+
+impl From<io::Error> for PlyError {
+    fn from(e: io::Error) -> Self {
+        PlyError::Io(e)
+    }
+}
+
+fn invalid<T>(message: impl Into<String>) -> Result<T, PlyError> {
+    Err(PlyError::Invalid(message.into()))
+}
+
+/// Loads a PLY file into a BVH over a shared-vertex mesh, like `load_obj_to_hittable_list`.
+pub(crate) fn load_ply_to_hittable_list<P: AsRef<Path>, Mat: Material + Sync + Send + 'static>(
+    path: P,
+    material: Mat,
+    options: ImportOptions,
+) -> Result<HittableList, PlyError> {
+    let mesh = Arc::new(load_ply_mesh(path, material, options)?);
+    if mesh.indices.is_empty() {
+        return Err(PlyError::NoFaces);
+    }
+    Ok(HittableList::set(Arc::new(LinearBvh::set(mesh.triangles()))))
+}
+
+/// Loads an ASCII or binary PLY file. Normals, texture coordinates and vertex colors are
+/// kept when the vertices have them, polygons are split into triangles, and elements
+/// other than `vertex` and `face` are skipped.
+pub(crate) fn load_ply_mesh<P: AsRef<Path>, Mat: Material + Sync + Send + 'static>(
+    path: P,
+    material: Mat,
+    options: ImportOptions,
+) -> Result<TriangleMesh<Mat>, PlyError> {
+    let ply = parse_ply(&std::fs::read(path)?)?;
+    let mut indices = Vec::new();
+    for face in &ply.faces {
+        if let Some(&index) = face.iter().find(|&&i| i as usize >= ply.positions.len()) {
+            return invalid(format!("face uses vertex {} of {}", index, ply.positions.len()));
+        }
+        let points: Vec<Vec3> = face.iter().map(|&i| ply.positions[i as usize]).collect();
+        for [a, b, c] in triangulate(&points) {
+            indices.push([face[a], face[b], face[c]]);
+        }
+    }
+    Ok(TriangleMesh::new(ply.positions, ply.normals, ply.uvs, indices, material).with_colors(ply.colors).transformed(&options))
+}
+
+/// Vertex and face data of a PLY file, the optional buffers are empty when missing.
+struct PlyMesh {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    colors: Vec<Vec3>,
+    faces: Vec<Vec<u32>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Scalar {
+    Char,
+    Uchar,
+    Short,
+    Ushort,
+    Int,
+    Uint,
+    Float,
+    Double,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar, PlyError> {
+        Ok(match name {
+            "char" | "int8" => Scalar::Char,
+            "uchar" | "uint8" => Scalar::Uchar,
+            "short" | "int16" => Scalar::Short,
+            "ushort" | "uint16" => Scalar::Ushort,
+            "int" | "int32" => Scalar::Int,
+            "uint" | "uint32" => Scalar::Uint,
+            "float" | "float32" => Scalar::Float,
+            "double" | "float64" => Scalar::Double,
+            _ => return invalid(format!("unknown property type '{}'", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::Char | Scalar::Uchar => 1,
+            Scalar::Short | Scalar::Ushort => 2,
+            Scalar::Int | Scalar::Uint | Scalar::Float => 4,
+            Scalar::Double => 8,
+        }
+    }
+
+    /// Value that stands for full intensity when the type is used for a color channel.
+    fn full_color(self) -> f64 {
+        match self {
+            Scalar::Char => i8::MAX as f64,
+            Scalar::Uchar => u8::MAX as f64,
+            Scalar::Short => i16::MAX as f64,
+            Scalar::Ushort => u16::MAX as f64,
+            Scalar::Int => i32::MAX as f64,
+            Scalar::Uint => u32::MAX as f64,
+            Scalar::Float | Scalar::Double => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads the values of the data section one at a time, whatever the encoding.
+struct Body<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    encoding: Encoding,
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: Scalar) -> Result<f64, PlyError> {
+        if self.encoding == Encoding::Ascii {
+            while self.bytes.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            let start = self.pos;
+            while self.bytes.get(self.pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            let word = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+            return match word.parse() {
+                Ok(value) => Ok(value),
+                Err(_) if word.is_empty() => invalid("unexpected end of data"),
+                Err(_) => invalid(format!("'{}' is not a number", word)),
+            };
+        }
+        let size = ty.size();
+        let Some(bytes) = self.bytes.get(self.pos..self.pos + size) else {
+            return invalid("unexpected end of data");
+        };
+        self.pos += size;
+        let mut raw = [0u8; 8];
+        raw[..size].copy_from_slice(bytes);
+        if self.encoding == Encoding::BinaryBigEndian {
+            raw[..size].reverse();
+        }
+        Ok(match ty {
+            Scalar::Char => raw[0] as i8 as f64,
+            Scalar::Uchar => raw[0] as f64,
+            Scalar::Short => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+            Scalar::Ushort => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+            Scalar::Int => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            Scalar::Uint => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            Scalar::Float => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            Scalar::Double => f64::from_le_bytes(raw),
+        })
+    }
+
+    /// Reads a list length or a vertex index, which must be a whole number that fits a `u32`.
+    fn read_index(&mut self, ty: Scalar) -> Result<u32, PlyError> {
+        let value = self.read(ty)?;
+        if value.fract() != 0.0 || !(0.0..=u32::MAX as f64).contains(&value) {
+            return invalid(format!("{} is not a valid index", value));
+        }
+        Ok(value as u32)
+    }
+}
+
+fn parse_header(header: &str) -> Result<(Encoding, Vec<Element>), PlyError> {
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return invalid("missing 'ply' magic");
+    }
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::BinaryLittleEndian,
+                    "binary_big_endian" => Encoding::BinaryBigEndian,
+                    _ => return invalid(format!("unknown format '{}'", format)),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().or_else(|_| invalid(format!("bad element count '{}'", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => match elements.last_mut() {
+                Some(element) => element.properties.push(Property::List(name.to_string(), Scalar::parse(count)?, Scalar::parse(item)?)),
+                None => return invalid("property before any element"),
+            },
+            ["property", ty, name] => match elements.last_mut() {
+                Some(element) => element.properties.push(Property::Scalar(name.to_string(), Scalar::parse(ty)?)),
+                None => return invalid("property before any element"),
+            },
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return invalid(format!("unexpected header line '{}'", line)),
+        }
+    }
+    match encoding {
+        Some(encoding) => Ok((encoding, elements)),
+        None => invalid("missing format line"),
+    }
+}
+
+fn parse_ply(bytes: &[u8]) -> Result<PlyMesh, PlyError> {
+    const END: &[u8] = b"end_header";
+    let Some(end) = bytes.windows(END.len()).position(|w| w == END) else {
+        return invalid("missing end_header");
+    };
+    // the data starts on the line after end_header
+    let data = match bytes[end..].iter().position(|&b| b == b'\n') {
+        Some(newline) => end + newline + 1,
+        None => bytes.len(),
+    };
+    let header = String::from_utf8_lossy(&bytes[..end]);
+    let (encoding, elements) = parse_header(&header)?;
+    let mut body = Body { bytes, pos: data, encoding };
+
+    let mut mesh = PlyMesh { positions: Vec::new(), normals: Vec::new(), uvs: Vec::new(), colors: Vec::new(), faces: Vec::new() };
+    for element in &elements {
+        let find = |names: &[&str]| element.properties.iter().position(|p| matches!(p, Property::Scalar(name, _) if names.contains(&name.as_str())));
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let uv = [find(&["u", "s", "texture_u", "texture_s"]), find(&["v", "t", "texture_v", "texture_t"])];
+        let color = [find(&["red", "r"]), find(&["green", "g"]), find(&["blue", "b"])];
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+
+        let mut row = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                match property {
+                    Property::Scalar(_, ty) => row[i] = body.read(*ty)?,
+                    Property::List(name, count, item) => {
+                        // the count comes from the file, so the list grows as the data is read
+                        let count = body.read_index(*count)?;
+                        let mut list = Vec::new();
+                        for _ in 0..count {
+                            list.push(body.read_index(*item)?);
+                        }
+                        if is_face && (name == "vertex_indices" || name == "vertex_index") {
+                            mesh.faces.push(list);
+                        }
+                    }
+                }
+            }
+            if !is_vertex {
+                continue;
+            }
+            let vec3 = |[x, y, z]: [Option<usize>; 3]| match (x, y, z) {
+                (Some(x), Some(y), Some(z)) => Some(Vec3::new(row[x], row[y], row[z])),
+                _ => None,
+            };
+            match vec3(position) {
+                Some(p) => mesh.positions.push(p),
+                None => return invalid("vertex without x, y and z"),
+            }
+            if let Some(n) = vec3(normal) {
+                mesh.normals.push(n.unit());
+            }
+            if let [Some(u), Some(v)] = uv {
+                mesh.uvs.push((row[u], row[v]));
+            }
+            if let Some(c) = vec3(color) {
+                let full = |i: Option<usize>| match element.properties[i.unwrap()] {
+                    Property::Scalar(_, ty) => ty.full_color(),
+                    Property::List(..) => 1.0,
+                };
+                mesh.colors.push(Vec3::new(c.x / full(color[0]), c.y / full(color[1]), c.z / full(color[2])));
+            }
+        }
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::temp_dir::TempDir;
+
+    const HEADER: &str = "element vertex 4\n\
+        property float x\nproperty float y\nproperty float z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn binary(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = format!("ply\nformat {} 1.0\ncomment made by hand\n{}", format, HEADER).into_bytes();
+        let vertices = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        for (i, v) in vertices.iter().enumerate() {
+            for x in v {
+                bytes.extend(if big_endian { x.to_be_bytes() } else { x.to_le_bytes() });
+            }
+            bytes.extend([255, (i * 85) as u8, 0]);
+        }
+        bytes.push(4);
+        for i in 0..4i32 {
+            bytes.extend(if big_endian { i.to_be_bytes() } else { i.to_le_bytes() });
+        }
+        bytes
+    }
+
+    fn check(mesh: &PlyMesh) {
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.positions[2], Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.colors[1], Vec3::new(1.0, 1.0 / 3.0, 0.0));
+        assert!(mesh.normals.is_empty() && mesh.uvs.is_empty());
+        assert_eq!(mesh.faces, vec![vec![0, 1, 2, 3]]);
+    }
+
+    #[test]
+    fn parse_ascii() {
+        let text = format!("ply\nformat ascii 1.0\n{}0 0 0 255 0 0\n1 0 0 255 85 0\n1 1 0 255 170 0\n0 1 0 255 255 0\n4 0 1 2 3\n", HEADER);
+        check(&parse_ply(text.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn parse_binary_little_endian() {
+        check(&parse_ply(&binary("binary_little_endian", false)).unwrap());
+    }
+
+    #[test]
+    fn parse_binary_big_endian() {
+        check(&parse_ply(&binary("binary_big_endian", true)).unwrap());
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let bytes = binary("binary_little_endian", false);
+        assert!(matches!(parse_ply(&bytes[..bytes.len() - 2]), Err(PlyError::Invalid(_))));
+    }
+
+    #[test]
+    fn bad_indices_are_an_error() {
+        let ascii = |face: &str| format!("ply\nformat ascii 1.0\n{}0 0 0 255 0 0\n1 0 0 255 85 0\n1 1 0 255 170 0\n0 1 0 255 255 0\n{}\n", HEADER, face);
+        for face in ["4 0 1 -2 3", "4 0 1.5 2 3", "-4 0 1 2 3", "4 0 1 2 5e9"] {
+            assert!(matches!(parse_ply(ascii(face).as_bytes()), Err(PlyError::Invalid(_))), "{}", face);
+        }
+
+        // a huge list count runs out of data instead of allocating it up front
+        let bytes = binary("binary_little_endian", false);
+        let end = bytes.windows(10).position(|w| w == b"end_header").unwrap();
+        let header = String::from_utf8(bytes[..end].to_vec()).unwrap().replacen("list uchar int", "list uint int", 1);
+        // the face is the last 17 bytes, a uchar count and four ints
+        let face = bytes.len() - 17;
+        let bytes = [header.as_bytes(), &bytes[end..face], &u32::MAX.to_le_bytes(), &bytes[face + 1..]].concat();
+        assert!(matches!(parse_ply(&bytes), Err(PlyError::Invalid(_))));
+    }
+
+    #[test]
+    fn no_faces_is_an_error() {
+        let dir = TempDir::new("empty_ply");
+        let text = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 0\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n";
+        let result = load_ply_to_hittable_list(dir.write("empty.ply", text), Lambertian::new(Vec3::ones()), ImportOptions::default());
+        assert!(matches!(result, Err(PlyError::NoFaces)));
+    }
+}
@@ -4,9 +4,24 @@ use crate::hittable::{HitRecord, Hittable};
 use crate::hittable_list::HittableList;
 use crate::interval::Interval;
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 
+/// The part of the plane through `q` spanned by `u` and `v` that a `quad` covers,
+/// decided from the planar coordinates of the hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaneShape {
+    /// `q`, `q+u`, `q+u+v`, `q+v`.
+    Parallelogram,
+    /// `q`, `q+u`, `q+v`.
+    Triangle,
+    /// Centered on `q` with semi-axes `u` and `v`.
+    Ellipse,
+    /// The ellipse minus the same ellipse scaled by the inner fraction.
+    Annulus(f64),
+}
+
 pub struct quad <Mat: Material+ Sync + Send> {
     pub(crate) q: Vec3,
     pub(crate) u: Vec3,
@@ -16,6 +31,7 @@ pub struct quad <Mat: Material+ Sync + Send> {
     pub(crate) bbox: Aabb,
     pub(crate) d: f64,
     pub(crate) normal: Vec3,
+    pub(crate) shape: PlaneShape,
 }
 
 impl<Mat: Material + Clone + Sync + Send+ 'static>   quad<Mat> {
@@ -36,15 +52,86 @@ impl<Mat: Material + Clone + Sync + Send+ 'static>   quad<Mat> {
             bbox,
             d,
             normal,
+            shape: PlaneShape::Parallelogram,
         }
     }
 
-    pub fn is_interior(a: f64, b: f64) -> Option<(f64, f64)> {
+    pub fn triangle(q: Vec3, u: Vec3, v: Vec3, mat: Mat) -> Self {
+        let mut triangle = quad::new(q, u, v, mat);
+        triangle.shape = PlaneShape::Triangle;
+        triangle.bbox = Aabb::surrounding_box(Aabb::set(q, q + u), Aabb::set(q, q + v));
+        triangle
+    }
+
+    pub fn ellipse(center: Vec3, u: Vec3, v: Vec3, mat: Mat) -> Self {
+        let mut ellipse = quad::new(center, u, v, mat);
+        ellipse.shape = PlaneShape::Ellipse;
+        ellipse.bbox = Aabb::surrounding_box(Aabb::set(center - u - v, center + u + v), Aabb::set(center + u - v, center - u + v));
+        ellipse
+    }
+
+    /// A ring whose hole is `inner` (between 0 and 1) times the size of the outer ellipse.
+    pub fn annulus(center: Vec3, u: Vec3, v: Vec3, inner: f64, mat: Mat) -> Self {
+        let mut annulus = quad::ellipse(center, u, v, mat);
+        annulus.shape = PlaneShape::Annulus(inner.clamp(0.0, 1.0));
+        annulus
+    }
+
+    /// A round disk facing `normal`.
+    pub fn disk(center: Vec3, normal: Vec3, radius: f64, mat: Mat) -> Self {
+        let onb = Onb::new(normal);
+        quad::ellipse(center, onb.u * radius, onb.v * radius, mat)
+    }
+
+    /// Texture coordinates of the planar coordinates `(a, b)` if the shape covers them.
+    pub fn is_interior(&self, a: f64, b: f64) -> Option<(f64, f64)> {
         let unit_interval = Interval::set(0.0, 1.0);
-        if !unit_interval.contains(a) || !unit_interval.contains(b) {
+        match self.shape {
+            PlaneShape::Parallelogram => {
+                if !unit_interval.contains(a) || !unit_interval.contains(b) {
+                    return None;
+                }
+                Some((a, b))
+            }
+            PlaneShape::Triangle => {
+                if a < 0.0 || b < 0.0 || a + b > 1.0 {
+                    return None;
+                }
+                Some((a, b))
+            }
+            PlaneShape::Ellipse | PlaneShape::Annulus(_) => {
+                let r2 = a * a + b * b;
+                let inner = match self.shape {
+                    PlaneShape::Annulus(inner) => inner,
+                    _ => 0.0,
+                };
+                if r2 > 1.0 || r2 < inner * inner {
+                    return None;
+                }
+                Some((a / 2.0 + 0.5, b / 2.0 + 0.5))
+            }
+        }
+    }
+    /// `t`, hit point and texture coordinates where `r` crosses the quad inside `ray_t`
+    /// and the material is opaque.
+    fn intersect(&self, r: &Ray, ray_t: Interval) -> Option<(f64, Vec3, f64, f64)> {
+        let denom = self.normal * r.direction;
+        if denom.abs() < 1e-8 {
             return None;
         }
-        Some((a, b))
+        let t = (self.d - self.normal * r.origin) / denom;
+        if !ray_t.contains(t) {
+            return None;
+        }
+        let p = r.at(t);
+        let planar_hitpt_vector = p - self.q;
+        let alpha = self.w * Vec3::cross(planar_hitpt_vector, self.v);
+        let beta = self.w * Vec3::cross(self.u, planar_hitpt_vector);
+        let (a, b) = self.is_interior(alpha, beta)?;
+        if !self.mat.is_opaque(a, b, &p) {
+            return None;
+        }
+        Some((t, p, a, b))
     }
     pub fn bx(a:Vec3,b:Vec3,mat:Mat)->  HittableList{
         let mut sides=HittableList::new();
@@ -64,28 +151,23 @@ impl<Mat: Material + Clone + Sync + Send+ 'static>   quad<Mat> {
 }
 
 impl<Mat: Material + Clone + Sync + Send+ 'static>Hittable for quad<Mat> {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let denom = self.normal * r.direction;
-        if denom.abs() < 1e-8 {
-            return false;
-        }
-        let t = (self.d - self.normal * r.origin) / denom;
-        if !ray_t.contains(t) {
-            return false;
-        }
-        let p = r.at(t);
-        let planar_hitpt_vector = p - self.q;
-        let alpha = self.w * Vec3::cross(planar_hitpt_vector, self.v);
-        let beta = self.w * Vec3::cross(self.u, planar_hitpt_vector);
-        let (a, b) = match quad::<Mat>::is_interior(alpha, beta) {
-            Some((a, b)) => (a, b),
+    fn hit<'a>(&'a self, r: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (t, p, a, b) = match self.intersect(r, ray_t) {
+            Some(hit) => hit,
             None => return false,
         };
         rec.t = t;
         rec.p = p;
         rec.u = a;
         rec.v = b;
-        rec.material =  Box::new(self.mat.clone());
+        (rec.dpdu, rec.dpdv) = match self.shape {
+            // u, v run from 0 to 1 across the whole ellipse, which spans twice its semi-axes
+            PlaneShape::Ellipse | PlaneShape::Annulus(_) => (self.u * 2.0, self.v * 2.0),
+            _ => (self.u, self.v),
+        };
+        rec.tangent = Vec3::zero();
+        rec.color = Vec3::ones();
+        rec.material = &self.mat;
         rec.normal = self.normal;
         rec.front_face = true;
         rec.set_face_normal(*r, self.normal);
@@ -95,4 +177,79 @@ impl<Mat: Material + Clone + Sync + Send+ 'static>Hittable for quad<Mat> {
     fn bounding_box(&self) -> Option<Aabb> {
         Some(self.bbox)
     }
+
+    fn occluded(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.intersect(r, ray_t).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn white() -> Lambertian {
+        Lambertian::new(Vec3::ones())
+    }
+
+    fn unit(shape: fn(Vec3, Vec3, Vec3, Lambertian) -> quad<Lambertian>) -> quad<Lambertian> {
+        shape(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), white())
+    }
+
+    #[test]
+    fn parallelogram_interior() {
+        let square = unit(quad::new);
+        assert_eq!(square.is_interior(0.0, 0.0), Some((0.0, 0.0)));
+        assert_eq!(square.is_interior(1.0, 1.0), Some((1.0, 1.0)));
+        assert_eq!(square.is_interior(-1e-9, 0.5), None);
+        assert_eq!(square.is_interior(0.5, 1.0 + 1e-9), None);
+    }
+
+    #[test]
+    fn triangle_interior() {
+        let triangle = unit(quad::triangle);
+        assert_eq!(triangle.is_interior(0.5, 0.5), Some((0.5, 0.5)));
+        assert_eq!(triangle.is_interior(0.0, 1.0), Some((0.0, 1.0)));
+        assert_eq!(triangle.is_interior(0.5, 0.5 + 1e-9), None);
+        assert_eq!(triangle.is_interior(-1e-9, 0.2), None);
+        assert_eq!(triangle.is_interior(0.9, 0.9), None);
+    }
+
+    #[test]
+    fn ellipse_and_annulus_interior() {
+        // the whole ellipse maps onto the unit square of texture coordinates
+        let ellipse = unit(quad::ellipse);
+        assert_eq!(ellipse.is_interior(0.0, 0.0), Some((0.5, 0.5)));
+        assert_eq!(ellipse.is_interior(1.0, 0.0), Some((1.0, 0.5)));
+        assert_eq!(ellipse.is_interior(0.0, -1.0), Some((0.5, 0.0)));
+        assert!(ellipse.is_interior(0.6, 0.8).is_some());
+        assert_eq!(ellipse.is_interior(0.8, 0.61), None);
+
+        let annulus = quad::annulus(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.5, white());
+        assert_eq!(annulus.is_interior(0.5, 0.0), Some((0.75, 0.5)));
+        assert_eq!(annulus.is_interior(1.0, 0.0), Some((1.0, 0.5)));
+        assert_eq!(annulus.is_interior(0.49, 0.0), None);
+        assert_eq!(annulus.is_interior(0.0, 0.0), None);
+        assert_eq!(annulus.is_interior(0.0, 1.01), None);
+    }
+
+    #[test]
+    fn stretched_ellipse_and_disk_hits() {
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        let hits = |shape: &quad<Lambertian>, x: f64, y: f64| {
+            let mut rec = HitRecord::new();
+            shape.hit(&Ray::new(Vec3::new(x, y, 5.0), down), Interval::set(0.001, f64::INFINITY), &mut rec)
+        };
+        // semi-axes 2 along x and 1 along y
+        let ellipse = quad::ellipse(Vec3::zero(), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), white());
+        assert!(hits(&ellipse, 1.9, 0.0) && hits(&ellipse, 0.0, 0.9));
+        assert!(!hits(&ellipse, 0.0, 1.1) && !hits(&ellipse, 1.5, 0.7));
+
+        let disk = quad::disk(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 2.0, white());
+        assert!(hits(&disk, 0.0, 0.0) && hits(&disk, 1.9, 0.0) && hits(&disk, 0.0, -1.9));
+        assert!(!hits(&disk, 1.5, 1.5));
+        let mut rec = HitRecord::new();
+        assert!(disk.hit(&Ray::new(Vec3::new(1.0, 1.0, 5.0), down), Interval::set(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.t - 5.0).abs() < 1e-9 && (rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+    }
 }
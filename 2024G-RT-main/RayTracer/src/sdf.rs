@@ -0,0 +1,361 @@
+use crate::AABB::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::vec3::Vec3;
+
+/// Steps after which a ray that is still creeping along a surface counts as a miss.
+const MAX_STEPS: usize = 256;
+/// How close to the surface sphere tracing stops, in world units.
+const SURFACE_EPSILON: f64 = 1e-5;
+
+/// A signed distance function, negative inside. Built from the primitives below, all
+/// centered on the origin, and combined with the methods that take `self`.
+#[derive(Clone, Debug)]
+pub enum Sdf {
+    Sphere(f64),
+    /// Box with the given half extents.
+    Cuboid(Vec3),
+    /// Ring around the `y` axis with major and minor radius.
+    Torus(f64, f64),
+    /// Segment from `a` to `b` thickened by the radius.
+    Capsule(Vec3, Vec3, f64),
+    /// Capped cylinder along `y` with radius and half height.
+    Cylinder(f64, f64),
+    Translate(Box<Sdf>, Vec3),
+    Scale(Box<Sdf>, f64),
+    /// Grows the shape by the radius, rounding off its edges.
+    Round(Box<Sdf>, f64),
+    Union(Box<Sdf>, Box<Sdf>),
+    Intersection(Box<Sdf>, Box<Sdf>),
+    Difference(Box<Sdf>, Box<Sdf>),
+    /// Union blended over a width of `k`.
+    SmoothUnion(Box<Sdf>, Box<Sdf>, f64),
+    SmoothIntersection(Box<Sdf>, Box<Sdf>, f64),
+    SmoothDifference(Box<Sdf>, Box<Sdf>, f64),
+    /// Copies with the given spacing per axis, `count` more on each side of the original.
+    Repeat(Box<Sdf>, Vec3, [u32; 3]),
+    /// Rotation around `y` by the given radians per unit of height.
+    Twist(Box<Sdf>, f64),
+}
+
+impl Sdf {
+    pub fn sphere(radius: f64) -> Self {
+        Sdf::Sphere(radius)
+    }
+    pub fn cuboid(half_extent: Vec3) -> Self {
+        Sdf::Cuboid(half_extent)
+    }
+    pub fn torus(major: f64, minor: f64) -> Self {
+        Sdf::Torus(major, minor)
+    }
+    pub fn capsule(a: Vec3, b: Vec3, radius: f64) -> Self {
+        Sdf::Capsule(a, b, radius)
+    }
+    pub fn cylinder(radius: f64, half_height: f64) -> Self {
+        Sdf::Cylinder(radius, half_height)
+    }
+    pub fn translate(self, offset: Vec3) -> Self {
+        Sdf::Translate(Box::new(self), offset)
+    }
+    pub fn scale(self, factor: f64) -> Self {
+        Sdf::Scale(Box::new(self), factor)
+    }
+    pub fn round(self, radius: f64) -> Self {
+        Sdf::Round(Box::new(self), radius)
+    }
+    pub fn union(self, other: Sdf) -> Self {
+        Sdf::Union(Box::new(self), Box::new(other))
+    }
+    pub fn intersection(self, other: Sdf) -> Self {
+        Sdf::Intersection(Box::new(self), Box::new(other))
+    }
+    pub fn difference(self, other: Sdf) -> Self {
+        Sdf::Difference(Box::new(self), Box::new(other))
+    }
+    pub fn smooth_union(self, other: Sdf, k: f64) -> Self {
+        Sdf::SmoothUnion(Box::new(self), Box::new(other), k)
+    }
+    pub fn smooth_intersection(self, other: Sdf, k: f64) -> Self {
+        Sdf::SmoothIntersection(Box::new(self), Box::new(other), k)
+    }
+    pub fn smooth_difference(self, other: Sdf, k: f64) -> Self {
+        Sdf::SmoothDifference(Box::new(self), Box::new(other), k)
+    }
+    /// The copies have to fit in their cell of `spacing`, or the distance overestimates.
+    pub fn repeat(self, spacing: Vec3, count: [u32; 3]) -> Self {
+        Sdf::Repeat(Box::new(self), spacing, count)
+    }
+    pub fn twist(self, rate: f64) -> Self {
+        Sdf::Twist(Box::new(self), rate)
+    }
+
+    pub fn distance(&self, p: Vec3) -> f64 {
+        match self {
+            Sdf::Sphere(radius) => p.length() - radius,
+            Sdf::Cuboid(half) => {
+                let q = Vec3::new(p.x.abs() - half.x, p.y.abs() - half.y, p.z.abs() - half.z);
+                let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+                outside + q.x.max(q.y).max(q.z).min(0.0)
+            }
+            Sdf::Torus(major, minor) => {
+                let ring = (p.x * p.x + p.z * p.z).sqrt() - major;
+                (ring * ring + p.y * p.y).sqrt() - minor
+            }
+            Sdf::Capsule(a, b, radius) => {
+                let (pa, ba) = (p - *a, *b - *a);
+                let h = ((pa * ba) / (ba * ba)).clamp(0.0, 1.0);
+                (pa - ba * h).length() - radius
+            }
+            Sdf::Cylinder(radius, half_height) => {
+                let dx = (p.x * p.x + p.z * p.z).sqrt() - radius;
+                let dy = p.y.abs() - half_height;
+                dx.max(dy).min(0.0) + (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt()
+            }
+            Sdf::Translate(sdf, offset) => sdf.distance(p - *offset),
+            Sdf::Scale(sdf, factor) => sdf.distance(p / *factor) * factor,
+            Sdf::Round(sdf, radius) => sdf.distance(p) - radius,
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Sdf::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion(a, b, k) => smooth_min(a.distance(p), b.distance(p), *k),
+            Sdf::SmoothIntersection(a, b, k) => -smooth_min(-a.distance(p), -b.distance(p), *k),
+            Sdf::SmoothDifference(a, b, k) => -smooth_min(-a.distance(p), b.distance(p), *k),
+            Sdf::Repeat(sdf, spacing, count) => {
+                // fold `p` into the nearest cell, clamped to the copies that exist
+                let fold = |x: f64, spacing: f64, count: u32| {
+                    if spacing <= 0.0 {
+                        return x;
+                    }
+                    let n = count as f64;
+                    x - spacing * (x / spacing).round().clamp(-n, n)
+                };
+                sdf.distance(Vec3::new(fold(p.x, spacing.x, count[0]), fold(p.y, spacing.y, count[1]), fold(p.z, spacing.z, count[2])))
+            }
+            Sdf::Twist(sdf, rate) => {
+                let (s, c) = (rate * p.y).sin_cos();
+                sdf.distance(Vec3::new(c * p.x - s * p.z, p.y, s * p.x + c * p.z))
+            }
+        }
+    }
+
+    /// A box the surface stays inside of.
+    pub(crate) fn bounds(&self) -> Aabb {
+        match self {
+            Sdf::Sphere(radius) => cube(*radius),
+            Sdf::Cuboid(half) => Aabb::set(-*half, *half),
+            Sdf::Torus(major, minor) => Aabb::set(Vec3::new(-major - minor, -minor, -major - minor), Vec3::new(major + minor, *minor, major + minor)),
+            Sdf::Capsule(a, b, radius) => grow(Aabb::set(*a, *b), *radius),
+            Sdf::Cylinder(radius, half_height) => Aabb::set(Vec3::new(-radius, -half_height, -radius), Vec3::new(*radius, *half_height, *radius)),
+            Sdf::Translate(sdf, offset) => sdf.bounds() + *offset,
+            Sdf::Scale(sdf, factor) => {
+                let inner = sdf.bounds();
+                let (min, max) = (corner(&inner, false) * *factor, corner(&inner, true) * *factor);
+                Aabb::set(min, max)
+            }
+            Sdf::Round(sdf, radius) => grow(sdf.bounds(), *radius),
+            Sdf::Union(a, b) => Aabb::surrounding_box(a.bounds(), b.bounds()),
+            Sdf::Intersection(a, b) | Sdf::SmoothIntersection(a, b, _) => overlap(a.bounds(), b.bounds()),
+            Sdf::Difference(a, _) | Sdf::SmoothDifference(a, _, _) => a.bounds(),
+            // the blend can bulge out by at most a quarter of its width
+            Sdf::SmoothUnion(a, b, k) => grow(Aabb::surrounding_box(a.bounds(), b.bounds()), k / 4.0),
+            Sdf::Repeat(sdf, spacing, count) => {
+                let inner = sdf.bounds();
+                let reach = Vec3::new(spacing.x.max(0.0) * count[0] as f64, spacing.y.max(0.0) * count[1] as f64, spacing.z.max(0.0) * count[2] as f64);
+                Aabb::set(corner(&inner, false) - reach, corner(&inner, true) + reach)
+            }
+            Sdf::Twist(sdf, _) => {
+                let inner = sdf.bounds();
+                let reach = twist_reach(&inner);
+                Aabb::new(Interval::set(-reach, reach), inner.y, Interval::set(-reach, reach))
+            }
+        }
+    }
+
+    /// How much faster than the distance to the surface `distance` can change. It is
+    /// 1 for a true distance, twists stretch space and overestimate it.
+    pub(crate) fn lipschitz(&self) -> f64 {
+        match self {
+            Sdf::Sphere(_) | Sdf::Cuboid(_) | Sdf::Torus(_, _) | Sdf::Capsule(_, _, _) | Sdf::Cylinder(_, _) => 1.0,
+            Sdf::Translate(sdf, _) | Sdf::Scale(sdf, _) | Sdf::Round(sdf, _) | Sdf::Repeat(sdf, _, _) => sdf.lipschitz(),
+            Sdf::Union(a, b)
+            | Sdf::Intersection(a, b)
+            | Sdf::Difference(a, b)
+            | Sdf::SmoothUnion(a, b, _)
+            | Sdf::SmoothIntersection(a, b, _)
+            | Sdf::SmoothDifference(a, b, _) => a.lipschitz().max(b.lipschitz()),
+            Sdf::Twist(sdf, rate) => sdf.lipschitz() * (1.0 + (rate * twist_reach(&sdf.bounds())).powi(2)).sqrt(),
+        }
+    }
+}
+
+/// Polynomial smooth minimum, see https://iquilezles.org/articles/smin/.
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k / 4.0
+}
+
+fn cube(half: f64) -> Aabb {
+    Aabb::set(Vec3::new(-half, -half, -half), Vec3::new(half, half, half))
+}
+
+fn corner(bbox: &Aabb, max: bool) -> Vec3 {
+    if max {
+        Vec3::new(bbox.x.max, bbox.y.max, bbox.z.max)
+    } else {
+        Vec3::new(bbox.x.min, bbox.y.min, bbox.z.min)
+    }
+}
+
+fn grow(bbox: Aabb, by: f64) -> Aabb {
+    let by = Vec3::new(by, by, by);
+    Aabb::set(corner(&bbox, false) - by, corner(&bbox, true) + by)
+}
+
+fn overlap(a: Aabb, b: Aabb) -> Aabb {
+    Aabb::new(Interval::overlap(&a.x, &b.x), Interval::overlap(&a.y, &b.y), Interval::overlap(&a.z, &b.z))
+}
+
+/// Farthest any point of `bbox` gets from the `y` axis.
+fn twist_reach(bbox: &Aabb) -> f64 {
+    let x = bbox.x.min.abs().max(bbox.x.max.abs());
+    let z = bbox.z.min.abs().max(bbox.z.max.abs());
+    (x * x + z * z).sqrt()
+}
+
+/// An `Sdf` placed in the scene at `center`, intersected by sphere tracing.
+pub struct SdfShape<Mat: Material + Sync + Send> {
+    pub sdf: Sdf,
+    pub center: Vec3,
+    pub mat: Mat,
+    pub bbox: Aabb,
+    /// Fraction of the distance that is safe to step, from `Sdf::lipschitz`.
+    step_scale: f64,
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> SdfShape<Mat> {
+    pub fn new(sdf: Sdf, center: Vec3, mat: Mat) -> Self {
+        let bbox = sdf.bounds() + center;
+        let step_scale = 1.0 / sdf.lipschitz();
+        SdfShape { sdf, center, mat, bbox, step_scale }
+    }
+
+    fn distance(&self, p: Vec3) -> f64 {
+        self.sdf.distance(p - self.center)
+    }
+
+    /// Marches from where `ray` enters the bounding box, stepping by the distance to the
+    /// surface until it is closer than `SURFACE_EPSILON`. Rays that start inside march
+    /// on the negated distance, so they find the surface from within.
+    fn trace(&self, ray: &Ray, ray_t: Interval) -> Option<f64> {
+        let span = self.bbox.clip(ray, ray_t)?;
+        let speed = ray.direction.length();
+        let mut t = span.min;
+        // coming from outside the box it is outside, whatever the surface on the box does
+        let mut side = 1.0;
+        if span.min <= ray_t.min {
+            if self.distance(ray.at(t)).abs() < SURFACE_EPSILON {
+                // a ray leaving the surface: look one step further to see which side it is on
+                t += 2.0 * SURFACE_EPSILON / speed;
+            }
+            side = self.distance(ray.at(t)).signum();
+        }
+        for _ in 0..MAX_STEPS {
+            let d = side * self.distance(ray.at(t)) * self.step_scale;
+            if d < SURFACE_EPSILON {
+                return if ray_t.surrounds(t) { Some(t) } else { None };
+            }
+            t += d / speed;
+            if t > span.max {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Gradient of the distance by central differences.
+    fn normal(&self, p: Vec3) -> Vec3 {
+        let h = 1e-4;
+        let dx = Vec3::new(h, 0.0, 0.0);
+        let dy = Vec3::new(0.0, h, 0.0);
+        let dz = Vec3::new(0.0, 0.0, h);
+        Vec3::new(
+            self.distance(p + dx) - self.distance(p - dx),
+            self.distance(p + dy) - self.distance(p - dy),
+            self.distance(p + dz) - self.distance(p - dz),
+        )
+        .unit()
+    }
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> Hittable for SdfShape<Mat> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let t = match self.trace(ray, ray_t) {
+            Some(t) => t,
+            None => return false,
+        };
+        let p = ray.at(t);
+        let normal = self.normal(p);
+        // there is no natural parameterization, map the normal like on a sphere
+        let (u, v) = Sphere::<Mat>::get_sphere_uv(normal);
+
+        rec.t = t;
+        rec.p = p;
+        rec.u = u;
+        rec.v = v;
+        // too rough a parameterization to bend normals with
+        rec.dpdu = Vec3::zero();
+        rec.dpdv = Vec3::zero();
+        rec.tangent = Vec3::zero();
+        rec.color = Vec3::ones();
+        rec.material = &self.mat;
+        rec.set_face_normal(*ray, normal);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.trace(ray, ray_t).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn primitive_distances() {
+        let p = Vec3::new(3.0, 0.0, 0.0);
+        assert!((Sdf::sphere(1.0).distance(p) - 2.0).abs() < 1e-12);
+        assert!((Sdf::cuboid(Vec3::new(1.0, 1.0, 1.0)).distance(p) - 2.0).abs() < 1e-12);
+        assert!((Sdf::cuboid(Vec3::new(1.0, 1.0, 1.0)).distance(Vec3::zero()) + 1.0).abs() < 1e-12);
+        assert!((Sdf::torus(2.0, 0.5).distance(p) - 0.5).abs() < 1e-12);
+        assert!((Sdf::sphere(1.0).scale(2.0).distance(p) - 1.0).abs() < 1e-12);
+        // the smooth union stays below the plain one
+        let a = Sdf::sphere(1.0).translate(Vec3::new(-1.0, 0.0, 0.0));
+        let b = Sdf::sphere(1.0).translate(Vec3::new(1.0, 0.0, 0.0));
+        let q = Vec3::new(0.0, 1.0, 0.0);
+        assert!(a.clone().smooth_union(b.clone(), 0.5).distance(q) < a.union(b).distance(q));
+    }
+
+    #[test]
+    fn trace_sphere_from_outside_and_inside() {
+        let shape = SdfShape::new(Sdf::sphere(1.0), Vec3::zero(), Lambertian::new(Vec3::ones()));
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        let t = shape.trace(&ray, Interval::set(0.001, f64::INFINITY)).unwrap();
+        assert!((ray.at(t).x + 1.0).abs() < 1e-4);
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0));
+        let t = shape.trace(&ray, Interval::set(0.001, f64::INFINITY)).unwrap();
+        assert!((t - 1.0).abs() < 1e-4);
+        assert!(shape.trace(&ray, Interval::set(0.001, 0.5)).is_none());
+    }
+}
@@ -0,0 +1,438 @@
+use std::ops::{Add, BitAnd, Div, Mul, Sub};
+use wide::{f32x4, f32x8, f64x4, CmpGe, CmpGt, CmpLe};
+use crate::AABB::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Below this depth SAH gives way to median cuts, as in `LinearBvh`.
+const MAX_SAH_DEPTH: usize = 64;
+const STACK_SIZE: usize = 128;
+
+/// A SIMD register of mesh coordinates. `f64x4` keeps full precision, `f32x4` and
+/// `f32x8` halve the memory of the mesh. Their vertices are stored rounded to `f32`, so
+/// even after the `f64` recheck of a hit its `t`, uv and normal are off from the source
+/// mesh by about `f32` precision.
+pub trait Lanes: Copy + Send + Sync + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + BitAnd<Output = Self> {
+    const WIDTH: usize;
+    fn splat(x: f64) -> Self;
+    /// Takes the first `WIDTH` values of `values`.
+    fn load(values: &[f64]) -> Self;
+    fn lane(self, i: usize) -> f64;
+    fn abs(self) -> Self;
+    fn ge(self, rhs: Self) -> Self;
+    fn gt(self, rhs: Self) -> Self;
+    fn le(self, rhs: Self) -> Self;
+    /// One bit per lane of a comparison result.
+    fn mask(self) -> u32;
+}
+
+macro_rules! impl_lanes {
+    ($ty:ty, $scalar:ty, $width:expr) => {
+        impl Lanes for $ty {
+            const WIDTH: usize = $width;
+            fn splat(x: f64) -> Self {
+                <$ty>::splat(x as $scalar)
+            }
+            fn load(values: &[f64]) -> Self {
+                let mut lanes = [0.0 as $scalar; $width];
+                for (lane, value) in lanes.iter_mut().zip(values) {
+                    *lane = *value as $scalar;
+                }
+                <$ty>::from(lanes)
+            }
+            fn lane(self, i: usize) -> f64 {
+                self.to_array()[i] as f64
+            }
+            fn abs(self) -> Self {
+                <$ty>::abs(self)
+            }
+            fn ge(self, rhs: Self) -> Self {
+                self.cmp_ge(rhs)
+            }
+            fn gt(self, rhs: Self) -> Self {
+                self.cmp_gt(rhs)
+            }
+            fn le(self, rhs: Self) -> Self {
+                self.cmp_le(rhs)
+            }
+            fn mask(self) -> u32 {
+                self.move_mask() as u32
+            }
+        }
+    };
+}
+
+impl_lanes!(f64x4, f64, 4);
+impl_lanes!(f32x4, f32, 4);
+impl_lanes!(f32x8, f32, 8);
+
+fn cross<L: Lanes>(a: [L; 3], b: [L; 3]) -> [L; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot<L: Lanes>(a: [L; 3], b: [L; 3]) -> L {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// `L::WIDTH` triangles in structure-of-arrays layout with their edges precomputed.
+/// Unused lanes hold degenerate triangles that never report a hit.
+#[derive(Clone, Copy)]
+struct Packet<L: Lanes> {
+    v0: [L; 3],
+    e1: [L; 3],
+    e2: [L; 3],
+}
+
+impl<L: Lanes> Packet<L> {
+    fn new(triangles: &[[Vec3; 3]]) -> Self {
+        let mut columns = [[0.0; 8]; 9];
+        for (i, [a, b, c]) in triangles.iter().enumerate() {
+            let (e1, e2) = (*b - *a, *c - *a);
+            for axis in 0..3 {
+                columns[axis][i] = a[axis];
+                columns[3 + axis][i] = e1[axis];
+                columns[6 + axis][i] = e2[axis];
+            }
+        }
+        let load = |first: usize| [L::load(&columns[first]), L::load(&columns[first + 1]), L::load(&columns[first + 2])];
+        Packet {
+            v0: load(0),
+            e1: load(3),
+            e2: load(6),
+        }
+    }
+
+    /// Möller-Trumbore on every lane at once, returns the lane mask of hits inside
+    /// `(t_min, t_max)` along with `t`.
+    fn intersect(&self, origin: [L; 3], direction: [L; 3], t_min: L, t_max: L) -> (u32, L) {
+        let zero = L::splat(0.0);
+        let one = L::splat(1.0);
+        let h = cross(direction, self.e2);
+        let a = dot(self.e1, h);
+        let f = one / a;
+        let s = [origin[0] - self.v0[0], origin[1] - self.v0[1], origin[2] - self.v0[2]];
+        let u = f * dot(s, h);
+        let q = cross(s, self.e1);
+        let v = f * dot(direction, q);
+        let t = f * dot(self.e2, q);
+        let valid = a.abs().gt(L::splat(1e-8))
+            & u.ge(zero)
+            & v.ge(zero)
+            & (u + v).le(one)
+            & t.gt(t_min)
+            & t.le(t_max);
+        (valid.mask(), t)
+    }
+
+    /// Vertex and edges of lane `i` widened back to `f64`.
+    fn triangle(&self, i: usize) -> (Vec3, Vec3, Vec3) {
+        let get = |c: &[L; 3]| Vec3::new(c[0].lane(i), c[1].lane(i), c[2].lane(i));
+        (get(&self.v0), get(&self.e1), get(&self.e2))
+    }
+
+    /// Redoes lane `i` in `f64` and returns its `t`, `u` and `v`, or `None` when `t` falls
+    /// outside `ray_t`. The lanes accept hits with reduced precision, so a lane just past
+    /// either end of the range can get through them.
+    fn recheck(&self, i: usize, ray: &Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
+        let (v0, e1, e2) = self.triangle(i);
+        let h = Vec3::cross(ray.direction, e2);
+        let f = 1.0 / (e1 * h);
+        let s = ray.origin - v0;
+        let q = Vec3::cross(s, e1);
+        let t = f * (e2 * q);
+        ray_t.surrounds(t).then(|| (t, f * (s * h), f * (ray.direction * q)))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct MeshNode {
+    bbox: Aabb,
+    /// Leaf: index of its packet. Interior: index of the second child.
+    offset: u32,
+    leaf: bool,
+    axis: u8,
+}
+
+/// Triangle mesh with one material whose leaves are packets of `L::WIDTH` triangles
+/// tested together with SIMD. Meant for large OBJ models; a single `Triangle` is
+/// still the better choice for a handful of faces.
+pub struct SimdMesh<Mat: Material + Sync + Send, L: Lanes = f64x4> {
+    nodes: Vec<MeshNode>,
+    packets: Vec<Packet<L>>,
+    mat: Mat,
+}
+
+impl<Mat: Material + Sync + Send, L: Lanes> SimdMesh<Mat, L> {
+    pub fn new(mut triangles: Vec<[Vec3; 3]>, mat: Mat) -> Self {
+        assert!(!triangles.is_empty(), "SimdMesh needs at least one triangle");
+        let mut mesh = SimdMesh {
+            nodes: Vec::with_capacity(2 * triangles.len() / L::WIDTH + 1),
+            packets: Vec::with_capacity(triangles.len() / L::WIDTH + 1),
+            mat,
+        };
+        mesh.build(&mut triangles, 0);
+        mesh
+    }
+
+    /// Full-sweep SAH over all three axes, only cutting at multiples of the packet width
+    /// so that every packet but the last one is full.
+    fn build(&mut self, triangles: &mut [[Vec3; 3]], depth: usize) {
+        let bounds = |t: &[Vec3; 3]| Aabb::surrounding_box(Aabb::set(t[0], t[1]), Aabb::set(t[0], t[2]));
+        let bbox = triangles.iter().map(bounds).reduce(Aabb::surrounding_box).unwrap();
+        let index = self.nodes.len();
+        if triangles.len() <= L::WIDTH {
+            self.nodes.push(MeshNode {
+                bbox,
+                offset: self.packets.len() as u32,
+                leaf: true,
+                axis: 0,
+            });
+            self.packets.push(Packet::new(triangles));
+            return;
+        }
+        let count = triangles.len();
+        let sort = |triangles: &mut [[Vec3; 3]], axis: usize| {
+            triangles.sort_unstable_by(|a, b| (a[0][axis] + a[1][axis] + a[2][axis]).total_cmp(&(b[0][axis] + b[1][axis] + b[2][axis])));
+        };
+        // cost in packets tested, relative to the parent box
+        let packets = |n: usize| n.div_ceil(L::WIDTH) as f64;
+        let mut best = (f64::INFINITY, bbox.longest_axis() as usize, (count / 2).div_ceil(L::WIDTH) * L::WIDTH);
+        let mut right_area = vec![0.0; count];
+        let axes = if depth < MAX_SAH_DEPTH { 0..3 } else { 0..0 };
+        for axis in axes {
+            sort(triangles, axis);
+            let mut acc = bounds(&triangles[count - 1]);
+            for i in (1..count).rev() {
+                acc = Aabb::surrounding_box(acc, bounds(&triangles[i]));
+                right_area[i] = acc.surface_area();
+            }
+            let mut acc = bounds(&triangles[0]);
+            for i in 1..count {
+                if i % L::WIDTH == 0 {
+                    let cost = packets(i) * acc.surface_area() + packets(count - i) * right_area[i];
+                    if cost < best.0 {
+                        best = (cost, axis, i);
+                    }
+                }
+                acc = Aabb::surrounding_box(acc, bounds(&triangles[i]));
+            }
+        }
+        let (_, axis, mid) = best;
+        if depth >= MAX_SAH_DEPTH || axis != 2 {
+            sort(triangles, axis);
+        }
+        self.nodes.push(MeshNode {
+            bbox,
+            offset: 0,
+            leaf: false,
+            axis: axis as u8,
+        });
+        let (left, right) = triangles.split_at_mut(mid);
+        self.build(left, depth + 1);
+        self.nodes[index].offset = self.nodes.len() as u32;
+        self.build(right, depth + 1);
+    }
+
+    /// Visits the leaves whose boxes `ray` enters within `ray_t`, near child first.
+    /// `visit` returns the new upper bound of the interval, or `None` to stop.
+    fn traverse(&self, ray: &Ray, ray_t: Interval, mut visit: impl FnMut(&Packet<L>, f64) -> Option<f64>) {
+        let inv_dir = Vec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        let dir_is_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        let mut t_max = ray_t.max;
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit_inv(ray.origin, inv_dir, Interval::set(ray_t.min, t_max)) {
+                if node.leaf {
+                    match visit(&self.packets[node.offset as usize], t_max) {
+                        Some(t) => t_max = t,
+                        None => return,
+                    }
+                } else {
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                return;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+    }
+}
+
+fn splat3<L: Lanes>(v: Vec3) -> [L; 3] {
+    [L::splat(v.x), L::splat(v.y), L::splat(v.z)]
+}
+
+impl<Mat: Material + Sync + Send, L: Lanes> Hittable for SimdMesh<Mat, L> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let origin = splat3::<L>(ray.origin);
+        let direction = splat3::<L>(ray.direction);
+        let t_min = L::splat(ray_t.min);
+        let mut closest = None;
+        self.traverse(ray, ray_t, |packet, t_max| {
+            let (mut mask, _) = packet.intersect(origin, direction, t_min, L::splat(t_max));
+            let mut t_max = t_max;
+            // every lane is rechecked, one rejected there leaves the others in the running
+            while mask != 0 {
+                let i = mask.trailing_zeros() as usize;
+                mask &= mask - 1;
+                if let Some((t, u, v)) = packet.recheck(i, ray, Interval::set(ray_t.min, t_max)) {
+                    t_max = t;
+                    closest = Some((packet.triangle(i), t, u, v));
+                }
+            }
+            Some(t_max)
+        });
+        let ((_, e1, e2), t, u, v) = match closest {
+            Some(hit) => hit,
+            None => return false,
+        };
+        rec.t = t;
+        rec.p = ray.at(rec.t);
+        rec.u = u;
+        rec.v = v;
+        rec.dpdu = e1;
+        rec.dpdv = e2;
+        rec.tangent = Vec3::zero();
+        rec.color = Vec3::ones();
+        rec.material = &self.mat;
+        rec.set_face_normal(*ray, Vec3::cross(e1, e2).unit());
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.nodes[0].bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        let origin = splat3::<L>(ray.origin);
+        let direction = splat3::<L>(ray.direction);
+        let t_min = L::splat(ray_t.min);
+        let mut blocked = false;
+        self.traverse(ray, ray_t, |packet, t_max| {
+            let (mut mask, _) = packet.intersect(origin, direction, t_min, L::splat(t_max));
+            // the same f64 recheck as `hit`, so the two never disagree
+            while mask != 0 && !blocked {
+                let i = mask.trailing_zeros() as usize;
+                mask &= mask - 1;
+                blocked = packet.recheck(i, ray, ray_t).is_some();
+            }
+            if blocked { None } else { Some(t_max) }
+        });
+        blocked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::linear_bvh::LinearBvh;
+    use crate::material::Lambertian;
+    use crate::triangle_mesh::TriangleMesh;
+
+    // a bumpy 12 by 12 height field, two triangles per cell
+    fn terrain() -> Vec<[Vec3; 3]> {
+        let height = |x: f64, y: f64| Vec3::new(x, y, 0.3 * (1.3 * x).sin() * (0.7 * y).cos());
+        let mut triangles = Vec::new();
+        for i in 0..12 {
+            for j in 0..12 {
+                let (x, y) = (i as f64 * 0.5, j as f64 * 0.5);
+                let (a, b, c, d) = (height(x, y), height(x + 0.5, y), height(x + 0.5, y + 0.5), height(x, y + 0.5));
+                triangles.push([a, b, c]);
+                triangles.push([a, c, d]);
+            }
+        }
+        triangles
+    }
+
+    // every triangle is shot through its centroid and a point near a corner
+    fn matches_scalar_triangles<L: Lanes>(tolerance: f64) {
+        let triangles = terrain();
+        let simd = SimdMesh::<_, L>::new(triangles.clone(), Lambertian::new(Vec3::ones()));
+        let positions: Vec<Vec3> = triangles.iter().flatten().copied().collect();
+        let indices = (0..triangles.len() as u32).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+        let mesh = Arc::new(TriangleMesh::new(positions, Vec::new(), Vec::new(), indices, Lambertian::new(Vec3::ones())));
+        let scalar = LinearBvh::set(mesh.triangles());
+
+        let direction = Vec3::new(0.05, -0.03, -1.0);
+        for [a, b, c] in &triangles {
+            for target in [(*a + *b + *c) / 3.0, *a * 0.8 + *b * 0.1 + *c * 0.1] {
+                let ray = Ray::new(target - direction * 5.0, direction);
+                let ray_t = Interval::set(0.001, f64::INFINITY);
+                let (mut expected, mut got) = (HitRecord::new(), HitRecord::new());
+                assert!(scalar.hit(&ray, ray_t, &mut expected));
+                assert!(simd.hit(&ray, ray_t, &mut got), "{} lanes missed {:?}", L::WIDTH, target);
+                assert!((got.t - expected.t).abs() < tolerance, "{} != {}", got.t, expected.t);
+                assert!((got.u - expected.u).abs() < tolerance && (got.v - expected.v).abs() < tolerance);
+                assert!((got.normal - expected.normal).length() < tolerance);
+                assert!((got.p - expected.p).length() < tolerance);
+
+                // nothing is reported beyond the range asked for
+                let short = Interval::set(0.001, expected.t * (1.0 - 1e-4));
+                assert!(!simd.hit(&ray, short, &mut got));
+                assert!(!simd.hit(&ray, Interval::set(expected.t * (1.0 + 1e-4), f64::INFINITY), &mut got));
+            }
+        }
+    }
+
+    #[test]
+    fn f64x4_matches_scalar_triangles() {
+        matches_scalar_triangles::<f64x4>(1e-9);
+    }
+
+    #[test]
+    fn f32x4_matches_scalar_triangles() {
+        matches_scalar_triangles::<f32x4>(1e-4);
+    }
+
+    #[test]
+    fn f32x8_matches_scalar_triangles() {
+        matches_scalar_triangles::<f32x8>(1e-4);
+    }
+
+    // two stacked triangles in one packet; as the range starts ever closer past the near one,
+    // lanes that only f32 still lets through must not hide the far one
+    fn lanes_rejected_in_f64_leave_the_others<L: Lanes>() {
+        let layer = |z: f64| [Vec3::new(-1.0, -1.0, z), Vec3::new(2.0, -1.0, z), Vec3::new(-1.0, 2.0, z)];
+        let mesh = SimdMesh::<_, L>::new(vec![layer(-0.3), layer(-1.7)], Lambertian::new(Vec3::ones()));
+        let ray = Ray::new(Vec3::new(0.1, 0.2, 0.0), Vec3::new(0.013, 0.027, -0.91));
+        let mut near = HitRecord::new();
+        assert!(mesh.hit(&ray, Interval::set(0.0, f64::INFINITY), &mut near));
+        for k in -200..200 {
+            let ray_t = Interval::set(near.t * (1.0 + k as f64 * 1e-9), f64::INFINITY);
+            let mut rec = HitRecord::new();
+            assert!(mesh.hit(&ray, ray_t, &mut rec), "{} lanes leaked at {}", L::WIDTH, ray_t.min);
+            assert!(ray_t.surrounds(rec.t));
+            assert!(mesh.occluded(&ray, ray_t));
+            let before = Interval::set(0.0, ray_t.min);
+            assert_eq!(mesh.hit(&ray, before, &mut rec), mesh.occluded(&ray, before));
+        }
+    }
+
+    #[test]
+    fn lanes_rejected_in_f64_are_skipped() {
+        lanes_rejected_in_f64_leave_the_others::<f64x4>();
+        lanes_rejected_in_f64_leave_the_others::<f32x4>();
+        lanes_rejected_in_f64_leave_the_others::<f32x8>();
+    }
+}
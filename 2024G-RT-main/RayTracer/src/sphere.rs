@@ -60,11 +60,21 @@ impl<Mat: Material + Clone + Sync + Send+ 'static> Sphere<Mat> {
         let v = theta / f64::PI();
         (u, v)
     }
-}
 
-impl<Mat: Material + Clone+ Sync + Send + 'static> Hittable for Sphere<Mat> {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let center = self.sphere_center(ray.time);
+    /// How a point of the sphere of radius `radius` moves with the `u`, `v` of
+    /// `get_sphere_uv`, at unit normal `n`. `dpdv` vanishes at the poles.
+    pub fn sphere_dpduv(n: Vec3, radius: f64) -> (Vec3, Vec3) {
+        let dpdu = Vec3::new(n.z, 0.0, -n.x) * (2.0 * f64::PI() * radius);
+        let sin_theta = (n.x * n.x + n.z * n.z).sqrt();
+        if sin_theta < 1e-9 {
+            return (dpdu, Vec3::zero());
+        }
+        let dpdv = Vec3::new(-n.y * n.x / sin_theta, sin_theta, -n.y * n.z / sin_theta) * (f64::PI() * radius);
+        (dpdu, dpdv)
+    }
+
+    /// Nearest `t` inside `ray_t` where `ray` meets the sphere centered at `center`.
+    fn root(&self, ray: &Ray, center: Vec3, ray_t: Interval) -> Option<f64> {
         let oc = ray.origin - center;
         let a = ray.direction.squared_length();
         let half_b = oc * ray.direction;
@@ -72,7 +82,7 @@ impl<Mat: Material + Clone+ Sync + Send + 'static> Hittable for Sphere<Mat> {
         let discriminant = half_b * half_b - a * c;
 
         if discriminant < 0.0 {
-            return false;
+            return None;
         }
 
         let sqrtd = discriminant.sqrt();
@@ -81,9 +91,20 @@ impl<Mat: Material + Clone+ Sync + Send + 'static> Hittable for Sphere<Mat> {
         if !ray_t.surrounds(root) {
             root = (-half_b + sqrtd) / a;
             if !ray_t.surrounds(root) {
-                return false;
+                return None;
             }
         }
+        Some(root)
+    }
+}
+
+impl<Mat: Material + Clone+ Sync + Send + 'static> Hittable for Sphere<Mat> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let center = self.sphere_center(ray.time);
+        let root = match self.root(ray, center, ray_t) {
+            Some(root) => root,
+            None => return false,
+        };
 
         let t = root;
         let p = ray.at(t);
@@ -94,7 +115,10 @@ impl<Mat: Material + Clone+ Sync + Send + 'static> Hittable for Sphere<Mat> {
         rec.p = p;
         rec.u = u;
         rec.v = v;
-        rec.material = Box::new(self.material.clone());
+        (rec.dpdu, rec.dpdv) = Sphere::<Mat>::sphere_dpduv(normal, self.radius);
+        rec.tangent = Vec3::zero();
+        rec.color = Vec3::ones();
+        rec.material = &self.material;
         rec.normal = normal;
         rec.front_face = true;
         rec.set_face_normal(*ray, normal);
@@ -105,4 +129,8 @@ impl<Mat: Material + Clone+ Sync + Send + 'static> Hittable for Sphere<Mat> {
     fn bounding_box(&self) -> Option<Aabb> {
         Some(self.bbox)
     }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.root(ray, self.sphere_center(ray.time), ray_t).is_some()
+    }
 }
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use crate::hittable_list::HittableList;
+use crate::linear_bvh::LinearBvh;
+use crate::material::Material;
+use crate::triangle_mesh::{ImportOptions, TriangleMesh};
+use crate::vec3::Vec3;
+
+#[derive(Debug)]
+pub enum StlError {
+    Io(io::Error),
+    /// Neither a well formed binary nor an ASCII STL file.
+    Invalid(String),
+    /// There are no faces to build a BVH over.
+    NoFaces,
+}
+
+impl std::error::Error for StlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StlError::Io(e) => Some(e),
+            StlError::Invalid(_) | StlError::NoFaces => None,
+        }
+    }
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StlError::Io(e) => write!(f, "Failed to read STL file: {}", e),
+            StlError::Invalid(message) => write!(f, "Invalid STL file: {}", message),
+            StlError::NoFaces => write!(f, "The STL file has no faces"),
+        }
+    }
+}
+
+impl From<io::Error> for StlError {
+    fn from(e: io::Error) -> Self {
+        StlError::Io(e)
+    }
+}
+
+/// Loads an STL file into a BVH over a shared-vertex mesh, like `load_obj_to_hittable_list`.
+pub(crate) fn load_stl_to_hittable_list<P: AsRef<Path>, Mat: Material + Sync + Send + 'static>(
+    path: P,
+    material: Mat,
+    options: ImportOptions,
+) -> Result<HittableList, StlError> {
+    let mesh = Arc::new(load_stl_mesh(path, material, options)?);
+    if mesh.indices.is_empty() {
+        return Err(StlError::NoFaces);
+    }
+    Ok(HittableList::set(Arc::new(LinearBvh::set(mesh.triangles()))))
+}
+
+/// Loads a binary or ASCII STL file. STL stores every triangle on its own, so vertices
+/// at exactly the same position are merged. The facet normals are ignored and the
+/// faces shaded flat.
+pub(crate) fn load_stl_mesh<P: AsRef<Path>, Mat: Material + Sync + Send + 'static>(
+    path: P,
+    material: Mat,
+    options: ImportOptions,
+) -> Result<TriangleMesh<Mat>, StlError> {
+    let triangles = parse_stl(&std::fs::read(path)?)?;
+    let mut vertex_ids: HashMap<[u64; 3], u32> = HashMap::new();
+    let mut positions = Vec::new();
+    let mut indices = Vec::with_capacity(triangles.len());
+    for triangle in triangles {
+        indices.push(triangle.map(|p| {
+            *vertex_ids.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).or_insert_with(|| {
+                positions.push(p);
+                (positions.len() - 1) as u32
+            })
+        }));
+    }
+    Ok(TriangleMesh::new(positions, Vec::new(), Vec::new(), indices, material).transformed(&options))
+}
+
+fn parse_stl(bytes: &[u8]) -> Result<Vec<[Vec3; 3]>, StlError> {
+    // Binary files may also start with "solid", so trust the size check first.
+    if bytes.len() >= 84 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if bytes.len() == 84 + count * 50 {
+            return Ok(parse_binary(&bytes[84..], count));
+        }
+    }
+    if bytes.trim_ascii_start().starts_with(b"solid") {
+        return parse_ascii(&String::from_utf8_lossy(bytes));
+    }
+    Err(StlError::Invalid("size does not match the triangle count and no 'solid' header".to_string()))
+}
+
+fn parse_binary(data: &[u8], count: usize) -> Vec<[Vec3; 3]> {
+    let float = |offset: usize| f32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as f64;
+    (0..count)
+        .map(|i| {
+            // 12 bytes of normal, three vertices, then 2 bytes of attributes
+            let start = i * 50 + 12;
+            std::array::from_fn(|v| {
+                let offset = start + v * 12;
+                Vec3::new(float(offset), float(offset + 4), float(offset + 8))
+            })
+        })
+        .collect()
+}
+
+fn parse_ascii(text: &str) -> Result<Vec<[Vec3; 3]>, StlError> {
+    let mut triangles = Vec::new();
+    let mut corners = Vec::with_capacity(3);
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("vertex") => {
+                let coords: Vec<f64> = words.map(str::parse).collect::<Result<_, _>>()
+                    .map_err(|_| StlError::Invalid(format!("bad vertex '{}'", line.trim())))?;
+                match coords.as_slice() {
+                    &[x, y, z] => corners.push(Vec3::new(x, y, z)),
+                    _ => return Err(StlError::Invalid(format!("bad vertex '{}'", line.trim()))),
+                }
+            }
+            Some("endloop") => {
+                // facets are triangles by the spec, anything larger is fanned
+                for i in 2..corners.len() {
+                    triangles.push([corners[0], corners[i - 1], corners[i]]);
+                }
+                corners.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn parse_ascii_facets() {
+        let text = "solid test\n\
+            facet normal 0 0 1\n outer loop\n  vertex 0 0 0\n  vertex 1 0 0\n  vertex 0 1 0\n endloop\nendfacet\n\
+            facet normal 0 0 1\n outer loop\n  vertex 1 0 0\n  vertex 1 1 0\n  vertex 0 1 0\n endloop\nendfacet\n\
+            endsolid test\n";
+        let triangles = parse_stl(text.as_bytes()).unwrap();
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[1][1], Vec3::new(1.0, 1.0, 0.0));
+    }
+
+    // The header starts with "solid" like many exporters write it, the size decides.
+    #[test]
+    fn parse_binary_with_solid_header() {
+        let mut bytes = b"solid binary".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend(1u32.to_le_bytes());
+        for value in [0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend([0, 0]);
+        let triangles = parse_stl(&bytes).unwrap();
+        assert_eq!(triangles, vec![[Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)]]);
+    }
+
+    #[test]
+    fn no_faces_is_an_error() {
+        let dir = TempDir::new("empty_stl");
+        let path = dir.write("empty.stl", "solid empty\nendsolid empty\n");
+        let result = load_stl_to_hittable_list(&path, Lambertian::new(Vec3::ones()), ImportOptions::default());
+        assert!(matches!(result, Err(StlError::NoFaces)));
+    }
+}
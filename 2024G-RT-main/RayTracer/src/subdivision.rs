@@ -0,0 +1,445 @@
+use std::collections::HashMap;
+use crate::material::Material;
+use crate::obj_read::triangulate;
+use crate::texture::Texture;
+use crate::triangle_mesh::TriangleMesh;
+use crate::vec3::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubdivisionScheme {
+    /// Loop's scheme for triangles, other faces are triangulated first.
+    Loop,
+    /// Catmull-Clark, for any polygons. Every face becomes quads.
+    CatmullClark,
+}
+
+/// How often and by which scheme a control cage is refined. Each level splits every
+/// face into four.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Subdivision {
+    pub scheme: SubdivisionScheme,
+    pub levels: u32,
+}
+
+/// A height map that moves the vertices of a mesh along their normals, by `scale` times
+/// the average of the texture's channels.
+#[derive(Clone)]
+pub struct Displacement {
+    pub height: Texture,
+    pub scale: f64,
+}
+
+impl Displacement {
+    pub fn new(height: Texture, scale: f64) -> Self {
+        Self { height, scale }
+    }
+
+    /// How far to move a vertex at `p` with texture coordinates `uv`.
+    pub fn offset(&self, uv: (f64, f64), p: Vec3) -> f64 {
+        let c = self.height.value(uv.0, uv.1, &p);
+        self.scale * (c.x + c.y + c.z) / 3.0
+    }
+}
+
+/// A polygon mesh used as a subdivision control cage. Texture coordinates have faces of
+/// their own, as in OBJ files, so a seam is a boundary for the texture coordinates while
+/// the surface is smooth across it.
+#[derive(Clone, Debug, Default)]
+pub struct PolyMesh {
+    pub positions: Vec<Vec3>,
+    pub faces: Vec<Vec<u32>>,
+    /// Empty for cages without texture coordinates.
+    pub uvs: Vec<(f64, f64)>,
+    /// The `uvs` at the corners of each face, empty along with `uvs`.
+    pub uv_faces: Vec<Vec<u32>>,
+}
+
+/// A single subdivision step of points connected by faces.
+type Step = fn(&[Vec3], &[Vec<u32>]) -> (Vec<Vec3>, Vec<Vec<u32>>);
+
+impl PolyMesh {
+    pub fn new(positions: Vec<Vec3>, faces: Vec<Vec<u32>>) -> Self {
+        Self {
+            positions,
+            faces,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>, uv_faces: Vec<Vec<u32>>) -> Self {
+        assert_eq!(uv_faces.len(), self.faces.len(), "one uv face per face");
+        self.uvs = uvs;
+        self.uv_faces = uv_faces;
+        self
+    }
+
+    /// The limit surface approached by `subdivision.levels` steps of the scheme.
+    pub fn subdivided(self, subdivision: Subdivision) -> Self {
+        let mut mesh = self.without_degenerate_faces();
+        for _ in 0..subdivision.levels {
+            mesh = match subdivision.scheme {
+                SubdivisionScheme::Loop => mesh.triangulated().refined(loop_step),
+                SubdivisionScheme::CatmullClark => mesh.refined(catmull_clark_step),
+            };
+        }
+        mesh
+    }
+
+    /// The mesh without faces that use a vertex twice, such as `f 1 2 1`. They have no
+    /// area and would make their edges look like creases.
+    fn without_degenerate_faces(mut self) -> Self {
+        let keep: Vec<bool> = self.faces.iter()
+            .map(|face| face.len() >= 3 && face.iter().enumerate().all(|(i, v)| !face[..i].contains(v)))
+            .collect();
+        let kept = |faces: Vec<Vec<u32>>| faces.into_iter().zip(&keep).filter(|(_, &k)| k).map(|(f, _)| f).collect();
+        self.faces = kept(self.faces);
+        if !self.uv_faces.is_empty() {
+            self.uv_faces = kept(self.uv_faces);
+        }
+        self
+    }
+
+    /// The same surface with every face that is not a triangle split into triangles.
+    pub fn triangulated(&self) -> Self {
+        let mut faces = Vec::with_capacity(self.faces.len());
+        let mut uv_faces = Vec::with_capacity(self.uv_faces.len());
+        for (i, face) in self.faces.iter().enumerate() {
+            let points: Vec<Vec3> = face.iter().map(|&v| self.positions[v as usize]).collect();
+            for corners in triangulate(&points) {
+                faces.push(corners.map(|c| face[c]).to_vec());
+                if let Some(uv_face) = self.uv_faces.get(i) {
+                    uv_faces.push(corners.map(|c| uv_face[c]).to_vec());
+                }
+            }
+        }
+        Self {
+            positions: self.positions.clone(),
+            faces,
+            uvs: self.uvs.clone(),
+            uv_faces,
+        }
+    }
+
+    /// One step of `step` on the positions, and on the texture coordinates by their own faces.
+    fn refined(&self, step: Step) -> Self {
+        let (positions, faces) = step(&self.positions, &self.faces);
+        if self.uvs.is_empty() {
+            return Self::new(positions, faces);
+        }
+        let points: Vec<Vec3> = self.uvs.iter().map(|&(u, v)| Vec3::new(u, v, 0.0)).collect();
+        let (uvs, uv_faces) = step(&points, &self.uv_faces);
+        Self::new(positions, faces).with_uvs(uvs.iter().map(|p| (p.x, p.y)).collect(), uv_faces)
+    }
+
+    /// A triangle mesh of the faces with smooth normals, a vertex for every pair of
+    /// position and texture coordinate.
+    pub fn into_mesh<Mat: Material + Sync + Send + 'static>(self, mat: Mat) -> TriangleMesh<Mat> {
+        let has_uvs = !self.uvs.is_empty();
+        let mut vertex_ids: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut vertices: Vec<(u32, u32)> = Vec::new();
+        let mut indices = Vec::new();
+        for (i, face) in self.faces.iter().enumerate() {
+            let points: Vec<Vec3> = face.iter().map(|&v| self.positions[v as usize]).collect();
+            for corners in triangulate(&points) {
+                indices.push(corners.map(|c| {
+                    let key = (face[c], if has_uvs { self.uv_faces[i][c] } else { 0 });
+                    *vertex_ids.entry(key).or_insert_with(|| {
+                        vertices.push(key);
+                        (vertices.len() - 1) as u32
+                    })
+                }));
+            }
+        }
+        let positions = vertices.iter().map(|&(p, _)| self.positions[p as usize]).collect();
+        let uvs = if has_uvs {
+            vertices.iter().map(|&(_, uv)| self.uvs[uv as usize]).collect()
+        } else {
+            Vec::new()
+        };
+        TriangleMesh::new(positions, Vec::new(), uvs, indices, mat).with_smooth_normals()
+    }
+}
+
+/// The edges of a mesh, each with the faces on either side of it.
+struct Edges {
+    ids: HashMap<(u32, u32), usize>,
+    ends: Vec<(u32, u32)>,
+    faces: Vec<Vec<usize>>,
+}
+
+impl Edges {
+    fn new(faces: &[Vec<u32>]) -> Self {
+        let mut edges = Edges {
+            ids: HashMap::new(),
+            ends: Vec::new(),
+            faces: Vec::new(),
+        };
+        for (f, face) in faces.iter().enumerate() {
+            for (i, &a) in face.iter().enumerate() {
+                let b = face[(i + 1) % face.len()];
+                let next = edges.ends.len();
+                let id = *edges.ids.entry((a.min(b), a.max(b))).or_insert(next);
+                if id == next {
+                    edges.ends.push((a, b));
+                    edges.faces.push(Vec::new());
+                }
+                edges.faces[id].push(f);
+            }
+        }
+        edges
+    }
+
+    fn id(&self, a: u32, b: u32) -> u32 {
+        self.ids[&(a.min(b), a.max(b))] as u32
+    }
+
+    /// Whether the surface has a crease along the edge, at the border of the mesh or
+    /// where more than two faces meet.
+    fn is_sharp(&self, e: usize) -> bool {
+        self.faces[e].len() != 2
+    }
+
+    /// For every point the sum of its neighbours and how many there are, once over all
+    /// edges and once over the sharp ones.
+    fn neighbours(&self, points: &[Vec3]) -> Vec<Neighbours> {
+        let none = Neighbours { sum: Vec3::zero(), count: 0, sharp_sum: Vec3::zero(), sharp_count: 0 };
+        let mut neighbours = vec![none; points.len()];
+        for (e, &(a, b)) in self.ends.iter().enumerate() {
+            for (v, other) in [(a, b), (b, a)] {
+                let n = &mut neighbours[v as usize];
+                n.sum += points[other as usize];
+                n.count += 1;
+                if self.is_sharp(e) {
+                    n.sharp_sum += points[other as usize];
+                    n.sharp_count += 1;
+                }
+            }
+        }
+        neighbours
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Neighbours {
+    sum: Vec3,
+    count: usize,
+    sharp_sum: Vec3,
+    sharp_count: usize,
+}
+
+impl Neighbours {
+    /// The new position of a point on a crease, the cubic B-spline rule along the two
+    /// sharp edges. A corner, where more or fewer sharp edges meet or that has only the
+    /// two edges of a single face, stays in place so the corners of a texture keep theirs.
+    fn crease(&self, p: Vec3) -> Option<Vec3> {
+        match self.sharp_count {
+            0 => None,
+            2 if self.count > 2 => Some(p * 0.75 + self.sharp_sum * 0.125),
+            _ => Some(p),
+        }
+    }
+}
+
+/// One Catmull-Clark step. The new points are the old vertices, then one per edge, then
+/// one per face.
+fn catmull_clark_step(points: &[Vec3], faces: &[Vec<u32>]) -> (Vec<Vec3>, Vec<Vec<u32>>) {
+    let edges = Edges::new(faces);
+    let face_points: Vec<Vec3> = faces.iter()
+        .map(|face| face.iter().fold(Vec3::zero(), |sum, &v| sum + points[v as usize]) / face.len() as f64)
+        .collect();
+    let mut new_points = Vec::with_capacity(points.len() + edges.ends.len() + faces.len());
+
+    let mut face_sums = vec![(Vec3::zero(), 0usize); points.len()];
+    for (face, &center) in faces.iter().zip(&face_points) {
+        for &v in face {
+            face_sums[v as usize].0 += center;
+            face_sums[v as usize].1 += 1;
+        }
+    }
+    for ((&p, n), (face_sum, face_count)) in points.iter().zip(edges.neighbours(points)).zip(face_sums) {
+        new_points.push(match n.crease(p) {
+            Some(p) => p,
+            None if n.count == 0 => p,
+            None => {
+                let k = n.count as f64;
+                // the average of the edge midpoints is halfway between `p` and its neighbours
+                let midpoints = (p + n.sum / k) / 2.0;
+                (face_sum / face_count as f64 + midpoints * 2.0 + p * (k - 3.0)) / k
+            }
+        });
+    }
+    for (e, &(a, b)) in edges.ends.iter().enumerate() {
+        let mid = (points[a as usize] + points[b as usize]) / 2.0;
+        new_points.push(if edges.is_sharp(e) {
+            mid
+        } else {
+            let fs = &edges.faces[e];
+            (mid + (face_points[fs[0]] + face_points[fs[1]]) / 2.0) / 2.0
+        });
+    }
+    new_points.extend(face_points);
+
+    let edge_base = points.len() as u32;
+    let face_base = edge_base + edges.ends.len() as u32;
+    let mut new_faces = Vec::with_capacity(faces.iter().map(Vec::len).sum());
+    for (f, face) in faces.iter().enumerate() {
+        let k = face.len();
+        for i in 0..k {
+            let (prev, v, next) = (face[(i + k - 1) % k], face[i], face[(i + 1) % k]);
+            new_faces.push(vec![
+                v,
+                edge_base + edges.id(v, next),
+                face_base + f as u32,
+                edge_base + edges.id(prev, v),
+            ]);
+        }
+    }
+    (new_points, new_faces)
+}
+
+/// One step of Loop's scheme on triangles. The new points are the old vertices, then one
+/// per edge.
+fn loop_step(points: &[Vec3], faces: &[Vec<u32>]) -> (Vec<Vec3>, Vec<Vec<u32>>) {
+    let edges = Edges::new(faces);
+    let mut new_points = Vec::with_capacity(points.len() + edges.ends.len());
+    for (&p, n) in points.iter().zip(edges.neighbours(points)) {
+        new_points.push(match n.crease(p) {
+            Some(p) => p,
+            None if n.count == 0 => p,
+            None => {
+                let k = n.count as f64;
+                let beta = if n.count == 3 { 3.0 / 16.0 } else { 3.0 / (8.0 * k) };
+                p * (1.0 - k * beta) + n.sum * beta
+            }
+        });
+    }
+    for (e, &(a, b)) in edges.ends.iter().enumerate() {
+        let (pa, pb) = (points[a as usize], points[b as usize]);
+        // texture coordinates can repeat within a face, leaving no opposite vertex
+        let opposite = |f: usize| faces[f].iter().find(|&&v| v != a && v != b).map(|&v| points[v as usize]);
+        new_points.push(match edges.faces[e][..] {
+            [f, g] => match (opposite(f), opposite(g)) {
+                (Some(c), Some(d)) => (pa + pb) * 0.375 + (c + d) * 0.125,
+                _ => (pa + pb) / 2.0,
+            },
+            _ => (pa + pb) / 2.0,
+        });
+    }
+
+    let edge_base = points.len() as u32;
+    let mut new_faces = Vec::with_capacity(faces.len() * 4);
+    for face in faces {
+        let [a, b, c] = [face[0], face[1], face[2]];
+        let [ab, bc, ca] = [(a, b), (b, c), (c, a)].map(|(x, y)| edge_base + edges.id(x, y));
+        new_faces.extend([vec![a, ab, ca], vec![ab, b, bc], vec![ca, bc, c], vec![ab, bc, ca]]);
+    }
+    (new_points, new_faces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::assert_near;
+    use crate::material::Lambertian;
+
+    fn cube() -> PolyMesh {
+        let positions = (0..8)
+            .map(|i| Vec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+            ))
+            .collect();
+        let faces = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+        PolyMesh::new(positions, faces.iter().map(|f| f.to_vec()).collect())
+    }
+
+    #[test]
+    fn catmull_clark_cube() {
+        let once = cube().subdivided(Subdivision { scheme: SubdivisionScheme::CatmullClark, levels: 1 });
+        assert_eq!(once.positions.len(), 8 + 12 + 6);
+        assert_eq!(once.faces.len(), 24);
+        // the corners move to 5/9, the edge points to (3/4, 3/4, 0) and the faces stay put
+        assert_near(once.positions[7], Vec3::new(5.0, 5.0, 5.0) / 9.0, 1e-9);
+        assert!(once.positions[8..20].iter().all(|p| (p.length() - 0.75 * 2f64.sqrt()).abs() < 1e-9));
+        assert_near(once.positions[20], Vec3::new(0.0, 0.0, -1.0), 1e-9);
+
+        // the limit surface is close to a sphere
+        let smooth = cube().subdivided(Subdivision { scheme: SubdivisionScheme::CatmullClark, levels: 4 });
+        let radii = smooth.positions.iter().map(|p| p.length());
+        let (min, max) = radii.fold((f64::INFINITY, 0.0f64), |(lo, hi), r| (lo.min(r), hi.max(r)));
+        assert!(max / min < 1.1, "{} {}", min, max);
+    }
+
+    #[test]
+    fn loop_tetrahedron() {
+        let positions = vec![
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+        ];
+        let faces = vec![vec![0, 1, 2], vec![0, 3, 1], vec![0, 2, 3], vec![1, 3, 2]];
+        let mesh = PolyMesh::new(positions.clone(), faces)
+            .subdivided(Subdivision { scheme: SubdivisionScheme::Loop, levels: 1 });
+        assert_eq!(mesh.positions.len(), 4 + 6);
+        assert_eq!(mesh.faces.len(), 16);
+        // with valence 3 and the neighbours summing to -p, a vertex moves to p / 4
+        assert_near(mesh.positions[0], positions[0] / 4.0, 1e-9);
+    }
+
+    #[test]
+    fn seams_are_boundaries_for_texture_coordinates() {
+        // a quad strip folded into a ring, the texture wraps around with a seam at u = 0 and 1
+        let positions: Vec<Vec3> = (0..4)
+            .flat_map(|i| {
+                let a = i as f64 * std::f64::consts::FRAC_PI_2;
+                [Vec3::new(a.cos(), 0.0, a.sin()), Vec3::new(a.cos(), 1.0, a.sin())]
+            })
+            .collect();
+        let faces = (0..4u32).map(|i| vec![2 * i, 2 * i + 1, (2 * i + 3) % 8, (2 * i + 2) % 8]).collect();
+        let uvs = (0..5).flat_map(|i| [(i as f64 / 4.0, 0.0), (i as f64 / 4.0, 1.0)]).collect();
+        let uv_faces = (0..4u32).map(|i| vec![2 * i, 2 * i + 1, 2 * i + 3, 2 * i + 2]).collect();
+        let mesh = PolyMesh::new(positions, faces)
+            .with_uvs(uvs, uv_faces)
+            .subdivided(Subdivision { scheme: SubdivisionScheme::CatmullClark, levels: 2 })
+            .into_mesh(Lambertian::new(Vec3::ones()));
+
+        // the seam keeps u = 0 on one side and u = 1 on the other at the same positions
+        for (i, p) in mesh.positions.iter().enumerate() {
+            if (p.z).abs() < 1e-12 && p.x > 0.0 {
+                let u = mesh.uvs[i].0;
+                assert!(u.abs() < 1e-12 || (u - 1.0).abs() < 1e-12, "u = {}", u);
+            }
+        }
+        assert!(mesh.normals.iter().zip(&mesh.positions).all(|(n, p)| *n * Vec3::new(p.x, 0.0, p.z) > 0.0));
+    }
+
+    #[test]
+    fn degenerate_faces_are_dropped() {
+        let positions = vec![
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+        ];
+        let faces = vec![vec![0, 1, 2], vec![0, 3, 1], vec![0, 2, 3], vec![1, 3, 2]];
+        let loop_once = Subdivision { scheme: SubdivisionScheme::Loop, levels: 1 };
+        let clean = PolyMesh::new(positions.clone(), faces.clone()).subdivided(loop_once);
+
+        // `f 1 2 1` and a quad with a corner twice, both with texture coordinates all on one point
+        let mut with_degenerate = faces.clone();
+        with_degenerate.extend([vec![0, 1, 0], vec![2, 3, 3, 0]]);
+        let uv_faces: Vec<Vec<u32>> = with_degenerate.iter().map(|face| vec![0; face.len()]).collect();
+        for scheme in [SubdivisionScheme::Loop, SubdivisionScheme::CatmullClark] {
+            let subdivision = Subdivision { scheme, levels: 2 };
+            let mesh = PolyMesh::new(positions.clone(), with_degenerate.clone())
+                .with_uvs(vec![(0.5, 0.5)], uv_faces.clone())
+                .subdivided(subdivision);
+            assert!(mesh.uvs.iter().all(|&uv| uv == (0.5, 0.5)));
+            assert_eq!(mesh.faces.len(), PolyMesh::new(positions.clone(), faces.clone()).subdivided(subdivision).faces.len());
+        }
+        let mesh = PolyMesh::new(positions, with_degenerate).subdivided(loop_once);
+        assert_eq!(mesh.positions, clean.positions);
+    }
+}
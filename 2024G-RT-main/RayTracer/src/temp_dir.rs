@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+/// A directory of its own in the temporary directory for the tests that read files,
+/// removed again when dropped, so a failing test does not leave it behind.
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// `name` only has to be unique among the tests, the process id tells runs apart.
+    pub(crate) fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("ray_tracer_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub(crate) fn join(&self, file: &str) -> PathBuf {
+        self.path.join(file)
+    }
+
+    /// Writes `contents` to `file`, creating the directories on the way, and returns its path.
+    pub(crate) fn write(&self, file: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -1,5 +1,8 @@
+use std::sync::Arc;
 use crate::vec3::Vec3;
+use image::DynamicImage;
 use crate::image_::RtwImage;
+use crate::hittable::HitRecord;
 use crate::interval::Interval;
 use crate::perlin::Perlin;
 #[derive(Clone)]
@@ -8,6 +11,8 @@ pub enum Texture{
     Checker(Box<CheckerTexture>),
     ImageTex(ImageTexture),
     Noise(NoiseTexture),
+    /// The vertex colors of the mesh that was hit, see `HitRecord::color`.
+    VertexColor,
 }
 
 
@@ -17,7 +22,18 @@ impl Texture{
             Texture::Checker(tex)=>tex.value(u,v,p),
             Texture::SolidColor(color)=>*color,
             Texture::ImageTex(tex)=>tex.value(u,v,p),
-            Texture::Noise(tex)=>NoiseTexture::value(tex,u,v,*p)
+            Texture::Noise(tex)=>NoiseTexture::value(tex,u,v,*p),
+            // only `sample` knows the hit, nested in a checker it stays white
+            Texture::VertexColor=>Vec3::new(1.0,1.0,1.0),
+        }
+    }
+    /// The texture at a hit point, unlike `value` this can read the vertex colors and
+    /// filter images over the footprint of a pixel.
+    pub fn sample(&self,rec:&HitRecord)->Vec3{
+        match self{
+            Texture::VertexColor=>rec.color,
+            Texture::ImageTex(tex)=>tex.filtered(rec.u,rec.v,rec.duv_dx,rec.duv_dy),
+            _=>self.value(rec.u,rec.v,&rec.p),
         }
     }
     pub fn color(scale:f64,c1:Vec3,c2:Vec3)->Self{
@@ -67,28 +83,211 @@ impl CheckerTexture{
         }
     }
 }
+/// How texture coordinates outside [0, 1] are brought back onto the image.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum WrapMode{
+    Repeat,
+    Clamp,
+    /// Repeat with every other copy flipped, so the copies meet at matching edges.
+    Mirror,
+}
+impl WrapMode{
+    /// Texel `i` of a row or column of `n` texels, moved into `0..n`.
+    fn texel(self,i:i64,n:i64)->i64{
+        match self{
+            WrapMode::Repeat=>i.rem_euclid(n),
+            WrapMode::Clamp=>i.clamp(0,n-1),
+            WrapMode::Mirror=>{
+                let m=i.rem_euclid(2*n);
+                if m<n {m} else {2*n-1-m}
+            }
+        }
+    }
+}
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum TextureFilter{
+    Nearest,
+    Bilinear,
+    /// Bilinear on the two mip levels around the footprint of a pixel, blended between
+    /// them. Without a footprint it is the same as `Bilinear`.
+    Trilinear,
+}
+/// How the numbers in an image file relate to the values a texture returns.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ColorSpace{
+    /// Colors as 8 and 16-bit images store them, gamma encoded. They are decoded to linear
+    /// before they are filtered or lit.
+    Srgb,
+    /// Data such as roughness, normals or heights, used exactly as stored.
+    Linear,
+}
+/// The sRGB transfer function undone, from the stored value to linear light.
+fn srgb_to_linear(c:f32)->f32{
+    if c<=0.04045 {c/12.92} else {((c+0.055)/1.055).powf(2.4)}
+}
+/// One level of a mip map, linear RGB row by row from the top of the image. Texels
+/// are kept in `f32`, a large image and its levels would take twice the memory in `Vec3`.
+struct MipLevel{
+    width:i64,
+    height:i64,
+    texels:Vec<[f32;3]>,
+}
+impl MipLevel{
+    /// Every level has half the width and height of the one before, each texel the
+    /// average of the up to four it covers.
+    fn reduce(&self)->MipLevel{
+        let width=(self.width+1)/2;
+        let height=(self.height+1)/2;
+        let mut texels=Vec::with_capacity((width*height) as usize);
+        for y in 0..height{
+            for x in 0..width{
+                let mut sum=Vec3::zero();
+                for (dx,dy) in [(0,0),(1,0),(0,1),(1,1)]{
+                    let sx=(2*x+dx).min(self.width-1);
+                    let sy=(2*y+dy).min(self.height-1);
+                    sum+=self.at(sx,sy);
+                }
+                let average=sum/4.0;
+                texels.push([average.x as f32,average.y as f32,average.z as f32]);
+            }
+        }
+        MipLevel{width,height,texels}
+    }
+    fn at(&self,x:i64,y:i64)->Vec3{
+        let [r,g,b]=self.texels[(y*self.width+x) as usize];
+        Vec3::new(r as f64,g as f64,b as f64)
+    }
+    fn texel(&self,x:i64,y:i64,wrap_u:WrapMode,wrap_v:WrapMode)->Vec3{
+        self.at(wrap_u.texel(x,self.width),wrap_v.texel(y,self.height))
+    }
+    fn nearest(&self,u:f64,v:f64,wrap_u:WrapMode,wrap_v:WrapMode)->Vec3{
+        let x=(u*self.width as f64).floor() as i64;
+        let y=((1.0-v)*self.height as f64).floor() as i64;
+        self.texel(x,y,wrap_u,wrap_v)
+    }
+    /// Blends the four texels around `(u, v)`, taking texel centers at half-integers.
+    fn bilinear(&self,u:f64,v:f64,wrap_u:WrapMode,wrap_v:WrapMode)->Vec3{
+        let x=u*self.width as f64-0.5;
+        let y=(1.0-v)*self.height as f64-0.5;
+        let (x0,y0)=(x.floor(),y.floor());
+        let (fx,fy)=(x-x0,y-y0);
+        let (x0,y0)=(x0 as i64,y0 as i64);
+        let top=self.texel(x0,y0,wrap_u,wrap_v)*(1.0-fx)+self.texel(x0+1,y0,wrap_u,wrap_v)*fx;
+        let bottom=self.texel(x0,y0+1,wrap_u,wrap_v)*(1.0-fx)+self.texel(x0+1,y0+1,wrap_u,wrap_v)*fx;
+        top*(1.0-fy)+bottom*fy
+    }
+}
+/// Images are decoded once into a mip map that all clones of the texture share. Float
+/// images (`.hdr`, `.exr`) are linear already and keep values above one.
 #[derive(Clone)]
 pub struct ImageTexture{
- image_texture: RtwImage,
+    levels:Arc<Vec<MipLevel>>,
+    pub filter:TextureFilter,
+    pub wrap_u:WrapMode,
+    pub wrap_v:WrapMode,
+    /// Multiplies every lookup, such as the base color factor of a glTF material.
+    pub tint:Vec3,
 }
 impl ImageTexture{
+    /// A color texture such as an albedo map, stored in sRGB.
     pub fn new(image_filename:&str)->Self{
+        Self::from_rtw_image(RtwImage::new(image_filename),ColorSpace::Srgb)
+    }
+    /// A data texture such as a roughness, normal or height map, read without decoding.
+    pub fn data(image_filename:&str)->Self{
+        Self::from_rtw_image(RtwImage::new(image_filename),ColorSpace::Linear)
+    }
+    /// A texture from an image that is already decoded, holding colors or data.
+    pub fn from_image(image:DynamicImage,color_space:ColorSpace)->Self{
+        Self::from_rtw_image(RtwImage::from_image(image),color_space)
+    }
+    /// Opacity for cutouts from the alpha channel of an image, gray in all three channels.
+    /// Images without alpha are read as data instead, as MTL `map_d` files come both ways.
+    pub fn opacity(image_filename:&str)->Self{
+        Self::opacity_from_rtw_image(RtwImage::new(image_filename))
+    }
+    /// Opacity from an image that is already decoded, see `opacity`.
+    pub fn opacity_from_image(image:DynamicImage)->Self{
+        Self::opacity_from_rtw_image(RtwImage::from_image(image))
+    }
+    fn opacity_from_rtw_image(image:RtwImage)->Self{
+        if !image.has_alpha(){
+            return Self::from_rtw_image(image,ColorSpace::Linear);
+        }
+        Self::from_texels(image.width(),image.height(),image.alpha_f32())
+    }
+    fn from_rtw_image(image:RtwImage,color_space:ColorSpace)->Self{
+        let mut texels=image.rgb_f32();
+        if color_space==ColorSpace::Srgb && !image.is_float(){
+            for texel in &mut texels{
+                *texel=texel.map(srgb_to_linear);
+            }
+        }
+        Self::from_texels(image.width(),image.height(),texels)
+    }
+    /// Trilinear filtering and repeating texture coordinates, as OBJ and glTF expect.
+    fn from_texels(width:u32,height:u32,texels:Vec<[f32;3]>)->Self{
+        let mut levels=Vec::new();
+        if width>0 && height>0{
+            levels.push(MipLevel{width:width as i64,height:height as i64,texels});
+            while let Some(last)=levels.last().filter(|l| l.width>1 || l.height>1){
+                let next=last.reduce();
+                levels.push(next);
+            }
+        }
         Self{
-            image_texture:RtwImage::new(image_filename),
+            levels:Arc::new(levels),
+            filter:TextureFilter::Trilinear,
+            wrap_u:WrapMode::Repeat,
+            wrap_v:WrapMode::Repeat,
+            tint:Vec3::ones(),
         }
     }
-    pub fn value(&self,u:f64,v:f64,p:&Vec3)->Vec3{
-        let i=self.image_texture.width() as f64;
-        let j=self.image_texture.height() as f64;
-        if j<=0.0{
+    pub fn with_filter(mut self,filter:TextureFilter)->Self{
+        self.filter=filter;
+        self
+    }
+    pub fn with_wrap(mut self,wrap_u:WrapMode,wrap_v:WrapMode)->Self{
+        self.wrap_u=wrap_u;
+        self.wrap_v=wrap_v;
+        self
+    }
+    pub fn with_tint(mut self,tint:Vec3)->Self{
+        self.tint=tint;
+        self
+    }
+    /// The texture at a single point, the full resolution image is used.
+    pub fn value(&self,u:f64,v:f64,_p:&Vec3)->Vec3{
+        self.filtered(u,v,(0.0,0.0),(0.0,0.0))
+    }
+    /// The texture over the footprint of a pixel, `duv_dx` and `duv_dy` being how far the
+    /// texture coordinates move to the next pixel across and down.
+    pub fn filtered(&self,u:f64,v:f64,duv_dx:(f64,f64),duv_dy:(f64,f64))->Vec3{
+        Vec3::elemul(self.untinted(u,v,duv_dx,duv_dy),self.tint)
+    }
+    fn untinted(&self,u:f64,v:f64,duv_dx:(f64,f64),duv_dy:(f64,f64))->Vec3{
+        let Some(full)=self.levels.first() else{
             return Vec3::new(0.0,1.0,1.0);
+        };
+        let (wrap_u,wrap_v)=(self.wrap_u,self.wrap_v);
+        match self.filter{
+            TextureFilter::Nearest=>full.nearest(u,v,wrap_u,wrap_v),
+            TextureFilter::Bilinear=>full.bilinear(u,v,wrap_u,wrap_v),
+            TextureFilter::Trilinear=>{
+                // the footprint in texels of the full image, its longest side picks the level
+                let (w,h)=(full.width as f64,full.height as f64);
+                let width=(duv_dx.0.abs()*w).max(duv_dx.1.abs()*h).max(duv_dy.0.abs()*w).max(duv_dy.1.abs()*h);
+                let level=Interval::set(0.0,(self.levels.len()-1) as f64).clamp(width.max(1.0).log2());
+                let lower=level.floor() as usize;
+                let fine=self.levels[lower].bilinear(u,v,wrap_u,wrap_v);
+                if lower+1==self.levels.len(){
+                    return fine;
+                }
+                let coarse=self.levels[lower+1].bilinear(u,v,wrap_u,wrap_v);
+                let t=level-lower as f64;
+                fine*(1.0-t)+coarse*t
+            }
         }
-        let u=Interval::set(0.0,1.0).clamp(u);
-        let v=1.0-Interval::set(0.0,1.0).clamp(v);
-        let i=(i*u) as u32;
-        let j=(j*v) as u32;
-        let pixel=self.image_texture.pixel_data(i,j);
-        Vec3::new(pixel[0] as f64,pixel[1] as f64,pixel[2] as f64)/255.0
     }
 }
 #[derive(Clone)]
@@ -106,4 +305,86 @@ impl NoiseTexture{
     pub fn value(&self,u:f64,v:f64,p:Vec3)->Vec3{
         Vec3::new(0.5,0.5,0.5)*(1.0+(self.scale*p.z+10.0*self.noise.turb(p,7)).sin())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use crate::vec3::assert_near;
+    use image::{Rgb, RgbImage};
+
+    fn gray(level: f64) -> Vec3 {
+        Vec3::new(level, level, level)
+    }
+
+    // black and white texels alternating in both directions
+    fn checker(width: u32, height: u32) -> ImageTexture {
+        let image = RgbImage::from_fn(width, height, |x, y| if (x + y) % 2 == 0 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) });
+        ImageTexture::from_image(DynamicImage::ImageRgb8(image),ColorSpace::Srgb)
+    }
+
+    #[test]
+    fn wrap_modes() {
+        assert_eq!([-1, 0, 4, 9].map(|i| WrapMode::Repeat.texel(i, 4)), [3, 0, 0, 1]);
+        assert_eq!([-1, 0, 4, 9].map(|i| WrapMode::Clamp.texel(i, 4)), [0, 0, 3, 3]);
+        assert_eq!([-1, 0, 4, 9].map(|i| WrapMode::Mirror.texel(i, 4)), [0, 0, 3, 1]);
+    }
+
+    #[test]
+    fn lookups_at_the_edge_of_the_image() {
+        let texture = checker(2, 1).with_filter(TextureFilter::Nearest);
+        let p = Vec3::zero();
+        // u = 1 used to index one column past the image
+        assert_near(texture.clone().with_wrap(WrapMode::Clamp, WrapMode::Clamp).value(1.0, 0.5, &p), gray(1.0), 1e-6);
+        assert_near(texture.clone().with_wrap(WrapMode::Repeat, WrapMode::Repeat).value(1.0, 0.5, &p), gray(0.0), 1e-6);
+        assert_near(texture.with_wrap(WrapMode::Mirror, WrapMode::Mirror).value(1.0, 0.5, &p), gray(1.0), 1e-6);
+        // halfway between the last texel and the first one repeated
+        let bilinear = checker(2, 1).with_filter(TextureFilter::Bilinear);
+        assert_near(bilinear.value(1.0, 0.5, &p), gray(0.5), 1e-6);
+    }
+
+    #[test]
+    fn footprint_picks_the_mip_level() {
+        let texture = checker(4, 4);
+        assert_eq!(texture.levels.iter().map(|l| (l.width, l.height)).collect::<Vec<_>>(), [(4, 4), (2, 2), (1, 1)]);
+        // the center of the second texel of the first row
+        let (u, v) = (0.375, 0.875);
+        assert_near(texture.filtered(u, v, (0.0, 0.0), (0.0, 0.0)), gray(1.0), 1e-6);
+        // a footprint of four texels averages the whole image
+        assert_near(texture.filtered(u, v, (1.0, 0.0), (0.0, 1.0)), gray(0.5), 1e-6);
+        // two texels is the level in between, which is already gray everywhere
+        assert_near(texture.filtered(u, v, (0.5, 0.0), (0.0, 0.0)), gray(0.5), 1e-6);
+        // a footprint of sqrt(2) texels is halfway between the full image and the first level
+        assert_near(texture.filtered(u, v, (2f64.powf(0.5) / 4.0, 0.0), (0.0, 0.0)), gray(0.75), 1e-6);
+    }
+
+    // Writes a file to a directory of its own and loads it back.
+    fn round_trip(name: &str, write: impl FnOnce(&std::path::Path), load: fn(&str) -> ImageTexture) -> Vec3 {
+        let dir = TempDir::new(name);
+        let path = dir.join(name);
+        write(&path);
+        load(&path.to_string_lossy()).value(0.5, 0.5, &Vec3::zero())
+    }
+
+    #[test]
+    fn color_textures_are_decoded_and_data_textures_are_not() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([128, 128, 128])));
+        assert_near(round_trip("srgb.png", |path| image.save(path).unwrap(), ImageTexture::new), gray(0.2158605), 1e-6);
+        assert_near(round_trip("data.png", |path| image.save(path).unwrap(), ImageTexture::data), gray(128.0 / 255.0), 1e-6);
+    }
+
+    #[test]
+    fn deep_and_float_images() {
+        use image::codecs::hdr::HdrEncoder;
+        use image::{ImageBuffer, Rgb32FImage};
+        // a 16-bit value 8 bits could not hold
+        let deep = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(1, 1, Rgb([32768u16; 3])));
+        assert_near(round_trip("deep.png", |path| deep.save(path).unwrap(), ImageTexture::data), gray(32768.0 / 65535.0), 1e-6);
+        // float images are linear already and may be brighter than white
+        let bright = Rgb([4.0, 0.5, 0.25]);
+        let hdr = |path: &std::path::Path| HdrEncoder::new(std::fs::File::create(path).unwrap()).encode(&[bright], 1, 1).unwrap();
+        assert_near(round_trip("bright.hdr", hdr, ImageTexture::new), Vec3::new(4.0, 0.5, 0.25), 1e-6);
+        let exr = DynamicImage::ImageRgb32F(Rgb32FImage::from_pixel(1, 1, bright));
+        assert_near(round_trip("bright.exr", |path| exr.save(path).unwrap(), ImageTexture::new), Vec3::new(4.0, 0.5, 0.25), 1e-6);
+    }
+}
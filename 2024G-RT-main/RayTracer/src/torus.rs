@@ -0,0 +1,193 @@
+use crate::AABB::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use num_traits::float::FloatConst;
+
+/// A ring torus around `axis`, intersected in the frame where the axis is `z` and the
+/// center is the origin.
+pub struct Torus<Mat: Material + Sync + Send> {
+    pub center: Vec3,
+    /// Distance from the center to the middle of the tube.
+    pub major: f64,
+    /// Radius of the tube.
+    pub minor: f64,
+    pub onb: Onb,
+    pub mat: Mat,
+    pub bbox: Aabb,
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> Torus<Mat> {
+    pub fn new(center: Vec3, axis: Vec3, major: f64, minor: f64, mat: Mat) -> Self {
+        let onb = Onb::new(axis);
+        let minor = minor.max(0.0);
+        let extent = onb.circle_extent(major) + minor;
+        Torus {
+            center,
+            major,
+            minor,
+            onb,
+            mat,
+            bbox: Aabb::set(center - extent, center + extent),
+        }
+    }
+
+    /// Nearest `t` inside `ray_t` where `ray` meets the torus.
+    fn root(&self, ray: &Ray, ray_t: Interval) -> Option<f64> {
+        let local = self.onb.local_ray(self.center, ray);
+        // solve along the unit direction, the quartic is badly scaled otherwise
+        let length = local.direction.length();
+        if length == 0.0 {
+            return None;
+        }
+        let d = local.direction / length;
+
+        // only search where the ray is inside the bounding sphere
+        let outer = self.major + self.minor;
+        let half_b = local.origin * d;
+        let discriminant = half_b * half_b - (local.origin.squared_length() - outer * outer);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let lo = (-half_b - discriminant.sqrt()).max(ray_t.min * length);
+        let hi = (-half_b + discriminant.sqrt()).min(ray_t.max * length);
+        if lo >= hi {
+            return None;
+        }
+
+        // (|p|² + R² - r²)² = 4R² (px² + py²), from a point moved up to the sphere
+        let o = local.origin + d * lo;
+        let r2 = self.major * self.major;
+        let b = 2.0 * (o * d);
+        let c = o.squared_length() + r2 - self.minor * self.minor;
+        let coeffs = [
+            1.0,
+            2.0 * b,
+            b * b + 2.0 * c - 4.0 * r2 * (d.x * d.x + d.y * d.y),
+            2.0 * b * c - 8.0 * r2 * (o.x * d.x + o.y * d.y),
+            c * c - 4.0 * r2 * (o.x * o.x + o.y * o.y),
+        ];
+        real_roots(&coeffs, 0.0, hi - lo)
+            .into_iter()
+            .map(|s| (lo + s) / length)
+            .find(|&t| ray_t.surrounds(t))
+    }
+}
+
+/// Ascending real roots in `[lo, hi]` of the polynomial with `coeffs` from the highest
+/// power down. The roots of the derivative split the range into monotonic pieces, each
+/// holding at most one root that bisection then finds.
+fn real_roots(coeffs: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let degree = coeffs.len() - 1;
+    let eval = |x: f64| coeffs.iter().fold(0.0, |acc, &c| acc * x + c);
+    if degree == 0 {
+        return Vec::new();
+    }
+    if degree == 1 {
+        let root = -coeffs[1] / coeffs[0];
+        return if (lo..=hi).contains(&root) { vec![root] } else { Vec::new() };
+    }
+    let derivative: Vec<f64> = coeffs[..degree].iter().enumerate().map(|(i, &c)| c * (degree - i) as f64).collect();
+    let mut bounds = vec![lo];
+    bounds.extend(real_roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots = Vec::new();
+    for piece in bounds.windows(2) {
+        let (mut a, mut b) = (piece[0], piece[1]);
+        let (mut fa, fb) = (eval(a), eval(b));
+        if fa == 0.0 {
+            roots.push(a);
+            continue;
+        }
+        if fa * fb > 0.0 {
+            continue;
+        }
+        for _ in 0..64 {
+            let mid = 0.5 * (a + b);
+            if mid <= a || mid >= b {
+                break;
+            }
+            let fm = eval(mid);
+            if (fm < 0.0) == (fa < 0.0) {
+                a = mid;
+                fa = fm;
+            } else {
+                b = mid;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    roots
+}
+
+impl<Mat: Material + Clone + Sync + Send + 'static> Hittable for Torus<Mat> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let t = match self.root(ray, ray_t) {
+            Some(t) => t,
+            None => return false,
+        };
+        let p = self.onb.local_ray(self.center, ray).at(t);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        // away from the nearest point on the center circle of the tube
+        let ring = Vec3::new(p.x, p.y, 0.0) * (self.major / rho);
+        let normal = (p - ring) / self.minor;
+        // u goes around the axis, v around the tube
+        let u = (f64::atan2(p.y, p.x) + f64::PI()) / (2.0 * f64::PI());
+        let v = (f64::atan2(p.z, rho - self.major) + f64::PI()) / (2.0 * f64::PI());
+        let dpdu = Vec3::new(-p.y, p.x, 0.0) * (2.0 * f64::PI());
+        let dpdv = Vec3::new(-p.z * p.x / rho, -p.z * p.y / rho, rho - self.major) * (2.0 * f64::PI());
+
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.u = u;
+        rec.v = v;
+        rec.dpdu = self.onb.to_world(dpdu);
+        rec.dpdv = self.onb.to_world(dpdv);
+        rec.tangent = Vec3::zero();
+        rec.color = Vec3::ones();
+        rec.material = &self.mat;
+        rec.set_face_normal(*ray, self.onb.to_world(normal));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.root(ray, ray_t).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn real_roots_of_quartic() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let roots = real_roots(&[1.0, -10.0, 35.0, -50.0, 24.0], 0.0, 10.0);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        assert_eq!(real_roots(&[1.0, -10.0, 35.0, -50.0, 24.0], 2.5, 3.5).len(), 1);
+    }
+
+    #[test]
+    fn ray_through_the_hole() {
+        let torus = Torus::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 2.0, 0.5, Lambertian::new(Vec3::ones()));
+        // in the plane of the ring, the tube is first met at x = -2.5
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        let t = torus.root(&ray, Interval::set(0.001, f64::INFINITY)).unwrap();
+        assert!((t - 1.25).abs() < 1e-9);
+        // along the axis it passes through the hole
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(torus.root(&ray, Interval::set(0.001, f64::INFINITY)).is_none());
+    }
+}
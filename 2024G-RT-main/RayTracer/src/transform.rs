@@ -0,0 +1,269 @@
+use std::sync::Arc;
+use crate::AABB::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::mat4::{Mat4, Quat};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Bounding box of `bbox` after it has been moved by `m`, found from its eight corners.
+pub(crate) fn transform_box(bbox: Aabb, m: &Mat4) -> Aabb {
+    let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for i in 0..2 {
+        for j in 0..2 {
+            for k in 0..2 {
+                let x = if i == 1 { bbox.x.max } else { bbox.x.min };
+                let y = if j == 1 { bbox.y.max } else { bbox.y.min };
+                let z = if k == 1 { bbox.z.max } else { bbox.z.min };
+                let tester = m.transform_point(Vec3::new(x, y, z));
+                for c in 0..3 {
+                    min[c] = min[c].min(tester[c]);
+                    max[c] = max[c].max(tester[c]);
+                }
+            }
+        }
+    }
+    Aabb::set(min, max)
+}
+
+/// An instance of `obj` placed in the world by an arbitrary affine matrix.
+///
+/// The object is shared through an `Arc`, so one BVH can be placed many times.
+pub struct Transform {
+    pub(crate) obj: Arc<dyn Hittable + Sync + Send>,
+    pub(crate) matrix: Mat4,
+    pub(crate) inverse: Mat4,
+    pub(crate) bbox: Aabb,
+}
+
+impl Transform {
+    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, matrix: Mat4) -> Self {
+        let inverse = matrix.inverse().expect("Transform matrix is not invertible");
+        let bbox = transform_box(obj.bounding_box().unwrap(), &matrix);
+        Self {
+            obj,
+            matrix,
+            inverse,
+            bbox,
+        }
+    }
+}
+
+/// `ray` moved into object space by `inverse`. The direction is not renormalized,
+/// so `t` means the same thing in both spaces.
+fn local_ray(ray: &Ray, inverse: &Mat4) -> Ray {
+    Ray::new_time(
+        inverse.transform_point(ray.origin),
+        inverse.transform_vector(ray.direction),
+        ray.time,
+    )
+}
+
+impl Hittable for Transform {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let local_ray = local_ray(ray, &self.inverse);
+        if !self.obj.hit(&local_ray, ray_t, rec) {
+            return false;
+        }
+        rec.p = self.matrix.transform_point(rec.p);
+        rec.normal = self.inverse.transform_normal(rec.normal).unit();
+        rec.geometric_normal = self.inverse.transform_normal(rec.geometric_normal).unit();
+        if !rec.tangent.near_zero() {
+            rec.tangent = self.matrix.transform_vector(rec.tangent).unit();
+        }
+        rec.dpdu = self.matrix.transform_vector(rec.dpdu);
+        rec.dpdv = self.matrix.transform_vector(rec.dpdv);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.obj.occluded(&local_ray(ray, &self.inverse), ray_t)
+    }
+}
+
+/// Placement of an animated object at one point in time.
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    /// `angle` is in degrees about `axis`.
+    pub fn new(time: f64, translation: Vec3, axis: Vec3, angle: f64, scale: Vec3) -> Self {
+        Self {
+            time,
+            translation,
+            rotation: Quat::from_axis_angle(axis, angle),
+            scale,
+        }
+    }
+}
+
+/// Like `Transform`, but the placement is keyframed over `Ray::time`.
+///
+/// Translation and scale are interpolated linearly, rotation with slerp. Times before
+/// the first or after the last keyframe hold that keyframe.
+pub struct AnimatedTransform {
+    pub(crate) obj: Arc<dyn Hittable + Sync + Send>,
+    pub(crate) keyframes: Vec<Keyframe>,
+    pub(crate) bbox: Aabb,
+}
+
+impl AnimatedTransform {
+    const BOUND_SAMPLES: usize = 128;
+
+    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "AnimatedTransform needs at least one keyframe");
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let obj_bbox = obj.bounding_box().unwrap();
+        let mut animated = Self {
+            obj,
+            keyframes,
+            bbox: obj_bbox,
+        };
+        animated.bbox = animated.motion_bounds(obj_bbox);
+        animated
+    }
+
+    /// Union of the object's bounds over the whole motion. Translation and scale are linear
+    /// between keyframes, so every keyframe is part of the union. Rotations are sampled
+    /// densely in between and the result is padded by the largest chord error between two
+    /// samples.
+    fn motion_bounds(&self, obj_bbox: Aabb) -> Aabb {
+        let start = self.keyframes[0].time;
+        let end = self.keyframes[self.keyframes.len() - 1].time;
+        let mut bbox = transform_box(obj_bbox, &self.interpolate(start).0);
+        if end <= start {
+            return bbox;
+        }
+        let samples = (1..=Self::BOUND_SAMPLES).map(|i| start + (end - start) * i as f64 / Self::BOUND_SAMPLES as f64);
+        for time in samples.chain(self.keyframes.iter().map(|k| k.time)) {
+            bbox = Aabb::surrounding_box(bbox, transform_box(obj_bbox, &self.interpolate(time).0));
+        }
+        let mut max_step: f64 = 0.0;
+        for pair in self.keyframes.windows(2) {
+            let cos_half = pair[0].rotation.w * pair[1].rotation.w + pair[0].rotation.v * pair[1].rotation.v;
+            let angle = 2.0 * cos_half.abs().min(1.0).acos();
+            max_step = max_step.max(angle * (end - start) / Self::BOUND_SAMPLES as f64 / (pair[1].time - pair[0].time).max(1e-12));
+        }
+        // the farthest a corner gets from the pivot it rotates about, which is at a keyframe
+        // because the scale is linear in between
+        let mut radius: f64 = 0.0;
+        for key in &self.keyframes {
+            for x in [obj_bbox.x.min, obj_bbox.x.max] {
+                for y in [obj_bbox.y.min, obj_bbox.y.max] {
+                    for z in [obj_bbox.z.min, obj_bbox.z.max] {
+                        radius = radius.max(Vec3::new(x * key.scale.x, y * key.scale.y, z * key.scale.z).length());
+                    }
+                }
+            }
+        }
+        let pad = radius * (1.0 - (max_step.min(std::f64::consts::PI) / 2.0).cos());
+        Aabb::new(bbox.x.expand(2.0 * pad), bbox.y.expand(2.0 * pad), bbox.z.expand(2.0 * pad))
+    }
+
+    /// Object-to-world matrix at `time` together with its inverse.
+    pub fn interpolate(&self, time: f64) -> (Mat4, Mat4) {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        let (translation, rotation, scale) = if time <= first.time {
+            (first.translation, first.rotation, first.scale)
+        } else if time >= last.time {
+            (last.translation, last.rotation, last.scale)
+        } else {
+            let next = self.keyframes.partition_point(|k| k.time <= time);
+            let a = &self.keyframes[next - 1];
+            let b = &self.keyframes[next];
+            let t = (time - a.time) / (b.time - a.time);
+            (
+                a.translation + (b.translation - a.translation) * t,
+                Quat::slerp(a.rotation, b.rotation, t),
+                a.scale + (b.scale - a.scale) * t,
+            )
+        };
+        let matrix = Mat4::translate(translation) * rotation.to_mat4() * Mat4::scale(scale);
+        let inverse = Mat4::scale(Vec3::new(1.0 / scale.x, 1.0 / scale.y, 1.0 / scale.z))
+            * rotation.conjugate().to_mat4()
+            * Mat4::translate(-translation);
+        (matrix, inverse)
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (matrix, inverse) = self.interpolate(ray.time);
+        let local_ray = local_ray(ray, &inverse);
+        if !self.obj.hit(&local_ray, ray_t, rec) {
+            return false;
+        }
+        rec.p = matrix.transform_point(rec.p);
+        rec.normal = inverse.transform_normal(rec.normal).unit();
+        rec.geometric_normal = inverse.transform_normal(rec.geometric_normal).unit();
+        if !rec.tangent.near_zero() {
+            rec.tangent = matrix.transform_vector(rec.tangent).unit();
+        }
+        rec.dpdu = matrix.transform_vector(rec.dpdu);
+        rec.dpdv = matrix.transform_vector(rec.dpdv);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        let (_, inverse) = self.interpolate(ray.time);
+        self.obj.occluded(&local_ray(ray, &inverse), ray_t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    // the bounds of the object at many times in between the keyframes stay inside
+    fn assert_bounds_hold(animated: &AnimatedTransform, obj_bbox: Aabb) {
+        let inside = |a: Interval, b: Interval| a.min >= b.min - 1e-9 && a.max <= b.max + 1e-9;
+        for i in 0..=10000 {
+            let placed = transform_box(obj_bbox, &animated.interpolate(i as f64 / 10000.0).0);
+            let bbox = animated.bbox;
+            assert!(inside(placed.x, bbox.x) && inside(placed.y, bbox.y) && inside(placed.z, bbox.z), "time {}", i as f64 / 10000.0);
+        }
+    }
+
+    #[test]
+    fn bounds_cover_keyframes_between_samples() {
+        let sphere: Arc<dyn Hittable + Sync + Send> = Arc::new(Sphere::new(Vec3::zero(), 1.0, Lambertian::new(Vec3::ones())));
+        let obj_bbox = sphere.bounding_box().unwrap();
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let animated = AnimatedTransform::new(sphere, vec![
+            Keyframe::new(0.0, Vec3::zero(), y, 0.0, Vec3::ones()),
+            Keyframe::new(0.3, Vec3::new(10.0, 0.0, 0.0), y, 0.0, Vec3::ones()),
+            Keyframe::new(1.0, Vec3::zero(), y, 0.0, Vec3::ones()),
+        ]);
+        assert!(animated.bbox.x.max >= 11.0);
+        assert_bounds_hold(&animated, obj_bbox);
+    }
+
+    #[test]
+    fn bounds_cover_objects_swinging_far_from_the_pivot() {
+        let sphere: Arc<dyn Hittable + Sync + Send> = Arc::new(Sphere::new(Vec3::new(50.0, 0.0, 0.0), 0.1, Lambertian::new(Vec3::ones())));
+        let obj_bbox = sphere.bounding_box().unwrap();
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let animated = AnimatedTransform::new(sphere, vec![
+            Keyframe::new(0.0, Vec3::zero(), y, 0.0, Vec3::ones()),
+            Keyframe::new(1.0, Vec3::zero(), y, 90.0, Vec3::new(2.0, 2.0, 2.0)),
+        ]);
+        assert_bounds_hold(&animated, obj_bbox);
+    }
+}
@@ -12,6 +12,8 @@ pub struct Triangle<Mat: Material + Sync + Send> {
     pub v2: Vec3,
     pub mat: Mat,
     pub normal: Vec3,
+    /// Vertex normals for smooth shading, `None` shades the face flat.
+    pub normals: Option<[Vec3; 3]>,
     pub bbox: Aabb,
 }
 
@@ -30,20 +32,29 @@ impl<Mat: Material + Sync + Send + Clone + 'static> Triangle<Mat> {
             v2,
             mat,
             normal,
+            normals: None,
             bbox,
         }
     }
-}
 
-impl<Mat: Material + Sync + Send + Clone + 'static> Hittable for Triangle<Mat> {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    /// Smooth shaded triangle, `n0`..`n2` are interpolated across the face.
+    pub fn with_normals(v0: Vec3, v1: Vec3, v2: Vec3, n0: Vec3, n1: Vec3, n2: Vec3, mat: Mat) -> Self {
+        Triangle {
+            normals: Some([n0.unit(), n1.unit(), n2.unit()]),
+            ..Triangle::new(v0, v1, v2, mat)
+        }
+    }
+
+    /// Möller-Trumbore: `t` and barycentric `u`, `v` of the hit inside `ray_t`, if the
+    /// material is opaque there.
+    fn intersect(&self, ray: &Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
         let edge1 = self.v1 - self.v0;
         let edge2 = self.v2 - self.v0;
         let h = Vec3::cross(ray.direction, edge2);
         let a =edge1* h;
 
         if a.abs() < 1e-8 {
-            return false;
+            return None;
         }
 
         let f = 1.0 / a;
@@ -51,32 +62,101 @@ impl<Mat: Material + Sync + Send + Clone + 'static> Hittable for Triangle<Mat> {
         let u = f * s* h;
 
         if u < 0.0 || u > 1.0 {
-            return false;
+            return None;
         }
 
         let q = Vec3::cross(s, edge1);
         let v = f * ray.direction* q;
 
         if v < 0.0 || u + v > 1.0 {
-            return false;
+            return None;
         }
 
         let t = f *edge2* q;
-        if !ray_t.contains(t) {
-            return false;
+        if !ray_t.contains(t) || !self.mat.is_opaque(u, v, &ray.at(t)) {
+            return None;
         }
+        Some((t, u, v))
+    }
+}
+
+impl<Mat: Material + Sync + Send + Clone + 'static> Hittable for Triangle<Mat> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (t, u, v) = match self.intersect(ray, ray_t) {
+            Some(hit) => hit,
+            None => return false,
+        };
 
         rec.t = t;
         rec.p = ray.at(t);
         rec.u = u;
         rec.v = v;
-        rec.material = Box::new(self.mat.clone());
+        // the barycentric coordinates are the texture coordinates
+        rec.dpdu = self.v1 - self.v0;
+        rec.dpdv = self.v2 - self.v0;
+        rec.tangent = Vec3::zero();
+        rec.color = Vec3::ones();
+        rec.material = &self.mat;
         rec.normal = if ray.direction*self.normal < 0.0 { self.normal } else { -self.normal };
         rec.front_face = ray.direction* rec.normal < 0.0;
+        rec.geometric_normal = rec.normal;
+        if let Some([n0, n1, n2]) = self.normals {
+            // shading normal, kept on the same side as the geometric one
+            let shading = (n0 * (1.0 - u - v) + n1 * u + n2 * v).unit();
+            rec.normal = if shading * rec.geometric_normal < 0.0 { -shading } else { shading };
+        }
         true
     }
 
     fn bounding_box(&self) -> Option<Aabb> {
         Some(self.bbox)
     }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.intersect(ray, ray_t).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::assert_near;
+    use crate::material::Lambertian;
+
+    // the unit right triangle in the xy plane with its vertex normals leaning outward
+    fn tilted() -> Triangle<Lambertian> {
+        let (n0, n1, n2) = (Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 1.0));
+        Triangle::with_normals(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), n0, n1, n2, Lambertian::new(Vec3::ones()))
+    }
+
+    #[test]
+    fn vertex_normals_are_interpolated_barycentrically() {
+        let triangle = tilted();
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(Vec3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangle.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+        let expected = Vec3::new(0.0, 0.0, 1.0) * 0.25 + Vec3::new(1.0, 0.0, 1.0).unit() * 0.25 + Vec3::new(0.0, 1.0, 1.0).unit() * 0.5;
+        assert_near(rec.normal, expected.unit(), 1e-12);
+        assert_near(rec.geometric_normal, Vec3::new(0.0, 0.0, 1.0), 1e-12);
+
+        // from behind both normals turn to face the ray
+        let ray = Ray::new(Vec3::new(0.25, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(triangle.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        assert_near(rec.normal, -expected.unit(), 1e-12);
+        assert_near(rec.geometric_normal, Vec3::new(0.0, 0.0, -1.0), 1e-12);
+    }
+
+    #[test]
+    fn directions_below_the_face_are_not_above_the_surface() {
+        let triangle = tilted();
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(Vec3::new(0.9, 0.05, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangle.hit(&ray, Interval::set(0.001, f64::INFINITY), &mut rec));
+        // above the leaning shading normal but into the face
+        let grazing = Vec3::new(1.0, 0.0, -0.1);
+        assert!(grazing * rec.normal > 0.0);
+        assert!(!rec.above_surface(grazing));
+        assert!(rec.above_surface(Vec3::new(1.0, 0.0, 0.1)));
+    }
 }
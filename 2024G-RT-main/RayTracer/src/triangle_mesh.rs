@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::AABB::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::mat4::Mat4;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::subdivision::{Displacement, Subdivision};
+use crate::vec3::Vec3;
+
+/// Fix-ups for models in odd units or orientations, applied while loading. The axes are
+/// swapped first, then the bounding box is recentered and scaled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImportOptions {
+    /// Move the center of the bounding box to the origin.
+    pub recenter: bool,
+    /// Scale uniformly so the longest side of the bounding box gets this length.
+    pub target_size: Option<f64>,
+    /// Turn a Z-up model, as CAD tools and scanners write them, into our Y-up.
+    pub z_up: bool,
+    /// Reverse the vertex order of every face, for models that are inside out.
+    pub flip_winding: bool,
+    /// Treat the faces of an OBJ file as a control cage and subdivide them, the normals
+    /// in the file are replaced by smooth ones. Other formats ignore it.
+    pub subdivision: Option<Subdivision>,
+}
+
+impl ImportOptions {
+    /// The transform these options make of a model with these vertex positions.
+    pub fn matrix(&self, positions: &[Vec3]) -> Mat4 {
+        let swap = if self.z_up {
+            Mat4::rotate(Vec3::new(1.0, 0.0, 0.0), -90.0)
+        } else {
+            Mat4::identity()
+        };
+        if positions.is_empty() {
+            return swap;
+        }
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = -min;
+        for &p in positions {
+            let p = swap.transform_point(p);
+            min = Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let center = (min + max) / 2.0;
+        let size = max - min;
+        let longest = size.x.max(size.y).max(size.z);
+        let scale = match self.target_size {
+            // about the center, so a model that is not recentered stays in place
+            Some(target) if longest > 0.0 => {
+                let s = target / longest;
+                Mat4::translate(center) * Mat4::scale(Vec3::new(s, s, s)) * Mat4::translate(-center)
+            }
+            _ => Mat4::identity(),
+        };
+        let recenter = if self.recenter { Mat4::translate(-center) } else { Mat4::identity() };
+        recenter * scale * swap
+    }
+}
+
+/// Vertex buffers shared by all triangles of a model. Every vertex has a position and,
+/// when the mesh has them, a normal and a texture coordinate at the same index.
+pub struct TriangleMesh<Mat: Material + Sync + Send> {
+    pub positions: Vec<Vec3>,
+    /// Empty for meshes without vertex normals, the faces are then shaded flat.
+    pub normals: Vec<Vec3>,
+    /// Empty for meshes without texture coordinates, the barycentrics are used instead.
+    pub uvs: Vec<(f64, f64)>,
+    /// Empty for meshes without vertex colors, read through `Texture::VertexColor`.
+    pub colors: Vec<Vec3>,
+    pub indices: Vec<[u32; 3]>,
+    pub mat: Mat,
+}
+
+impl<Mat: Material + Sync + Send + 'static> TriangleMesh<Mat> {
+    pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<(f64, f64)>, indices: Vec<[u32; 3]>, mat: Mat) -> Self {
+        assert!(normals.is_empty() || normals.len() == positions.len(), "one normal per vertex");
+        assert!(uvs.is_empty() || uvs.len() == positions.len(), "one uv per vertex");
+        Self {
+            positions,
+            normals,
+            uvs,
+            colors: Vec::new(),
+            indices,
+            mat,
+        }
+    }
+
+    pub fn with_colors(mut self, colors: Vec<Vec3>) -> Self {
+        assert!(colors.is_empty() || colors.len() == self.positions.len(), "one color per vertex");
+        self.colors = colors;
+        self
+    }
+
+    /// The mesh with `options` applied to its vertices, normals and faces.
+    pub fn transformed(mut self, options: &ImportOptions) -> Self {
+        let matrix = options.matrix(&self.positions);
+        for p in &mut self.positions {
+            *p = matrix.transform_point(*p);
+        }
+        match matrix.inverse() {
+            Some(inverse) => {
+                for n in &mut self.normals {
+                    *n = inverse.transform_normal(*n).unit();
+                }
+            }
+            None => self.normals.clear(),
+        }
+        if options.flip_winding {
+            for face in &mut self.indices {
+                face.swap(1, 2);
+            }
+        }
+        self
+    }
+
+    /// The mesh with area-weighted vertex normals. Vertices at the same position share
+    /// their normal, so a model split along its texture seams is still shaded smoothly.
+    pub fn with_smooth_normals(mut self) -> Self {
+        let (welded, count) = self.welded();
+        let normals = self.welded_normals(&welded, count);
+        self.normals = welded.iter().map(|&w| normals[w]).collect();
+        self
+    }
+
+    /// The mesh with every vertex moved along its smooth normal by `displacement`, then
+    /// shaded smoothly again. Vertices at the same position move together, whatever their
+    /// texture coordinates, so the seams stay closed.
+    pub fn displaced(mut self, displacement: &Displacement) -> Self {
+        let (welded, count) = self.welded();
+        let normals = self.welded_normals(&welded, count);
+        let mut offsets = vec![(0.0, 0usize); count];
+        for (i, &w) in welded.iter().enumerate() {
+            let uv = self.uvs.get(i).copied().unwrap_or((0.0, 0.0));
+            offsets[w].0 += displacement.offset(uv, self.positions[i]);
+            offsets[w].1 += 1;
+        }
+        for (p, &w) in self.positions.iter_mut().zip(&welded) {
+            *p += normals[w] * (offsets[w].0 / offsets[w].1 as f64);
+        }
+        self.with_smooth_normals()
+    }
+
+    /// An index per vertex that is the same for vertices at the same position, and how
+    /// many different positions there are.
+    fn welded(&self) -> (Vec<usize>, usize) {
+        let mut ids: HashMap<[u64; 3], usize> = HashMap::new();
+        let welded = self.positions.iter()
+            .map(|p| {
+                let next = ids.len();
+                *ids.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).or_insert(next)
+            })
+            .collect();
+        (welded, ids.len())
+    }
+
+    /// The sum of the normals of the faces around each welded vertex, weighted by their area.
+    fn welded_normals(&self, welded: &[usize], count: usize) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::zero(); count];
+        for face in &self.indices {
+            let [a, b, c] = face.map(|i| i as usize);
+            let p0 = self.positions[a];
+            let n = Vec3::cross(self.positions[b] - p0, self.positions[c] - p0);
+            for i in [a, b, c] {
+                normals[welded[i]] += n;
+            }
+        }
+        normals.into_iter()
+            .map(|n| if n.length() > 0.0 { n.unit() } else { Vec3::new(0.0, 1.0, 0.0) })
+            .collect()
+    }
+
+    /// One `MeshTriangle` per face, ready to go into a BVH.
+    pub fn triangles(self: &Arc<Self>) -> HittableList {
+        let mut list = HittableList::new();
+        for face in 0..self.indices.len() {
+            list.add(Arc::new(MeshTriangle {
+                mesh: Arc::clone(self),
+                face: face as u32,
+            }));
+        }
+        list
+    }
+}
+
+/// `dpdu` and `dpdv` of a triangle with edges `e1`, `e2` whose texture coordinates change by
+/// `duv1`, `duv2` along them. Zero when the uvs collapse the triangle to a line.
+fn uv_tangents(e1: Vec3, e2: Vec3, duv1: (f64, f64), duv2: (f64, f64)) -> (Vec3, Vec3) {
+    let det = duv1.0 * duv2.1 - duv1.1 * duv2.0;
+    if det.abs() < 1e-12 {
+        return (Vec3::zero(), Vec3::zero());
+    }
+    let dpdu = (e1 * duv2.1 - e2 * duv1.1) / det;
+    let dpdv = (e2 * duv1.0 - e1 * duv2.0) / det;
+    (dpdu, dpdv)
+}
+
+/// A face of a `TriangleMesh`, stored as an index so the vertices are never copied.
+pub struct MeshTriangle<Mat: Material + Sync + Send> {
+    pub mesh: Arc<TriangleMesh<Mat>>,
+    pub face: u32,
+}
+
+impl<Mat: Material + Sync + Send> MeshTriangle<Mat> {
+    fn vertices(&self) -> [usize; 3] {
+        let [a, b, c] = self.mesh.indices[self.face as usize];
+        [a as usize, b as usize, c as usize]
+    }
+
+    /// Texture coordinates at barycentric `b1`, `b2`, the barycentrics themselves when the
+    /// mesh has none.
+    fn uv(&self, b1: f64, b2: f64) -> (f64, f64) {
+        let mesh = &self.mesh;
+        if mesh.uvs.is_empty() {
+            return (b1, b2);
+        }
+        let [i0, i1, i2] = self.vertices();
+        let (uv0, uv1, uv2) = (mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]);
+        let b0 = 1.0 - b1 - b2;
+        (b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0, b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1)
+    }
+
+    /// Möller-Trumbore: `t` and barycentric `b1`, `b2` of the hit inside `ray_t`, if the
+    /// material is opaque there.
+    fn intersect(&self, ray: &Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
+        let [i0, i1, i2] = self.vertices();
+        let p0 = self.mesh.positions[i0];
+        let edge1 = self.mesh.positions[i1] - p0;
+        let edge2 = self.mesh.positions[i2] - p0;
+        let h = Vec3::cross(ray.direction, edge2);
+        let a = edge1 * h;
+        if a.abs() < 1e-8 {
+            return None;
+        }
+        let f = 1.0 / a;
+        let s = ray.origin - p0;
+        let b1 = f * (s * h);
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let q = Vec3::cross(s, edge1);
+        let b2 = f * (ray.direction * q);
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = f * (edge2 * q);
+        if !ray_t.contains(t) {
+            return None;
+        }
+        let (u, v) = self.uv(b1, b2);
+        if !self.mesh.mat.is_opaque(u, v, &ray.at(t)) {
+            return None;
+        }
+        Some((t, b1, b2))
+    }
+}
+
+impl<Mat: Material + Sync + Send> Hittable for MeshTriangle<Mat> {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (t, b1, b2) = match self.intersect(ray, ray_t) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let mesh = &self.mesh;
+        let [i0, i1, i2] = self.vertices();
+        let b0 = 1.0 - b1 - b2;
+        let p0 = mesh.positions[i0];
+        let (e1, e2) = (mesh.positions[i1] - p0, mesh.positions[i2] - p0);
+        let geometric = Vec3::cross(e1, e2).unit();
+
+        rec.t = t;
+        rec.p = ray.at(t);
+        (rec.u, rec.v) = self.uv(b1, b2);
+        (rec.dpdu, rec.dpdv) = if mesh.uvs.is_empty() {
+            (e1, e2)
+        } else {
+            let (uv0, uv1, uv2) = (mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]);
+            uv_tangents(e1, e2, (uv1.0 - uv0.0, uv1.1 - uv0.1), (uv2.0 - uv0.0, uv2.1 - uv0.1))
+        };
+        rec.tangent = Vec3::zero();
+        rec.color = if mesh.colors.is_empty() {
+            Vec3::new(1.0, 1.0, 1.0)
+        } else {
+            mesh.colors[i0] * b0 + mesh.colors[i1] * b1 + mesh.colors[i2] * b2
+        };
+        rec.material = &mesh.mat;
+        rec.set_face_normal(*ray, geometric);
+        if !mesh.normals.is_empty() {
+            // interpolated normal, turned to the same side as the geometric one
+            let shading = (mesh.normals[i0] * b0 + mesh.normals[i1] * b1 + mesh.normals[i2] * b2).unit();
+            rec.normal = if shading * rec.normal < 0.0 { -shading } else { shading };
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [i0, i1, i2] = self.vertices();
+        let positions = &self.mesh.positions;
+        Some(Aabb::surrounding_box(
+            Aabb::set(positions[i0], positions[i1]),
+            Aabb::set(positions[i0], positions[i2]),
+        ))
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.intersect(ray, ray_t).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::assert_near;
+
+    #[test]
+    fn z_up_becomes_y_up() {
+        let options = ImportOptions { z_up: true, ..ImportOptions::default() };
+        let matrix = options.matrix(&[Vec3::zero(), Vec3::new(0.0, 0.0, 1.0)]);
+        assert_near(matrix.transform_point(Vec3::new(0.0, 0.0, 1.0)), Vec3::new(0.0, 1.0, 0.0), 1e-9);
+        assert_near(matrix.transform_point(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(1.0, 0.0, 0.0), 1e-9);
+    }
+
+    #[test]
+    fn recenter_and_scale() {
+        let positions = [Vec3::new(10.0, 0.0, 0.0), Vec3::new(14.0, 2.0, 1.0)];
+        let options = ImportOptions { recenter: true, target_size: Some(2.0), ..ImportOptions::default() };
+        let matrix = options.matrix(&positions);
+        assert_near(matrix.transform_point(positions[0]), Vec3::new(-1.0, -0.5, -0.25), 1e-9);
+        assert_near(matrix.transform_point(positions[1]), Vec3::new(1.0, 0.5, 0.25), 1e-9);
+    }
+
+    #[test]
+    fn scale_keeps_the_center_in_place() {
+        let positions = [Vec3::new(10.0, 0.0, 0.0), Vec3::new(14.0, 2.0, 1.0)];
+        let options = ImportOptions { target_size: Some(8.0), ..ImportOptions::default() };
+        let matrix = options.matrix(&positions);
+        assert_near(matrix.transform_point(Vec3::new(12.0, 1.0, 0.5)), Vec3::new(12.0, 1.0, 0.5), 1e-9);
+        assert_near(matrix.transform_point(positions[1]), Vec3::new(16.0, 3.0, 1.5), 1e-9);
+    }
+
+    #[test]
+    fn uv_tangents_rebuild_the_edges() {
+        let (e1, e2) = (Vec3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 3.0, 1.0));
+        let (duv1, duv2) = ((0.5, 0.25), (-0.25, 1.0));
+        let (dpdu, dpdv) = uv_tangents(e1, e2, duv1, duv2);
+        assert_near(dpdu * duv1.0 + dpdv * duv1.1, e1, 1e-9);
+        assert_near(dpdu * duv2.0 + dpdv * duv2.1, e2, 1e-9);
+        assert_eq!(uv_tangents(e1, e2, (1.0, 1.0), (2.0, 2.0)), (Vec3::zero(), Vec3::zero()));
+    }
+
+    // Two triangles split along their shared edge, with texture coordinates on either
+    // side of a height map that is black on the left and white on the right.
+    #[test]
+    fn displacement_keeps_seams_closed() {
+        use crate::material::Lambertian;
+        use crate::texture::{ColorSpace, ImageTexture, Texture};
+        use image::{DynamicImage, Rgb, RgbImage};
+
+        let positions = vec![
+            Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let uvs = vec![(0.25, 0.5); 3].into_iter().chain(vec![(0.75, 0.5); 3]).collect();
+        let image = RgbImage::from_fn(2, 1, |x, _| if x == 0 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) });
+        let height = Texture::ImageTex(ImageTexture::from_image(DynamicImage::ImageRgb8(image), ColorSpace::Linear));
+        let mesh = TriangleMesh::new(positions, Vec::new(), uvs, vec![[0, 1, 2], [3, 4, 5]], Lambertian::new(Vec3::ones()))
+            .displaced(&Displacement::new(height, 1.0));
+
+        assert_near(mesh.positions[0], Vec3::new(0.0, 0.0, 0.0), 1e-9);
+        assert_near(mesh.positions[4], Vec3::new(1.0, 1.0, 1.0), 1e-9);
+        // the shared corners move by the average height of both sides
+        assert_near(mesh.positions[1], Vec3::new(1.0, 0.0, 0.5), 1e-9);
+        assert_near(mesh.positions[3], mesh.positions[1], 1e-9);
+        assert_near(mesh.positions[5], mesh.positions[2], 1e-9);
+        assert_near(mesh.normals[3], mesh.normals[1], 1e-9);
+    }
+}
@@ -295,6 +295,14 @@ impl IndexMut<usize> for Vec3{
         }
     }
 }
+
+/// Asserts that two vectors are less than `eps` apart, for the tests of every module.
+#[cfg(test)]
+#[track_caller]
+pub(crate) fn assert_near(a: Vec3, b: Vec3, eps: f64) {
+    assert!((a - b).length() < eps, "{:?} != {:?}", a, b);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;